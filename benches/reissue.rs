@@ -1,223 +1,307 @@
 #![allow(clippy::from_iter_instead_of_collect)]
 
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::{BTreeMap, HashMap};
 use std::iter::FromIterator;
 
-use sn_dbc::{
-    bls_dkg_id, Amount, AmountSecrets, Dbc, DbcContent, Error, Mint, ReissueRequest,
-    SimpleKeyManager, SimpleSigner, SimpleSpendBook,
-};
+use sn_dbc::{bls_dkg_id, Amount, Dbc, DbcContent, MintCluster, RangeProofGens, ReissueRequest};
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
-fn decrypt_amount_secrets(
-    owner: &bls_dkg::outcome::Outcome,
-    dbcc: &DbcContent,
-) -> Result<AmountSecrets, Error> {
-    let shares = BTreeMap::from_iter([(0, owner.secret_key_share.clone())]);
+fn genesis(amount: Amount) -> (MintCluster, Dbc) {
+    let mut mint = MintCluster::random(1, 0);
+    let genesis_dbc = mint.issue_genesis_dbc(amount).unwrap();
 
-    dbcc.amount_secrets_by_secret_key_shares(&owner.public_key_set, &shares)
+    (mint, genesis_dbc)
 }
 
-fn genesis(
-    amount: Amount,
-) -> (
-    Mint<SimpleKeyManager, SimpleSpendBook>,
-    bls_dkg::outcome::Outcome,
-    Dbc,
-) {
-    let genesis_owner = bls_dkg_id();
-
-    let key_manager = SimpleKeyManager::new(
-        SimpleSigner::new(
-            genesis_owner.public_key_set.clone(),
-            (0, genesis_owner.secret_key_share.clone()),
-        ),
-        genesis_owner.public_key_set.public_key(),
-    );
-    let mut genesis_node = Mint::new(key_manager, SimpleSpendBook::new());
-
-    let (content, transaction, (mint_key_set, mint_sig_share)) =
-        genesis_node.issue_genesis_dbc(amount).unwrap();
+fn bench_reissue_1_to_100(c: &mut Criterion) {
+    bench_split(c, 100, false);
+}
 
-    let mint_sig = mint_key_set
-        .combine_signatures(vec![mint_sig_share.threshold_crypto()])
-        .unwrap();
+fn bench_reissue_1_to_100_aggregated(c: &mut Criterion) {
+    bench_split(c, 100, true);
+}
 
-    let transaction_sigs = BTreeMap::from_iter(
-        transaction
-            .inputs
-            .iter()
-            .map(|in_hash| (*in_hash, (mint_key_set.public_key(), mint_sig.clone()))),
-    );
+fn bench_split(c: &mut Criterion, n_outputs: u32, aggregate_range_proofs: bool) {
+    let (mut mint, reissue) = split(n_outputs, aggregate_range_proofs);
 
-    let genesis_dbc = Dbc {
-        content,
-        transaction,
-        transaction_sigs,
+    let bench_name = match aggregate_range_proofs {
+        true => format!("reissue split 1 to {} (aggregated range proof)", n_outputs),
+        false => format!("reissue split 1 to {}", n_outputs),
     };
 
-    (genesis_node, genesis_owner, genesis_dbc)
+    let genesis = mint.node_mut(0).unwrap();
+    let spendbook = genesis.snapshot_spendbook();
+    c.bench_function(&bench_name, |b| {
+        b.iter(|| {
+            genesis.reset_spendbook(spendbook.clone());
+            genesis.reissue(black_box(reissue.clone())).unwrap();
+        })
+    });
 }
 
-fn bench_reissue_1_to_100(c: &mut Criterion) {
-    let n_outputs: u32 = 100;
-    let (mut genesis, genesis_owner, genesis_dbc) = genesis(n_outputs as u64);
-    let genesis_secrets = decrypt_amount_secrets(&genesis_owner, &genesis_dbc.content).unwrap();
+// A mint, and a request splitting its genesis DBC into `n_outputs`.
+fn split(n_outputs: u32, aggregate_range_proofs: bool) -> (MintCluster, ReissueRequest) {
+    let (mint, genesis_dbc) = genesis(n_outputs as u64);
+    let genesis_secrets = mint.decrypt_amount_secrets(&genesis_dbc.content).unwrap();
 
     let output_owner = bls_dkg_id();
     let output_owner_pk = output_owner.public_key_set.public_key();
 
     let (reissue_tx, _) = sn_dbc::TransactionBuilder::default()
         .add_input(genesis_dbc.clone(), genesis_secrets)
-        .add_outputs((0..n_outputs).into_iter().map(|_| sn_dbc::Output {
-            amount: 1,
-            owner: output_owner_pk,
-        }))
+        .add_outputs((0..n_outputs).map(|_| sn_dbc::Output::new(1, output_owner_pk)))
+        .aggregate_range_proofs(aggregate_range_proofs)
         .build()
         .unwrap();
 
-    let sig_share = genesis_owner
-        .secret_key_share
-        .sign(&reissue_tx.blinded().hash());
-
-    let sig = genesis_owner
-        .public_key_set
-        .combine_signatures(vec![(0, &sig_share)])
-        .unwrap();
-
+    let ownership_proof = mint.sign_as_owner(&reissue_tx.blinded().hash());
     let reissue = ReissueRequest {
         transaction: reissue_tx,
-        input_ownership_proofs: HashMap::from_iter([(
-            genesis_dbc.name(),
-            (genesis_owner.public_key_set.public_key(), sig),
-        )]),
+        input_ownership_proofs: HashMap::from_iter([(genesis_dbc.name(), ownership_proof)]),
+        input_spending_proofs: Default::default(),
     };
+    (mint, reissue)
+}
 
+fn bench_reissue_100_to_1(c: &mut Criterion) {
+    let n_inputs = 100;
+    let (mut mint, merge_reissue) = merge(n_inputs);
+
+    let genesis = mint.node_mut(0).unwrap();
     let spendbook = genesis.snapshot_spendbook();
-    c.bench_function(&format!("reissue split 1 to {}", n_outputs), |b| {
+    c.bench_function(&format!("reissue merge {} to 1", n_inputs), |b| {
         b.iter(|| {
             genesis.reset_spendbook(spendbook.clone());
-            genesis
-                .reissue(
-                    black_box(reissue.clone()),
-                    black_box(BTreeSet::from_iter([genesis_dbc.name()])),
-                )
-                .unwrap();
+            genesis.reissue(black_box(merge_reissue.clone())).unwrap();
         })
     });
 }
 
-fn bench_reissue_100_to_1(c: &mut Criterion) {
-    let n_outputs: u32 = 100;
-    let (mut genesis, genesis_owner, genesis_dbc) = genesis(n_outputs as u64);
-    let genesis_amount_secrets =
-        sn_dbc::DbcHelper::decrypt_amount_secrets(&genesis_owner, &genesis_dbc.content).unwrap();
+// A mint, and a request merging `n_outputs` DBCs of its into one.
+fn merge(n_outputs: u32) -> (MintCluster, ReissueRequest) {
+    let (mut mint, genesis_dbc) = genesis(n_outputs as u64);
+    let genesis_amount_secrets = mint.decrypt_amount_secrets(&genesis_dbc.content).unwrap();
 
-    let owners = Vec::from_iter((0..n_outputs).into_iter().map(|_| bls_dkg_id()));
+    let owners = Vec::from_iter((0..n_outputs).map(|_| bls_dkg_id()));
 
-    let (reissue_tx, dbc_owners) = sn_dbc::TransactionBuilder::default()
+    let (reissue_tx, receipt) = sn_dbc::TransactionBuilder::default()
         .add_input(genesis_dbc.clone(), genesis_amount_secrets)
-        .add_outputs(owners.iter().map(|owner| sn_dbc::Output {
-            amount: 1,
-            owner: owner.public_key_set.public_key(),
-        }))
+        .add_outputs(
+            owners
+                .iter()
+                .map(|owner| sn_dbc::Output::new(1, owner.public_key_set.public_key())),
+        )
         .build()
         .unwrap();
 
-    let dbc_owners = BTreeMap::from_iter(dbc_owners.into_iter().map(|(dbc_hash, owner_pk)| {
-        let owner = owners
-            .iter()
-            .find(|o| o.public_key_set.public_key() == owner_pk)
-            .unwrap()
-            .clone();
-        (dbc_hash, owner)
-    }));
-
-    let sig_share = genesis_owner
-        .secret_key_share
-        .sign(&reissue_tx.blinded().hash());
-
-    let sig = genesis_owner
-        .public_key_set
-        .combine_signatures(vec![(0, &sig_share)])
-        .unwrap();
-
-    let input_ownership_proofs = HashMap::from_iter([(
-        genesis_dbc.name(),
-        (genesis_owner.public_key_set.public_key(), sig),
-    )]);
-
+    let dbc_owners =
+        BTreeMap::from_iter(receipt.output_owners.iter().map(|(dbc_hash, owner_pk)| {
+            let owner = owners
+                .iter()
+                .find(|o| &o.public_key_set.public_key() == owner_pk)
+                .unwrap()
+                .clone();
+            (*dbc_hash, owner)
+        }));
+
+    let ownership_proof = mint.sign_as_owner(&reissue_tx.blinded().hash());
     let reissue = ReissueRequest {
         transaction: reissue_tx,
-        input_ownership_proofs,
+        input_ownership_proofs: HashMap::from_iter([(genesis_dbc.name(), ownership_proof)]),
+        input_spending_proofs: Default::default(),
     };
-
-    let reissue_share = genesis
-        .reissue(reissue.clone(), BTreeSet::from_iter([genesis_dbc.name()]))
-        .unwrap();
-
-    let (mint_key_set, mint_sig_share) = reissue_share
-        .mint_node_signatures
-        .values()
-        .cloned()
-        .next()
-        .unwrap();
-
-    let mint_sig = genesis_owner
-        .public_key_set
-        .combine_signatures(vec![mint_sig_share.threshold_crypto()])
-        .unwrap();
-
-    let dbcs = Vec::from_iter(reissue.transaction.outputs.into_iter().map(|content| Dbc {
-        content,
-        transaction: reissue_share.dbc_transaction.clone(),
-        transaction_sigs: BTreeMap::from_iter([(
-            genesis_dbc.name(),
-            (mint_key_set.public_key(), mint_sig.clone()),
-        )]),
-    }));
+    let dbcs = mint.reissue(reissue).unwrap();
 
     let (merge_tx, _) = sn_dbc::TransactionBuilder::default()
         .add_inputs(dbcs.iter().cloned().map(|dbc| {
-            let owner = &dbc_owners[&dbc.name()];
-            let amount_secrets =
-                sn_dbc::DbcHelper::decrypt_amount_secrets(owner, &dbc.content).unwrap();
+            let amount_secrets = receipt.output_amount_secrets[&dbc.name()];
             (dbc, amount_secrets)
         }))
-        .add_output(sn_dbc::Output {
-            amount: n_outputs as Amount,
-            owner: bls_dkg_id().public_key_set.public_key(),
-        })
+        .add_output(sn_dbc::Output::new(
+            n_outputs as Amount,
+            bls_dkg_id().public_key_set.public_key(),
+        ))
         .build()
         .unwrap();
 
-    let input_ownership_proofs = HashMap::from_iter(dbcs.iter().map(|dbc| {
-        let owner = &dbc_owners[&dbc.name()];
-        let sig_share = owner.secret_key_share.sign(merge_tx.blinded().hash());
-        let sig = owner
-            .public_key_set
-            .combine_signatures(vec![(0, &sig_share)])
-            .unwrap();
-        (dbc.name(), (owner.public_key_set.public_key(), sig))
-    }));
-
-    let merge_reissue = ReissueRequest {
-        transaction: merge_tx,
-        input_ownership_proofs,
+    let merge_reissue = sn_dbc::ReissueRequestBuilder::new(merge_tx)
+        .add_signers(dbcs.iter().map(|dbc| {
+            let owner = &dbc_owners[&dbc.name()];
+            let signer = sn_dbc::OwnerSigner::SecretKeyShares(
+                owner.public_key_set.clone(),
+                BTreeMap::from_iter([(owner.index, owner.secret_key_share.clone())]),
+            );
+            (dbc.name(), signer)
+        }))
+        .build()
+        .unwrap();
+    (mint, merge_reissue)
+}
+
+// Verify the range proofs of a split into 100 outputs, and of a merge of 100 inputs,
+// with generators built for every proof, as before they were shared, and with the
+// shared generators.
+fn bench_range_proof_gens(c: &mut Criterion) {
+    let (_, reissue) = split(100, false);
+    let outputs = Vec::from_iter(reissue.transaction.outputs.iter());
+    bench_contents_gens(c, "split 1 to 100", &outputs);
+
+    let (_, reissue) = merge(100);
+    let contents = Vec::from_iter(
+        reissue
+            .transaction
+            .inputs
+            .iter()
+            .map(|input| &input.content)
+            .chain(reissue.transaction.outputs.iter()),
+    );
+    bench_contents_gens(c, "merge 100 to 1", &contents);
+
+    let (_, reissue) = split(100, true);
+    let proof = reissue.transaction.aggregated_range_proof.unwrap();
+    let n_parties = proof.commitments.len();
+    c.bench_function(
+        "verify split 1 to 100 (aggregated range proof) with fresh gens",
+        |b| {
+            b.iter(|| {
+                let gens = RangeProofGens::new(n_parties);
+                black_box(&proof).verify_with_gens(&gens).unwrap();
+            })
+        },
+    );
+    let gens = RangeProofGens::shared(n_parties);
+    c.bench_function(
+        "verify split 1 to 100 (aggregated range proof) with shared gens",
+        |b| {
+            b.iter(|| {
+                black_box(&proof).verify_with_gens(&gens).unwrap();
+            })
+        },
+    );
+}
+
+fn bench_contents_gens(c: &mut Criterion, name: &str, contents: &[&DbcContent]) {
+    c.bench_function(&format!("verify {} with fresh gens", name), |b| {
+        b.iter(|| {
+            for content in black_box(contents) {
+                let gens = RangeProofGens::new(1);
+                content.verify_range_proof_with_gens(&gens).unwrap();
+            }
+        })
+    });
+    let gens = RangeProofGens::shared(1);
+    c.bench_function(&format!("verify {} with shared gens", name), |b| {
+        b.iter(|| {
+            for content in black_box(contents) {
+                content.verify_range_proof_with_gens(&gens).unwrap();
+            }
+        })
+    });
+}
+
+// Validation and reissue of many inputs.  Run with and without --features parallel
+// to compare the two builds, the bench names say which one ran.
+fn bench_many_inputs(c: &mut Criterion) {
+    let build = match cfg!(feature = "parallel") {
+        true => "parallel",
+        false => "serial",
     };
-    let inputs = merge_reissue.transaction.blinded().inputs;
+    for n_inputs in [100, 250] {
+        let (mut mint, reissue) = merge(n_inputs);
+        let genesis = mint.node_mut(0).unwrap();
+
+        c.bench_function(
+            &format!("validate merge {} to 1 ({} build)", n_inputs, build),
+            |b| {
+                b.iter(|| {
+                    black_box(&reissue.transaction)
+                        .validate(genesis.key_manager())
+                        .unwrap();
+                })
+            },
+        );
+
+        let spendbook = genesis.snapshot_spendbook();
+        c.bench_function(
+            &format!("reissue merge {} to 1 ({} build)", n_inputs, build),
+            |b| {
+                b.iter(|| {
+                    genesis.reset_spendbook(spendbook.clone());
+                    genesis.reissue(black_box(reissue.clone())).unwrap();
+                })
+            },
+        );
+    }
+}
 
-    let spendbook = genesis.snapshot_spendbook();
-    c.bench_function(&format!("reissue merge {} to 1", n_outputs), |b| {
+// Reissue validation run on a pool of one thread, ie. serially, and on a pool of
+// every available thread.
+#[cfg(feature = "parallel")]
+fn bench_parallel_validation(c: &mut Criterion) {
+    let mut n_threads = vec![1, rayon::current_num_threads()];
+    n_threads.dedup();
+    let requests = [
+        ("split 1 to 100", split(100, false)),
+        ("merge 100 to 1", merge(100)),
+    ];
+    for (name, (mut mint, reissue)) in requests {
+        let genesis = mint.node_mut(0).unwrap();
+        let spendbook = genesis.snapshot_spendbook();
+        for &threads in &n_threads {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            c.bench_function(&format!("reissue {} on {} threads", name, threads), |b| {
+                b.iter(|| {
+                    genesis.reset_spendbook(spendbook.clone());
+                    pool.install(|| genesis.reissue(black_box(reissue.clone())).unwrap());
+                })
+            });
+        }
+    }
+}
+
+fn bench_verify_range_proof(c: &mut Criterion) {
+    let owner = bls_dkg_id();
+    let content = DbcContent::new(
+        Default::default(),
+        100,
+        owner.public_key_set.public_key(),
+        DbcContent::random_blinding_factor(),
+    )
+    .unwrap();
+
+    c.bench_function("verify range proof with fresh gens", |b| {
         b.iter(|| {
-            genesis.reset_spendbook(spendbook.clone());
-            genesis
-                .reissue(black_box(merge_reissue.clone()), black_box(inputs.clone()))
+            let gens = RangeProofGens::new(1);
+            black_box(&content)
+                .verify_range_proof_with_gens(&gens)
                 .unwrap();
         })
     });
+
+    c.bench_function("verify range proof with shared gens", |b| {
+        b.iter(|| {
+            black_box(&content).verify_range_proof().unwrap();
+        })
+    });
 }
 
-criterion_group!(reissue, bench_reissue_1_to_100, bench_reissue_100_to_1);
+criterion_group!(
+    reissue,
+    bench_reissue_1_to_100,
+    bench_reissue_1_to_100_aggregated,
+    bench_reissue_100_to_1,
+    bench_range_proof_gens,
+    bench_many_inputs,
+    bench_verify_range_proof
+);
+#[cfg(feature = "parallel")]
+criterion_group!(parallel, bench_parallel_validation);
+
+#[cfg(feature = "parallel")]
+criterion_main!(reissue, parallel);
+#[cfg(not(feature = "parallel"))]
 criterion_main!(reissue);
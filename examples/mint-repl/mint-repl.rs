@@ -9,23 +9,20 @@
 
 //! Safe Network DBC Mint CLI playground.
 
-use anyhow::{anyhow, Error, Result};
+use anyhow::{anyhow, Result};
 use blsttc::poly::Poly;
 use blsttc::serde_impl::SerdeSecret;
-use blsttc::{
-    PublicKey, PublicKeySet, SecretKey, SecretKeySet, SecretKeyShare, Signature, SignatureShare,
-};
+use blsttc::{PublicKey, PublicKeySet, SecretKey, SecretKeySet, SecretKeyShare};
 use rustyline::config::Configurer;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 use serde::{Deserialize, Serialize};
 use sn_dbc::{
-    Amount, Dbc, DbcBuilder, DbcContent, DbcTransaction, Hash, Mint, NodeSignature, Output,
-    ReissueRequest, ReissueTransaction, SimpleKeyManager as KeyManager, SimpleSigner as Signer,
+    Amount, Dbc, Hash, Mint, MintCluster, Output, OwnerSigner, PartiallySignedReissue,
+    ReissueRequest, ReissueRequestBuilder, ReissueTransaction, SimpleKeyManager as KeyManager,
     SimpleSpendBook as SpendBook, TransactionBuilder,
 };
-use std::collections::{BTreeMap, BTreeSet, HashMap};
-use std::iter::FromIterator;
+use std::collections::{BTreeMap, HashMap};
 
 #[cfg(unix)]
 use std::os::unix::{io::AsRawFd, prelude::RawFd};
@@ -36,7 +33,7 @@ use termios::{tcsetattr, Termios, ICANON, TCSADRAIN};
 /// Holds information about the Mint, which may be comprised
 /// of 1 or more nodes.
 struct MintInfo {
-    mintnodes: Vec<Mint<KeyManager, SpendBook>>,
+    mintnodes: MintCluster<SpendBook>,
     genesis: DbcUnblinded,
     secret_key_set: SecretKeySet,
     poly: Poly,
@@ -46,7 +43,7 @@ impl MintInfo {
     // returns the first mint node.
     fn mintnode(&self) -> Result<&Mint<KeyManager, SpendBook>> {
         self.mintnodes
-            .get(0)
+            .node(0)
             .ok_or_else(|| anyhow!("Mint not yet created"))
     }
 
@@ -81,10 +78,6 @@ struct ReissueRequestUnblinded {
     outputs_owners: HashMap<Hash, PublicKeySet>,
 }
 
-/// This type is just for serializing HashMap<Hash, <HashMap<usize, SignatureShare>>
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-struct SignatureSharesMap(HashMap<Hash, HashMap<usize, SignatureShare>>);
-
 /// program entry point and interactive command handler.
 fn main() -> Result<()> {
     // Disable TTY ICANON.  So readline() can read more than 4096 bytes.
@@ -103,7 +96,7 @@ fn main() -> Result<()> {
     loop {
         match rl.readline(">> ") {
             Ok(line) => {
-                let mut args = line.trim().split_whitespace();
+                let mut args = line.split_whitespace();
                 let cmd = if let Some(cmd) = args.next() {
                     cmd
                 } else {
@@ -166,7 +159,7 @@ fn newmint() -> Result<MintInfo> {
             let answer = readline_prompt(
                 "\nA mint with supply == 0 can only reissue Dbc worth 0. Change? [y/n]: ",
             )?;
-            if answer.to_ascii_lowercase() != "n" {
+            if !answer.eq_ignore_ascii_case("n") {
                 continue;
             }
             // note: we allow amount to be 0.  Let sn_dbc validation deal with it (or not).
@@ -210,47 +203,13 @@ fn mk_new_random_mint(threshold: usize, amount: Amount) -> Result<MintInfo> {
 
 /// creates a new mint from an existing SecretKeySet that was seeded by poly.
 fn mk_new_mint(secret_key_set: SecretKeySet, poly: Poly, amount: Amount) -> Result<MintInfo> {
-    let genesis_pubkey = secret_key_set.public_keys().public_key();
-    let mut mints: Vec<Mint<KeyManager, SpendBook>> = Default::default();
-
-    // Generate each Mint node, and corresponding NodeSignature. (Index + SignatureShare)
-    let mut genesis_set: Vec<(DbcContent, DbcTransaction, (PublicKeySet, NodeSignature))> =
-        Default::default();
-    for i in 0..secret_key_set.threshold() as u64 + 1 {
-        let key_manager = KeyManager::new(
-            Signer::new(
-                secret_key_set.public_keys().clone(),
-                (i, secret_key_set.secret_key_share(i).clone()),
-            ),
-            genesis_pubkey,
-        );
-        let mut mint = Mint::new(key_manager, SpendBook::new());
-        genesis_set.push(mint.issue_genesis_dbc(amount)?);
-        mints.push(mint);
-    }
-
-    // Make a list of (Index, SignatureShare) for combining sigs.
-    let node_sigs: Vec<(u64, &SignatureShare)> = genesis_set
-        .iter()
-        .map(|e| e.2 .1.threshold_crypto())
-        .collect();
-
-    // Todo: in a true multi-node mint, each node would call issue_genesis_dbc(), then the aggregated
-    // signatures would be combined here, so this mk_new_mint fn would to be broken apart.
-    let genesis_sig = secret_key_set
-        .public_keys()
-        .combine_signatures(node_sigs)
-        .map_err(|e| anyhow!(e))?;
-
-    // Create the Genesis Dbc
-    let genesis_dbc = Dbc {
-        content: genesis_set[0].0.clone(),
-        transaction: genesis_set[0].1.clone(),
-        transaction_sigs: BTreeMap::from_iter([(
-            sn_dbc::GENESIS_DBC_INPUT,
-            (genesis_pubkey, genesis_sig),
-        )]),
-    };
+    // Each Mint node signs with the corresponding SecretKeyShare.
+    let mut mints = MintCluster::from_secret_key_set(
+        secret_key_set.clone(),
+        secret_key_set.threshold() + 1,
+        |_| SpendBook::new(),
+    );
+    let genesis_dbc = mints.issue_genesis_dbc(amount)?;
 
     // Bob's your uncle.
     Ok(MintInfo {
@@ -297,7 +256,7 @@ fn newkey() -> Result<()> {
     // println!("Commitment Hex: {}", to_be_hex(&poly.commitment())?);
 
     println!("\n -- SecretKeyShares --");
-    for i in (0..sks.threshold() + 5).into_iter() {
+    for i in 0..sks.threshold() + 5 {
         println!(
             "  {}. {}",
             i,
@@ -306,13 +265,13 @@ fn newkey() -> Result<()> {
     }
 
     println!("\n -- PublicKeyShares --");
-    for i in (0..sks.threshold() + 5).into_iter() {
+    for i in 0..sks.threshold() + 5 {
         // the 2nd line matches ian coleman's bls tool output.  but why not the first?
         //        println!("  {}. {}", i, to_be_hex::<PublicKeyShare>(&sks.public_keys().public_key_share(i))?);
         println!(
             "  {}. {}",
             i,
-            encode(&sks.public_keys().public_key_share(i).to_bytes())
+            encode(sks.public_keys().public_key_share(i).to_bytes())
         );
     }
 
@@ -345,7 +304,7 @@ fn print_mintinfo_human(mintinfo: &MintInfo) -> Result<()> {
     );
 
     println!("\n   -- SecretKeyShares --");
-    for i in (0..mintinfo.secret_key_set.threshold() + 2).into_iter() {
+    for i in 0..mintinfo.secret_key_set.threshold() + 2 {
         println!(
             "    {}. {}",
             i,
@@ -356,14 +315,14 @@ fn print_mintinfo_human(mintinfo: &MintInfo) -> Result<()> {
     let mut secret_key_shares: BTreeMap<usize, SecretKeyShare> = Default::default();
 
     println!("\n   -- PublicKeyShares --");
-    for i in (0..mintinfo.secret_key_set.threshold() + 2).into_iter() {
+    for i in 0..mintinfo.secret_key_set.threshold() + 2 {
         // the 2nd line matches ian coleman's bls tool output.  but why not the first?
         //        println!("  {}. {}", i, to_be_hex::<PublicKeyShare>(&sks.public_keys().public_key_share(i))?);
         println!(
             "    {}. {}",
             i,
             encode(
-                &mintinfo
+                mintinfo
                     .secret_key_set
                     .public_keys()
                     .public_key_share(i)
@@ -390,7 +349,7 @@ fn print_mintinfo_human(mintinfo: &MintInfo) -> Result<()> {
 
     println!("-- SpendBook --\n");
     for (dbchash, _tx) in &mintinfo.mintnode()?.spendbook {
-        println!("  {}", encode(&dbchash));
+        println!("  {}", encode(dbchash));
     }
 
     println!();
@@ -400,7 +359,7 @@ fn print_mintinfo_human(mintinfo: &MintInfo) -> Result<()> {
 
 fn secret_key_set_to_shares(sks: &SecretKeySet) -> (PublicKeySet, BTreeMap<usize, SecretKeyShare>) {
     let mut secret_key_shares: BTreeMap<usize, SecretKeyShare> = Default::default();
-    for i in (0..sks.threshold() + 1).into_iter() {
+    for i in 0..sks.threshold() + 1 {
         secret_key_shares.insert(i, sks.secret_key_share(i));
     }
     (sks.public_keys(), secret_key_shares)
@@ -458,7 +417,7 @@ fn print_dbc_human(
 
 /// handles decode command.  
 fn decode_input() -> Result<()> {
-    let t = readline_prompt("\n[d: DBC, rt: ReissueTransaction, s: SignatureSharesMap, rr: ReissueRequest, pks: PublicKeySet, sks: SecretKeySet]\nType: ")?;
+    let t = readline_prompt("\n[d: DBC, rt: ReissueTransaction, s: PartiallySignedReissue, rr: ReissueRequest, pks: PublicKeySet, sks: SecretKeySet]\nType: ")?;
     let input = readline_prompt_nl("\nPaste Data: ")?;
     let bytes = decode(input)?;
 
@@ -490,16 +449,12 @@ fn decode_input() -> Result<()> {
                 pks.threshold(),
                 pks.threshold() + 1
             );
-            println!("  public_key: {}", encode(&pks.public_key().to_bytes()));
+            println!("  public_key: {}", encode(pks.public_key().to_bytes()));
             // temporary: the 2nd line matches ian coleman's bls tool output.  but why not the first?
             //            println!("PublicKeyShare[0]: {}", to_be_hex(&pks.public_key_share(0))? );
             println!("\n  PublicKeyShares:");
             for i in 0..pks.threshold() + 1 {
-                println!(
-                    "    {} : {}",
-                    i,
-                    encode(&pks.public_key_share(i).to_bytes())
-                );
+                println!("    {} : {}", i, encode(pks.public_key_share(i).to_bytes()));
             }
             println!("-- End PublicKeySet --\n");
         }
@@ -527,8 +482,8 @@ fn decode_input() -> Result<()> {
             from_be_bytes::<ReissueTransactionUnblinded>(&bytes)?
         ),
         "s" => println!(
-            "\n\n-- SignatureSharesMap --\n\n{:#?}",
-            from_be_bytes::<SignatureSharesMap>(&bytes)?
+            "\n\n-- PartiallySignedReissue --\n\n{:#?}",
+            from_be_bytes::<PartiallySignedReissue>(&bytes)?
         ),
         "rr" => println!(
             "\n\n-- ReissueRequest --\n\n{:#?}",
@@ -573,7 +528,7 @@ fn validate(mintinfo: &MintInfo) -> Result<()> {
             true => println!("\nThis DBC is unspendable.  (valid but has already been spent)\n"),
             false => println!("\nThis DBC is spendable.   (valid and has not been spent)\n"),
         },
-        Err(e) => println!("\nInvalid DBC.  {}", e.to_string()),
+        Err(e) => println!("\nInvalid DBC.  {}", e),
     }
 
     Ok(())
@@ -663,7 +618,7 @@ fn prepare_tx() -> Result<()> {
                 "\nThe amount should normally be in the range 1..{}. Change it? [y/n]: ",
                 remaining
             ))?;
-            if answer.to_ascii_lowercase() != "n" {
+            if !answer.eq_ignore_ascii_case("n") {
                 continue;
             }
         }
@@ -677,10 +632,7 @@ fn prepare_tx() -> Result<()> {
 
         let pub_out_set: PublicKeySet = from_be_hex(&pub_out)?;
 
-        tx_builder = tx_builder.add_output(Output {
-            amount,
-            owner: pub_out_set.public_key(),
-        });
+        tx_builder = tx_builder.add_output(Output::new(amount, pub_out_set.public_key()));
 
         pk_pks.insert(pub_out_set.public_key(), pub_out_set);
         i += 1;
@@ -688,11 +640,11 @@ fn prepare_tx() -> Result<()> {
 
     println!("\n\nThank-you.   Preparing ReissueTransaction...\n\n");
 
-    let (reissue_tx, output_owners) = tx_builder.build()?;
+    let (reissue_tx, receipt) = tx_builder.build()?;
 
     // generate output Hash -> PublicKeySet map
     let mut outputs_owners: HashMap<Hash, PublicKeySet> = Default::default();
-    for (h, pk) in output_owners.iter() {
+    for (h, pk) in receipt.output_owners.iter() {
         let pks = pk_pks.get(pk).ok_or_else(|| anyhow!("pubkey not found"))?;
         outputs_owners.insert(*h, pks.clone());
     }
@@ -758,21 +710,23 @@ fn sign_tx() -> Result<()> {
         inputs.insert(dbc.clone(), secrets);
     }
 
-    println!("\n\nThank-you.   Preparing SignatureSharesMap...\n\n");
+    println!("\n\nThank-you.   Preparing PartiallySignedReissue...\n\n");
 
-    let mut sig_shares: SignatureSharesMap = Default::default();
+    let mut partially_signed = PartiallySignedReissue::new(tx.inner.clone());
     for (dbc, secrets) in inputs.iter() {
-        let mut sigs: HashMap<usize, SignatureShare> = Default::default();
-        for (idx, secret) in secrets.iter() {
-            let sig_share = secret.sign(&tx.inner.blinded().hash());
-            sigs.insert(*idx, sig_share);
-        }
-        sig_shares.0.insert(dbc.name(), sigs);
+        let pubkeyset = tx
+            .inputs_owners
+            .get(&dbc.name())
+            .ok_or_else(|| anyhow!("PubKeySet not found"))?;
+        let sig_shares = secrets
+            .iter()
+            .map(|(idx, secret)| (*idx, secret.sign(tx.inner.blinded().hash())));
+        partially_signed.add_signature_shares(dbc.name(), pubkeyset.clone(), sig_shares)?;
     }
 
-    println!("\n-- SignatureSharesMap --");
-    println!("{}", to_be_hex(&sig_shares)?);
-    println!("-- End SignatureSharesMap --\n");
+    println!("\n-- PartiallySignedReissue --");
+    println!("{}", to_be_hex(&partially_signed)?);
+    println!("-- End PartiallySignedReissue --\n");
 
     Ok(())
 }
@@ -781,71 +735,35 @@ fn sign_tx() -> Result<()> {
 fn prepare_reissue() -> Result<()> {
     let tx_input = readline_prompt_nl("\nReissueTransaction: ")?;
     let tx: ReissueTransactionUnblinded = from_be_hex(&tx_input)?;
-    let mut sig_shares_by_input: HashMap<Hash, BTreeMap<usize, SignatureShare>> =
-        Default::default();
+    let mut partially_signed = PartiallySignedReissue::new(tx.inner.clone());
 
-    // Get from user: SignatureSharesMap(s) for each tx input
-    //                until required # of SignatureShare obtained.
-    for dbc in tx.inner.inputs.iter() {
-        println!("-----------------");
-        println!("Input [id: {}]", encode(dbc.name()),);
+    // Get from user: PartiallySignedReissue(s) until every tx input is signed for.
+    while !partially_signed.is_complete() {
         println!("-----------------");
-
-        let pubkeyset = tx
-            .inputs_owners
-            .get(&dbc.name())
-            .ok_or_else(|| anyhow!("PubKeySet not found"))?;
-
-        let mut num_shares = 0usize;
-        while num_shares < pubkeyset.threshold() + 1 {
-            let ssm_input = readline_prompt_nl("\nSignatureSharesMap, or 'cancel': ")?;
-            let shares_map: SignatureSharesMap = if ssm_input == "cancel" {
-                println!("\nprepare_reissue cancelled.\n");
-                return Ok(());
-            } else {
-                from_be_hex(&ssm_input)?
-            };
-            for (name, shares) in shares_map.0.iter() {
-                for (idx, share) in shares.iter() {
-                    let list = sig_shares_by_input
-                        .entry(*name)
-                        .or_insert_with(BTreeMap::default);
-                    (*list).insert(*idx, share.clone());
-                    num_shares += 1;
-                }
+        for name in partially_signed.missing_inputs() {
+            match partially_signed.shares_needed(&name) {
+                Some(n) => println!(
+                    "Input [id: {}] needs {} more SignatureShare",
+                    encode(name),
+                    n
+                ),
+                None => println!("Input [id: {}] is not yet signed", encode(name)),
             }
         }
-    }
-
-    let mut proofs: HashMap<Hash, (PublicKey, Signature)> = Default::default();
-    for dbc in tx.inner.inputs.iter() {
-        let shares = match sig_shares_by_input.get(&dbc.name()) {
-            Some(s) => s,
-            None => {
-                return Err(anyhow!(
-                    "Signature Shares not found for input Dbc {}",
-                    encode(&dbc.name())
-                ))
-            }
-        };
-        let pubkeyset = tx
-            .inputs_owners
-            .get(&dbc.name())
-            .ok_or_else(|| anyhow!("PubKeySet not found"))?;
+        println!("-----------------");
 
-        let sig = pubkeyset
-            .combine_signatures(shares)
-            .map_err(|e| Error::msg(format!("{}", e)))?;
-        proofs.insert(dbc.name(), (pubkeyset.public_key(), sig));
+        let psr_input = readline_prompt_nl("\nPartiallySignedReissue, or 'cancel': ")?;
+        if psr_input == "cancel" {
+            println!("\nprepare_reissue cancelled.\n");
+            return Ok(());
+        }
+        partially_signed.merge(from_be_hex(&psr_input)?)?;
     }
 
     println!("\n\nThank-you.   Preparing ReissueRequest...\n\n");
 
     let reissue_request = ReissueRequestUnblinded {
-        inner: ReissueRequest {
-            transaction: tx.inner.clone(),
-            input_ownership_proofs: proofs,
-        },
+        inner: partially_signed.finalize()?,
         inputs_owners: tx.inputs_owners.clone(),
         outputs_owners: tx.outputs_owners,
     };
@@ -864,18 +782,9 @@ fn reissue(mintinfo: &mut MintInfo) -> Result<()> {
 
     println!("\n\nThank-you.   Generating DBC(s)...\n\n");
 
-    let input_hashes = reissue_request
-        .inner
-        .transaction
-        .inputs
-        .iter()
-        .map(|e| e.name())
-        .collect::<BTreeSet<_>>();
-
     reissue_exec(
         mintinfo,
         &reissue_request.inner,
-        &input_hashes,
         &reissue_request.outputs_owners,
     )
 }
@@ -956,7 +865,7 @@ fn reissue_ez(mintinfo: &mut MintInfo) -> Result<()> {
                 "\nThe amount should normally be in the range 1..{}. Change it? [y/n]: ",
                 remaining
             ))?;
-            if answer.to_ascii_lowercase() != "n" {
+            if !answer.eq_ignore_ascii_case("n") {
                 continue;
             }
         }
@@ -969,10 +878,7 @@ fn reissue_ez(mintinfo: &mut MintInfo) -> Result<()> {
 
         let pub_out_set: PublicKeySet = from_be_hex(&pub_out)?;
 
-        tx_builder = tx_builder.add_output(Output {
-            amount,
-            owner: pub_out_set.public_key(),
-        });
+        tx_builder = tx_builder.add_output(Output::new(amount, pub_out_set.public_key()));
 
         pk_pks.insert(pub_out_set.public_key(), pub_out_set);
         i += 1;
@@ -980,62 +886,38 @@ fn reissue_ez(mintinfo: &mut MintInfo) -> Result<()> {
 
     println!("\n\nThank-you.   Generating DBC(s)...\n\n");
 
-    let input_hashes = tx_builder.inputs_hashes();
-    let (transaction, output_owners) = tx_builder.build()?;
+    let (transaction, receipt) = tx_builder.build()?;
 
     // generate output Hash -> PublicKeySet map
     let mut outputs_pks: HashMap<Hash, PublicKeySet> = Default::default();
-    for (h, pk) in output_owners.iter() {
+    for (h, pk) in receipt.output_owners.iter() {
         let pks = pk_pks.get(pk).ok_or_else(|| anyhow!("pubkey not found"))?;
         outputs_pks.insert(*h, pks.clone());
     }
 
-    // for each input Dbc, combine owner's SignatureShare(s) to obtain owner's Signature
-    let mut proofs: HashMap<Hash, (PublicKey, Signature)> = Default::default();
-    for (dbc, secrets) in inputs_sks.iter() {
-        let mut sig_shares: BTreeMap<usize, SignatureShare> = Default::default();
-        for (idx, secret) in secrets.iter() {
-            let sig_share = secret.sign(&transaction.blinded().hash());
-            sig_shares.insert(*idx, sig_share.clone());
-        }
-        let sig = dbc
-            .owner
-            .combine_signatures(&sig_shares)
-            .map_err(|e| anyhow!(e))?;
-        proofs.insert(dbc.inner.name(), (dbc.owner.public_key(), sig));
-    }
-
-    let reissue_request = ReissueRequest {
-        transaction,
-        input_ownership_proofs: proofs,
-    };
+    // for each input Dbc, sign as owner with the owner's SecretKeyShare(s)
+    let reissue_request = ReissueRequestBuilder::new(transaction)
+        .add_signers(inputs_sks.into_iter().map(|(dbc, secrets)| {
+            (
+                dbc.inner.name(),
+                OwnerSigner::SecretKeyShares(dbc.owner, secrets),
+            )
+        }))
+        .build()?;
 
-    reissue_exec(mintinfo, &reissue_request, &input_hashes, &outputs_pks)
+    reissue_exec(mintinfo, &reissue_request, &outputs_pks)
 }
 
 /// Performs reissue
 fn reissue_exec(
     mintinfo: &mut MintInfo,
     reissue_request: &ReissueRequest,
-    input_hashes: &BTreeSet<Hash>,
     outputs_pks: &HashMap<Hash, PublicKeySet>,
 ) -> Result<()> {
-    let mut dbc_builder: DbcBuilder = Default::default();
-    dbc_builder = dbc_builder.set_reissue_transaction(reissue_request.transaction.clone());
-
     // Mint is multi-node.  So each mint node must execute Mint::reissue() and
     // provide its SignatureShare, which the client must then combine together
-    // to form the mint's Signature.  This loop would exec on the client.
-    for mint in mintinfo.mintnodes.iter_mut() {
-        // here we pretend the client has made a network request to a single mint node
-        // so this mint.reissue() execs on the Mint node and returns data to client.
-        let reissue_share = mint.reissue(reissue_request.clone(), input_hashes.clone())?;
-
-        // and now we are back to client code.
-        dbc_builder = dbc_builder.add_reissue_share(reissue_share);
-    }
-
-    let output_dbcs = dbc_builder.build()?;
+    // to form the mint's Signature.  MintCluster does this on behalf of the client.
+    let output_dbcs = mintinfo.mintnodes.reissue(reissue_request.clone())?;
 
     // for each output, construct DbcUnblinded and display
     for dbc in output_dbcs.iter() {
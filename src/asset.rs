@@ -0,0 +1,370 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Confidential assets: several kinds of token under one mint.
+//!
+//! Each asset has its own generator in place of the Pedersen value generator, so an
+//! amount commitment `v·A + r·H` only balances against commitments to the same asset.
+//! A DBC's asset tag `A = G_asset + s·H` is itself blinded by `s`, hiding which asset
+//! it holds, and each tagged output proves with an AssetSurjectionProof that its tag
+//! is a re-blinding of the tag of one of the transaction's inputs.
+//!
+//! New assets enter circulation only by the mint issuing them, see
+//! GenesisDbcProposal::new_asset().
+
+use bulletproofs::PedersenGens;
+use curve25519_dalek_ng::ristretto::RistrettoPoint;
+use curve25519_dalek_ng::scalar::Scalar;
+use merlin::Transcript;
+use serde::{Deserialize, Serialize};
+use std::iter::FromIterator;
+use tiny_keccak::{Hasher, Sha3};
+
+use crate::{DbcContent, DbcContentHash, Hash, GENESIS_DBC_INPUT};
+
+/// Identifies an asset, chosen by the mint when issuing it.
+pub type AssetId = Hash;
+
+/// The asset issued by the genesis DBC, whose generator is the Pedersen value generator
+/// so that DBCs without an asset tag are of this asset.
+pub const NATIVE_ASSET: AssetId = Hash([0u8; 32]);
+
+const ASSET_GENERATOR_DOMAIN: &[u8] = b"SN_DBC_ASSET_GENERATOR";
+const ASSET_ISSUANCE_DOMAIN: &[u8] = b"SN_DBC_ASSET_ISSUANCE";
+const SURJECTION_TRANSCRIPT_LABEL: &[u8] = b"sn_dbc asset surjection";
+
+/// The generator amounts of `asset` are committed to.
+pub fn asset_generator(asset: &AssetId) -> RistrettoPoint {
+    if *asset == NATIVE_ASSET {
+        return PedersenGens::default().B;
+    }
+
+    let mut sha3 = Sha3::v512();
+    sha3.update(ASSET_GENERATOR_DOMAIN);
+    sha3.update(asset);
+    let mut uniform_bytes = [0; 64];
+    sha3.finalize(&mut uniform_bytes);
+    RistrettoPoint::from_uniform_bytes(&uniform_bytes)
+}
+
+/// The asset tag of `asset`, blinded by `asset_blinding_factor`.
+pub fn blinded_asset_tag(asset: &AssetId, asset_blinding_factor: Scalar) -> RistrettoPoint {
+    asset_generator(asset) + asset_blinding_factor * PedersenGens::default().B_blinding
+}
+
+/// The pseudo input spent by the mint to issue `asset`, which can be spent only once.
+pub fn asset_issuance_input(asset: &AssetId) -> DbcContentHash {
+    if *asset == NATIVE_ASSET {
+        return GENESIS_DBC_INPUT;
+    }
+
+    let mut sha3 = Sha3::v256();
+    sha3.update(ASSET_ISSUANCE_DOMAIN);
+    sha3.update(asset);
+    let mut hash = [0; 32];
+    sha3.finalize(&mut hash);
+    Hash(hash)
+}
+
+pub(crate) fn native_asset() -> AssetId {
+    NATIVE_ASSET
+}
+
+/// Proof that an output's asset tag is a re-blinding of the asset tag of one of
+/// `inputs`, without revealing which.
+///
+/// A ring signature, over the differences between the output tag and each input tag,
+/// by the blinding factor difference which is known for the input of the same asset.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AssetSurjectionProof {
+    pub inputs: Vec<DbcContentHash>,
+    pub challenge: Scalar,
+    pub responses: Vec<Scalar>,
+}
+
+impl AssetSurjectionProof {
+    /// Prove `output_tag` re-blinds the tag of `ring[secret_index]`, where
+    /// `output_tag - ring[secret_index] = blinding_difference·H`.
+    pub fn new(
+        ring: &[(DbcContentHash, RistrettoPoint)],
+        output_tag: &RistrettoPoint,
+        secret_index: usize,
+        blinding_difference: Scalar,
+    ) -> Self {
+        let h = PedersenGens::default().B_blinding;
+        let keys = Vec::from_iter(ring.iter().map(|(_, tag)| output_tag - tag));
+        let transcript = Self::transcript(ring.iter().map(|(_, tag)| tag), output_tag);
+        let n = ring.len();
+
+        let mut challenges = vec![Scalar::zero(); n];
+        let mut responses = vec![Scalar::zero(); n];
+        let nonce = DbcContent::random_blinding_factor();
+        challenges[(secret_index + 1) % n] =
+            Self::challenge(&transcript, secret_index, &(nonce * h));
+        for offset in 1..n {
+            let i = (secret_index + offset) % n;
+            responses[i] = DbcContent::random_blinding_factor();
+            let commitment = responses[i] * h + challenges[i] * keys[i];
+            challenges[(i + 1) % n] = Self::challenge(&transcript, i, &commitment);
+        }
+        responses[secret_index] = nonce - challenges[secret_index] * blinding_difference;
+
+        Self {
+            inputs: Vec::from_iter(ring.iter().map(|(input, _)| *input)),
+            challenge: challenges[0],
+            responses,
+        }
+    }
+
+    /// Check `output_tag` re-blinds one of `input_tags`, the tags of self.inputs.
+    pub fn verify(&self, input_tags: &[RistrettoPoint], output_tag: &RistrettoPoint) -> bool {
+        if input_tags.is_empty()
+            || input_tags.len() != self.inputs.len()
+            || self.responses.len() != self.inputs.len()
+        {
+            return false;
+        }
+
+        let h = PedersenGens::default().B_blinding;
+        let transcript = Self::transcript(input_tags.iter(), output_tag);
+        let mut challenge = self.challenge;
+        for (i, (tag, response)) in input_tags.iter().zip(self.responses.iter()).enumerate() {
+            let commitment = response * h + challenge * (output_tag - tag);
+            challenge = Self::challenge(&transcript, i, &commitment);
+        }
+        challenge == self.challenge
+    }
+
+    fn transcript<'a>(
+        input_tags: impl Iterator<Item = &'a RistrettoPoint>,
+        output_tag: &RistrettoPoint,
+    ) -> Transcript {
+        let mut transcript = Transcript::new(SURJECTION_TRANSCRIPT_LABEL);
+        transcript.append_message(b"output_tag", output_tag.compress().as_bytes());
+        for tag in input_tags {
+            transcript.append_message(b"input_tag", tag.compress().as_bytes());
+        }
+        transcript
+    }
+
+    fn challenge(transcript: &Transcript, index: usize, commitment: &RistrettoPoint) -> Scalar {
+        let mut transcript = transcript.clone();
+        transcript.append_u64(b"index", index as u64);
+        transcript.append_message(b"commitment", commitment.compress().as_bytes());
+        let mut challenge = [0u8; 64];
+        transcript.challenge_bytes(b"challenge", &mut challenge);
+        Scalar::from_bytes_mod_order_wide(&challenge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Amount, AmountSecrets, DbcHelper, Error, MintCluster, Output, OwnerSigner,
+        ReissueRequestBuilder, Result, TransactionBuilder,
+    };
+    use std::collections::{BTreeMap, BTreeSet};
+
+    fn tagged_ring(assets: &[AssetId]) -> (Vec<(DbcContentHash, RistrettoPoint)>, Vec<Scalar>) {
+        let blinding_factors =
+            Vec::from_iter(assets.iter().map(|_| DbcContent::random_blinding_factor()));
+        let ring = Vec::from_iter(assets.iter().zip(blinding_factors.iter()).map(
+            |(asset, blinding_factor)| {
+                (
+                    Hash(rand::random()),
+                    blinded_asset_tag(asset, *blinding_factor),
+                )
+            },
+        ));
+        (ring, blinding_factors)
+    }
+
+    #[test]
+    fn test_surjection_proof_hides_which_input() {
+        let (gold, silver) = (Hash([1; 32]), Hash([2; 32]));
+        let (ring, blinding_factors) = tagged_ring(&[NATIVE_ASSET, gold, silver]);
+        let input_tags = Vec::from_iter(ring.iter().map(|(_, tag)| *tag));
+
+        let output_bf = DbcContent::random_blinding_factor();
+        let output_tag = blinded_asset_tag(&gold, output_bf);
+        let proof =
+            AssetSurjectionProof::new(&ring, &output_tag, 1, output_bf - blinding_factors[1]);
+        assert!(proof.verify(&input_tags, &output_tag));
+
+        // Only for the tag proven, and the input tags it was proven against.
+        assert!(!proof.verify(
+            &input_tags,
+            &blinded_asset_tag(&gold, output_bf + output_bf)
+        ));
+        assert!(!proof.verify(&input_tags[..2], &output_tag));
+        let mut reordered = input_tags.clone();
+        reordered.swap(0, 2);
+        assert!(!proof.verify(&reordered, &output_tag));
+
+        // Nor can an output of an asset not among the inputs be proven.
+        let bronze_tag = blinded_asset_tag(&Hash([3; 32]), output_bf);
+        let forged =
+            AssetSurjectionProof::new(&ring, &bronze_tag, 1, output_bf - blinding_factors[1]);
+        assert!(!forged.verify(&input_tags, &bronze_tag));
+    }
+
+    #[test]
+    fn test_amount_secrets_bytes() -> Result<()> {
+        let native = AmountSecrets::new(100, DbcContent::random_blinding_factor());
+        assert_eq!(native.to_bytes().len(), 40);
+        assert_eq!(AmountSecrets::from_bytes_ref(&native.to_bytes())?, native);
+
+        let tagged = AmountSecrets {
+            asset: Hash([1; 32]),
+            asset_blinding_factor: DbcContent::random_blinding_factor(),
+            ..native
+        };
+        assert_eq!(AmountSecrets::from_bytes_ref(&tagged.to_bytes())?, tagged);
+        assert!(AmountSecrets::from_bytes_ref(&tagged.to_bytes()[1..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_assets_are_spent_confidentially() -> Result<()> {
+        let gold = Hash([1; 32]);
+        let mut cluster = MintCluster::random(1, 0);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let gold_issued = cluster.issue_asset_dbc(gold, 500)?;
+        assert!(matches!(
+            cluster.issue_asset_dbc(gold, 500),
+            Err(Error::GenesisInputAlreadySpent)
+        ));
+
+        let inputs = Vec::from_iter([genesis, gold_issued].iter().map(|dbc| {
+            let secrets = cluster.decrypt_amount_secrets(&dbc.content).unwrap();
+            (dbc.clone(), secrets)
+        }));
+        assert_eq!(inputs[1].1.asset, gold);
+        let mint_keys = cluster.secret_key_set().clone();
+        let mint_signer = || OwnerSigner::SecretKeySet(mint_keys.clone());
+
+        let alice = crate::bls_dkg_id();
+        let bob = crate::bls_dkg_id();
+        let pay = |gold_to_bob: Amount, gold_change: Amount| {
+            TransactionBuilder::default()
+                .add_inputs(inputs.clone())
+                .add_output(Output::new(1000, alice.public_key_set.public_key()))
+                .add_asset_output(
+                    gold,
+                    Output::new(gold_to_bob, bob.public_key_set.public_key()),
+                )
+                .add_asset_output(
+                    gold,
+                    Output::new(gold_change, alice.public_key_set.public_key()),
+                )
+                .build()
+        };
+
+        // Gold cannot be minted by spending the native asset.
+        let (inflating_tx, _) = pay(300, 300)?;
+        let inflating_req = ReissueRequestBuilder::new(inflating_tx)
+            .add_signers(inputs.iter().map(|(dbc, _)| (dbc.name(), mint_signer())))
+            .build()?;
+        assert!(matches!(
+            cluster.reissue(inflating_req),
+            Err(Error::DbcReissueRequestDoesNotBalance)
+        ));
+
+        // Nor can an asset not among the inputs be paid.
+        assert!(matches!(
+            TransactionBuilder::default()
+                .add_inputs(inputs.clone())
+                .add_asset_output(
+                    Hash([2; 32]),
+                    Output::new(1, bob.public_key_set.public_key()),
+                )
+                .build(),
+            Err(Error::AssetNotInInputs(_))
+        ));
+
+        // Nor an output's proof be used for another.
+        let (transaction, receipt) = pay(200, 300)?;
+        let mut swapped = transaction.clone();
+        let proofs = Vec::from_iter(swapped.asset_surjection_proofs.values().cloned());
+        for (proof, other) in swapped
+            .asset_surjection_proofs
+            .values_mut()
+            .zip(proofs.iter().rev())
+        {
+            *proof = other.clone();
+        }
+        assert!(matches!(
+            swapped.validate_balance(),
+            Err(Error::InvalidAssetSurjectionProof(_))
+        ));
+
+        let reissue_req = ReissueRequestBuilder::new(transaction.clone())
+            .add_signers(inputs.iter().map(|(dbc, _)| (dbc.name(), mint_signer())))
+            .build()?;
+        let dbcs = cluster.reissue(reissue_req)?;
+        assert_eq!(dbcs.len(), 3);
+
+        // No output's tag reveals its asset, but each owner learns theirs.
+        let gold_generator = asset_generator(&gold).compress();
+        for dbc in dbcs.iter() {
+            assert!(dbc.content.asset_tag.is_some());
+            assert_ne!(dbc.content.asset_tag, Some(gold_generator));
+
+            let owner = if receipt.output_owners[&dbc.name()] == bob.public_key_set.public_key() {
+                &bob
+            } else {
+                &alice
+            };
+            let secrets = DbcHelper::decrypt_amount_secrets(owner, &dbc.content)?;
+            assert_eq!(secrets, receipt.output_amount_secrets[&dbc.name()]);
+            assert!(dbc
+                .content
+                .confirm_provided_amount_matches_commitment(&secrets));
+        }
+        assert_eq!(
+            receipt.amount_paid_to(&bob.public_key_set.public_key()),
+            200
+        );
+
+        // And the outputs are spent again, recombining the gold.
+        let (merge_tx, merge_receipt) = TransactionBuilder::default()
+            .add_inputs(
+                dbcs.iter()
+                    .map(|dbc| (dbc.clone(), receipt.output_amount_secrets[&dbc.name()])),
+            )
+            .add_output(Output::new(1000, alice.public_key_set.public_key()))
+            .add_asset_output(gold, Output::new(500, alice.public_key_set.public_key()))
+            .build()?;
+        let merge_req = ReissueRequestBuilder::new(merge_tx)
+            .add_signers(dbcs.iter().map(|dbc| {
+                let owner = match receipt.output_owners[&dbc.name()] {
+                    key if key == bob.public_key_set.public_key() => &bob,
+                    _ => &alice,
+                };
+                (
+                    dbc.name(),
+                    OwnerSigner::SecretKeyShares(
+                        owner.public_key_set.clone(),
+                        BTreeMap::from_iter([(owner.index, owner.secret_key_share.clone())]),
+                    ),
+                )
+            }))
+            .build()?;
+        let merged = cluster.reissue(merge_req)?;
+        assert_eq!(
+            BTreeSet::from_iter(merged.iter().map(|dbc| {
+                let secrets = merge_receipt.output_amount_secrets[&dbc.name()];
+                (secrets.asset, secrets.amount)
+            })),
+            BTreeSet::from_iter([(NATIVE_ASSET, 1000), (gold, 500)])
+        );
+
+        Ok(())
+    }
+}
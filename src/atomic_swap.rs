@@ -0,0 +1,229 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Atomic swaps of DBCs between independent mints, by hash time locked DBCs.
+//!
+//! Alice has DBCs of mint A and wants Bob's DBCs of mint B.  Neither mint knows of
+//! the other, so the swap is made atomic by locking both payments to the same hash:
+//!
+//! 1. Alice picks a secret preimage, and tells Bob its hash,
+//!    SpendingCondition::preimage_hash().
+//! 2. Alice pays Bob on mint A with HashTimeLock::output(), locked to the hash,
+//!    refundable to her after epoch `t_a`.  She gives Bob the DBC and its amount
+//!    secrets, from her PaymentReceipt.
+//! 3. Bob checks the DBC with HashTimeLock::validate(), then pays Alice on mint B
+//!    locked to the same hash, refundable to him after an epoch `t_b` well before
+//!    `t_a`.  He gives Alice that DBC and its amount secrets.
+//! 4. Alice checks Bob's DBC, and claims it on mint B before `t_b` with a
+//!    ReissueRequest carrying HashTimeLock::claim_proof(), which reveals the preimage.
+//! 5. Bob learns the preimage from mint B, Mint::revealed_preimage(), and with it
+//!    claims Alice's DBC on mint A before `t_a`.
+//!
+//! If Alice never claims, both reclaim their DBCs with their refund keys once the
+//! deadlines pass, Bob first.  If she claims late, mint B refuses, since from `t_b`
+//! only Bob's refund key may spend his DBC.  `t_a` must leave Bob time to claim after
+//! `t_b`, allowing for any difference between the two mints' epochs.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Amount, DbcContent, Epoch, Error, Hash, Output, PublicKey, Result, Signature,
+    SpendingCondition, SpendingProof, TimeLock,
+};
+
+/// One side of an atomic swap: a DBC which `recipient` may spend with the preimage
+/// of `hash`, until the epoch `refund_after`, from which only `refund` may spend it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashTimeLock {
+    pub hash: Hash,
+    pub recipient: PublicKey,
+    pub refund: PublicKey,
+    pub refund_after: Epoch,
+}
+
+impl HashTimeLock {
+    /// The condition on which `recipient` may claim the DBC.
+    pub fn spending_condition(&self) -> SpendingCondition {
+        SpendingCondition::all_of([
+            SpendingCondition::HashLock(self.hash),
+            SpendingCondition::Key(self.recipient),
+        ])
+    }
+
+    pub fn time_lock(&self) -> TimeLock {
+        TimeLock {
+            not_before: None,
+            refund: Some((self.refund_after, self.refund)),
+        }
+    }
+
+    /// An output of `amount` locked by this, whose amount is encrypted to `recipient`.
+    pub fn output(&self, amount: Amount) -> Output {
+        Output::new(amount, self.recipient)
+            .with_time_lock(self.time_lock())
+            .with_spending_condition(self.spending_condition())
+    }
+
+    /// Checks `content` is locked by this, as the recipient should before paying
+    /// anything in return.
+    pub fn validate(&self, content: &DbcContent) -> Result<()> {
+        let owner = self.spending_condition().blinded_owner(&content.parents);
+        let time_lock = self.time_lock().blind(&content.parents);
+        if content.owner == owner && content.time_lock.as_ref() == Some(&time_lock) {
+            Ok(())
+        } else {
+            Err(Error::HashTimeLockMismatch)
+        }
+    }
+
+    /// The SpendingProof for `recipient` to claim the DBC, given the preimage and their
+    /// signature over the claiming transaction, ie. `ReissueTransaction::blinded().hash()`.
+    pub fn claim_proof(&self, preimage: Vec<u8>, recipient_sig: Signature) -> SpendingProof {
+        SpendingProof::new(self.spending_condition())
+            .add_signature(self.recipient, recipient_sig)
+            .add_preimage(preimage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::spend_to;
+    use crate::{
+        AmountSecrets, Dbc, MintCluster, OwnerSigner, ReissueRequestBuilder, SimpleEpochSource,
+    };
+    use blsttc::SecretKey;
+
+    // Claim `dbc`, locked by `lock`, with `preimage` and the recipient's `key`.
+    fn claim(
+        mint: &mut MintCluster,
+        dbc: &Dbc,
+        secrets: AmountSecrets,
+        lock: &HashTimeLock,
+        key: &SecretKey,
+        preimage: &[u8],
+    ) -> Result<Vec<Dbc>> {
+        let transaction = spend_to(dbc, secrets, key.public_key())?;
+        let sig = key.sign(transaction.blinded().hash());
+        let reissue_req = ReissueRequestBuilder::new(transaction)
+            .add_spending_proof(dbc.name(), lock.claim_proof(preimage.to_vec(), sig))
+            .build()?;
+        mint.reissue(reissue_req)
+    }
+
+    #[test]
+    fn test_swap_between_mints() -> Result<()> {
+        let alice = SecretKey::random();
+        let bob = SecretKey::random();
+        let epoch = SimpleEpochSource::new(0);
+
+        // Alice locks 100 of mint A for bob, to the hash of her preimage.
+        let preimage = b"known only to alice, until she claims".to_vec();
+        let to_bob = HashTimeLock {
+            hash: SpendingCondition::preimage_hash(&preimage),
+            recipient: bob.public_key(),
+            refund: alice.public_key(),
+            refund_after: 20,
+        };
+        let mut mint_a = MintCluster::random(1, 0);
+        let (alice_dbc, alice_secrets) = mint_a.issue_genesis_to(to_bob.output(100))?;
+        mint_a.set_epoch_source(epoch.clone());
+        to_bob.validate(&alice_dbc.content)?;
+
+        // Bob locks 50 of mint B for alice to the same hash, with an earlier deadline.
+        let to_alice = HashTimeLock {
+            hash: to_bob.hash,
+            recipient: alice.public_key(),
+            refund: bob.public_key(),
+            refund_after: 10,
+        };
+        let mut mint_b = MintCluster::random(1, 0);
+        let (bob_dbc, bob_secrets) = mint_b.issue_genesis_to(to_alice.output(50))?;
+        mint_b.set_epoch_source(epoch.clone());
+        assert_ne!(mint_a.public_key_set(), mint_b.public_key_set());
+        to_alice.validate(&bob_dbc.content)?;
+        assert!(matches!(
+            to_bob.validate(&bob_dbc.content),
+            Err(Error::HashTimeLockMismatch)
+        ));
+
+        // Neither can claim without the preimage.
+        assert!(matches!(
+            claim(
+                &mut mint_a,
+                &alice_dbc,
+                alice_secrets,
+                &to_bob,
+                &bob,
+                b"a guess"
+            ),
+            Err(Error::SpendingConditionNotMet)
+        ));
+
+        // Alice claims on mint B, revealing the preimage, which bob learns from mint B.
+        epoch.set_epoch(5);
+        claim(
+            &mut mint_b,
+            &bob_dbc,
+            bob_secrets,
+            &to_alice,
+            &alice,
+            &preimage,
+        )?;
+        let revealed = mint_b
+            .node(0)
+            .unwrap()
+            .revealed_preimage(&to_bob.hash)?
+            .unwrap()
+            .to_vec();
+
+        let bob_dbcs = claim(
+            &mut mint_a,
+            &alice_dbc,
+            alice_secrets,
+            &to_bob,
+            &bob,
+            &revealed,
+        )?;
+        assert_eq!(bob_dbcs.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unclaimed_swap_is_refunded() -> Result<()> {
+        let alice = SecretKey::random();
+        let bob = SecretKey::random();
+        let preimage = b"never revealed".to_vec();
+        let to_alice = HashTimeLock {
+            hash: SpendingCondition::preimage_hash(&preimage),
+            recipient: alice.public_key(),
+            refund: bob.public_key(),
+            refund_after: 10,
+        };
+        let mut mint = MintCluster::random(1, 0);
+        let (dbc, secrets) = mint.issue_genesis_to(to_alice.output(50))?;
+        let epoch = SimpleEpochSource::new(10);
+        mint.set_epoch_source(epoch);
+
+        // Past the deadline alice may no longer claim, even with the preimage.
+        assert!(matches!(
+            claim(&mut mint, &dbc, secrets, &to_alice, &alice, &preimage),
+            Err(Error::FailedUnblinding)
+        ));
+
+        // While bob spends it with his refund key alone.
+        let transaction = spend_to(&dbc, secrets, bob.public_key())?;
+        let reissue_req = ReissueRequestBuilder::new(transaction)
+            .add_signer(dbc.name(), OwnerSigner::SecretKey(bob))
+            .build()?;
+        mint.reissue(reissue_req)?;
+
+        Ok(())
+    }
+}
@@ -1,24 +1,68 @@
-use blsttc::{PublicKeySet, SignatureShare};
-use std::collections::{BTreeSet, HashMap, HashSet};
+use blsttc::{
+    PublicKey, PublicKeySet, SecretKey, SecretKeySet, SecretKeyShare, Signature, SignatureShare,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::iter::FromIterator;
 
 use curve25519_dalek_ng::scalar::Scalar;
 
 use crate::{
-    Amount, AmountSecrets, Dbc, DbcContent, Error, Hash, NodeSignature, ReissueShare,
-    ReissueTransaction, Result,
+    AggregatedRangeProof, Amount, AmountSecrets, AssetId, AssetSurjectionProof, CoinSelector, Dbc,
+    DbcContent, DbcContentHash, DbcTransaction, Error, ExcessCommitment, Hash, PaymentReceipt,
+    ReissueRequest, ReissueShare, ReissueTransaction, Result, SpendingCondition, SpendingProof,
+    TimeLock, TransactionContribution, NATIVE_ASSET,
 };
 
-///! Unblinded data for creating sn_dbc::DbcContent
+// The mint's signature on each input of a transaction, see Dbc::transaction_sigs.
+type TransactionSigs = BTreeMap<DbcContentHash, (PublicKey, Signature)>;
+
+/// Unblinded data for creating sn_dbc::DbcContent
 pub struct Output {
     pub amount: Amount,
     pub owner: blsttc::PublicKey,
+    /// Encrypted to the owner along with the amount, eg. an invoice id.  At most MAX_MEMO_SIZE bytes.
+    pub memo: Option<Vec<u8>>,
+    /// When the output may be reissued, and by whom.
+    pub time_lock: Option<TimeLock>,
+    /// Spends need this condition met instead of a signature by `owner`; the amount is still
+    /// encrypted to `owner`.
+    pub spending_condition: Option<SpendingCondition>,
+}
+
+impl Output {
+    /// An output of `amount` to `owner`, without a memo, time lock or spending condition.
+    pub fn new(amount: Amount, owner: blsttc::PublicKey) -> Self {
+        Self {
+            amount,
+            owner,
+            memo: None,
+            time_lock: None,
+            spending_condition: None,
+        }
+    }
+
+    pub fn with_memo(mut self, memo: Vec<u8>) -> Self {
+        self.memo = Some(memo);
+        self
+    }
+
+    pub fn with_time_lock(mut self, time_lock: TimeLock) -> Self {
+        self.time_lock = Some(time_lock);
+        self
+    }
+
+    pub fn with_spending_condition(mut self, spending_condition: SpendingCondition) -> Self {
+        self.spending_condition = Some(spending_condition);
+        self
+    }
 }
 
 #[derive(Default)]
 pub struct TransactionBuilder {
     pub inputs: HashMap<Dbc, AmountSecrets>,
-    pub outputs: Vec<Output>,
+    pub outputs: Vec<(Output, AssetId)>,
+    pub aggregate_range_proofs: bool,
+    pub fee: Amount,
 }
 
 impl TransactionBuilder {
@@ -32,70 +76,544 @@ impl TransactionBuilder {
         self
     }
 
-    pub fn add_output(mut self, output: Output) -> Self {
-        self.outputs.push(output);
-        self
+    /// Add an output of the native asset.
+    pub fn add_output(self, output: Output) -> Self {
+        self.add_asset_output(NATIVE_ASSET, output)
     }
 
     pub fn add_outputs(mut self, outputs: impl IntoIterator<Item = Output>) -> Self {
-        self.outputs.extend(outputs);
+        self.outputs
+            .extend(outputs.into_iter().map(|output| (output, NATIVE_ASSET)));
+        self
+    }
+
+    /// Add an output of `asset`, which must be the asset of one of the inputs.
+    pub fn add_asset_output(mut self, asset: AssetId, output: Output) -> Self {
+        self.outputs.push((output, asset));
+        self
+    }
+
+    /// Prove the amounts of all outputs with a single AggregatedRangeProof
+    /// instead of giving each output its own range proof.  The proof is kept once,
+    /// in the ReissueTransaction and PaymentReceipt, which outputs reference.
+    pub fn aggregate_range_proofs(mut self, aggregate: bool) -> Self {
+        self.aggregate_range_proofs = aggregate;
+        self
+    }
+
+    /// Burn `fee`, on top of the outputs, as the mint may require to reissue.  It is paid
+    /// to no one, see DbcTransaction::fee.
+    pub fn fee(mut self, fee: Amount) -> Self {
+        self.fee = fee;
         self
     }
 
+    /// Select inputs from `candidates` to pay for the outputs added so far and the fee, adding
+    /// an output to `change_owner` for any excess.
+    ///
+    /// Inputs already added count towards the payment, and `selector` chooses which
+    /// candidates cover the remainder.  Fails with InsufficientFunds if all the inputs
+    /// and candidates together do not cover the outputs.  Only amounts of the native
+    /// asset are selected, other assets' inputs and outputs must balance already.
+    pub fn select_inputs(
+        mut self,
+        candidates: impl IntoIterator<Item = (Dbc, AmountSecrets)>,
+        change_owner: blsttc::PublicKey,
+        selector: &impl CoinSelector,
+    ) -> Result<Self> {
+        let candidates = Vec::from_iter(candidates.into_iter().filter(|(dbc, secrets)| {
+            !self.inputs.contains_key(dbc) && secrets.asset == NATIVE_ASSET
+        }));
+        let inputs_amount = self.checked_inputs_sum(&NATIVE_ASSET)?;
+        let outputs_amount = self
+            .checked_outputs_sum(&NATIVE_ASSET)?
+            .checked_add(self.fee)
+            .ok_or(Error::AmountOverflow)?;
+        let available = checked_sum(
+            std::iter::once(inputs_amount)
+                .chain(candidates.iter().map(|(_, secrets)| secrets.amount)),
+        )?;
+        if available < outputs_amount {
+            return Err(Error::InsufficientFunds {
+                available,
+                required: outputs_amount,
+            });
+        }
+
+        let target = outputs_amount.saturating_sub(inputs_amount);
+        let amounts = Vec::from_iter(candidates.iter().map(|(_, secrets)| secrets.amount));
+        let mut selected = selector.select(&amounts, target);
+        selected.sort_unstable();
+        selected.dedup();
+        if let Some(i) = selected.iter().find(|i| **i >= candidates.len()) {
+            return Err(Error::InvalidOperation(format!(
+                "coin selector chose candidate {} of only {}",
+                i,
+                candidates.len()
+            )));
+        }
+        self.inputs
+            .extend(selected.into_iter().map(|i| candidates[i].clone()));
+
+        let inputs_amount = self.checked_inputs_sum(&NATIVE_ASSET)?;
+        if inputs_amount < outputs_amount {
+            return Err(Error::InvalidOperation(
+                "coin selector chose too little to cover the outputs".to_string(),
+            ));
+        }
+        if inputs_amount > outputs_amount {
+            self = self.add_output(Output::new(inputs_amount - outputs_amount, change_owner));
+        }
+        Ok(self)
+    }
+
     pub fn inputs_hashes(&self) -> BTreeSet<Hash> {
         self.inputs
-            .iter()
-            .map(|(dbc, _)| dbc.name())
+            .keys()
+            .map(|dbc| dbc.name())
             .collect::<BTreeSet<_>>()
     }
 
+    /// The sum of the inputs of the native asset.
     pub fn inputs_amount_sum(&self) -> Amount {
-        self.inputs.iter().map(|(_, s)| s.amount).sum()
+        self.inputs_asset_sum(&NATIVE_ASSET)
     }
 
+    /// The sum of the outputs of the native asset.
     pub fn outputs_amount_sum(&self) -> Amount {
-        self.outputs.iter().map(|o| o.amount).sum()
+        self.outputs_asset_sum(&NATIVE_ASSET)
     }
 
-    pub fn build(self) -> Result<(ReissueTransaction, HashMap<crate::Hash, blsttc::PublicKey>)> {
-        let parents = BTreeSet::from_iter(self.inputs.keys().map(Dbc::name));
-        let inputs_bf_sum = self
-            .inputs
+    pub fn inputs_asset_sum(&self, asset: &AssetId) -> Amount {
+        self.inputs
             .values()
-            .map(|amount_secrets| amount_secrets.blinding_factor)
+            .filter(|s| s.asset == *asset)
+            .map(|s| s.amount)
+            .sum()
+    }
+
+    pub fn outputs_asset_sum(&self, asset: &AssetId) -> Amount {
+        self.outputs
+            .iter()
+            .filter(|(_, output_asset)| output_asset == asset)
+            .map(|(o, _)| o.amount)
+            .sum()
+    }
+
+    fn checked_inputs_sum(&self, asset: &AssetId) -> Result<Amount> {
+        checked_sum(
+            self.inputs
+                .values()
+                .filter(|s| s.asset == *asset)
+                .map(|s| s.amount),
+        )
+    }
+
+    fn checked_outputs_sum(&self, asset: &AssetId) -> Result<Amount> {
+        checked_sum(
+            self.outputs
+                .iter()
+                .filter(|(_, output_asset)| output_asset == asset)
+                .map(|(o, _)| o.amount),
+        )
+    }
+
+    /// Build the ReissueTransaction, along with the PaymentReceipt recording the
+    /// owner, AmountSecrets and any SpendingCondition of each output.
+    pub fn build(self) -> Result<(ReissueTransaction, PaymentReceipt)> {
+        let parents = self.inputs_hashes();
+        let outputs_secrets = self.outputs_secrets(true);
+        let built = self.build_outputs(&parents, outputs_secrets)?;
+
+        let output_owners = HashMap::from_iter(
+            built
+                .outputs_and_secrets
+                .iter()
+                .map(|(dbc_content, owner, _)| (dbc_content.hash(), *owner)),
+        );
+        let output_amount_secrets = output_amount_secrets(&built.outputs_and_secrets);
+        let output_spending_conditions = HashMap::from_iter(
+            self.outputs
+                .iter()
+                .zip(built.outputs_and_secrets.iter())
+                .filter_map(|((output, _), (dbc_content, _, _))| {
+                    let condition = output.spending_condition.clone()?;
+                    Some((dbc_content.hash(), condition))
+                }),
+        );
+        let reissue_transaction = ReissueTransaction {
+            inputs: HashSet::from_iter(self.inputs.into_keys()),
+            outputs: HashSet::from_iter(built.outputs_and_secrets.into_iter().map(|(o, _, _)| o)),
+            aggregated_range_proof: built.aggregated_range_proof,
+            excess_commitments: Default::default(),
+            fee: self.fee,
+            asset_surjection_proofs: built.asset_surjection_proofs,
+        };
+        let receipt = PaymentReceipt {
+            transaction: reissue_transaction.blinded(),
+            output_owners,
+            output_amount_secrets,
+            output_spending_conditions,
+            aggregated_range_proof: reissue_transaction.aggregated_range_proof.clone(),
+        };
+        Ok((reissue_transaction, receipt))
+    }
+
+    /// Build this party's contribution to a transaction built together with other
+    /// parties, whose inputs across every party are `parents`.
+    ///
+    /// Every output gets a random blinding factor, rather than one balancing the
+    /// blinding factors of the inputs as build() does, so this party's amounts and
+    /// blinding factors stay private.  The difference is given as an ExcessCommitment
+    /// instead.  This party's inputs must balance its outputs and fee, and each output
+    /// has its own range proof.  Returns the contribution and the AmountSecrets of each output.
+    pub fn build_contribution(
+        self,
+        parents: BTreeSet<Hash>,
+    ) -> Result<(TransactionContribution, HashMap<Hash, AmountSecrets>)> {
+        if self.aggregate_range_proofs {
+            return Err(Error::InvalidOperation(
+                "contribution outputs cannot share an aggregated range proof".to_string(),
+            ));
+        }
+        if !self.inputs_hashes().is_subset(&parents) {
+            return Err(Error::InvalidOperation(
+                "contribution inputs must be among the transaction's parents".to_string(),
+            ));
+        }
+        let assets = BTreeSet::from_iter(
+            self.inputs
+                .values()
+                .map(|s| s.asset)
+                .chain(self.outputs.iter().map(|(_, asset)| *asset)),
+        );
+        for asset in assets {
+            let fee = if asset == NATIVE_ASSET { self.fee } else { 0 };
+            let outputs_amount = self
+                .checked_outputs_sum(&asset)?
+                .checked_add(fee)
+                .ok_or(Error::AmountOverflow)?;
+            if self.checked_inputs_sum(&asset)? != outputs_amount {
+                return Err(Error::DbcReissueRequestDoesNotBalance);
+            }
+        }
+
+        let outputs_secrets = self.outputs_secrets(false);
+        let outputs_bf_sum: Scalar = outputs_secrets
+            .iter()
+            .map(AmountSecrets::total_blinding_factor)
             .sum();
+        let excess = ExcessCommitment::new(self.inputs_bf_sum() - outputs_bf_sum);
+
+        let built = self.build_outputs(&parents, outputs_secrets)?;
+        let output_amount_secrets = output_amount_secrets(&built.outputs_and_secrets);
+        let contribution = TransactionContribution {
+            inputs: HashSet::from_iter(self.inputs.into_keys()),
+            outputs: HashSet::from_iter(built.outputs_and_secrets.into_iter().map(|(o, _, _)| o)),
+            excess,
+            fee: self.fee,
+            asset_surjection_proofs: built.asset_surjection_proofs,
+        };
+        Ok((contribution, output_amount_secrets))
+    }
+
+    fn inputs_bf_sum(&self) -> Scalar {
+        self.inputs
+            .values()
+            .map(AmountSecrets::total_blinding_factor)
+            .sum()
+    }
+
+    // Whether any input or output is of an asset other than the native one, or has an
+    // asset tag.  If so every output's asset tag is blinded, so none reveals its asset.
+    fn is_confidential(&self) -> bool {
+        self.inputs.values().any(|s| !s.is_untagged())
+            || self.outputs.iter().any(|(_, asset)| *asset != NATIVE_ASSET)
+    }
 
+    // The AmountSecrets of each output.  If `balance_inputs`, the last output's blinding
+    // factor balances the inputs', otherwise every blinding factor is random.
+    fn outputs_secrets(&self, balance_inputs: bool) -> Vec<AmountSecrets> {
+        let confidential = self.is_confidential();
+        let inputs_bf_sum = self.inputs_bf_sum();
         let mut outputs_bf_sum: Scalar = Default::default();
-        let outputs_and_owners = self
+
+        Vec::from_iter(
+            self.outputs
+                .iter()
+                .enumerate()
+                .map(|(out_idx, (output, asset))| {
+                    let asset_blinding_factor = if confidential {
+                        DbcContent::random_blinding_factor()
+                    } else {
+                        Scalar::zero()
+                    };
+                    // An output's asset tag blinding adds to its commitment's blinding.
+                    let tag_blinding = Scalar::from(output.amount) * asset_blinding_factor;
+                    let blinding_factor = DbcContent::calc_blinding_factor(
+                        balance_inputs && out_idx == self.outputs.len() - 1,
+                        inputs_bf_sum - tag_blinding,
+                        outputs_bf_sum,
+                    );
+                    let secrets = AmountSecrets {
+                        amount: output.amount,
+                        blinding_factor,
+                        asset: *asset,
+                        asset_blinding_factor,
+                    };
+                    outputs_bf_sum += secrets.total_blinding_factor();
+                    secrets
+                }),
+        )
+    }
+
+    // The content, owner and AmountSecrets of each output, the AggregatedRangeProof
+    // covering them if aggregating range proofs, and the AssetSurjectionProof of each
+    // output with an asset tag.
+    fn build_outputs(
+        &self,
+        parents: &BTreeSet<Hash>,
+        outputs_secrets: Vec<AmountSecrets>,
+    ) -> Result<BuiltOutputs> {
+        let confidential = self.is_confidential();
+        let aggregated_range_proof = if self.aggregate_range_proofs && !self.outputs.is_empty() {
+            if confidential {
+                return Err(Error::InvalidOperation(
+                    "outputs with asset tags cannot share an aggregated range proof".to_string(),
+                ));
+            }
+            let amounts = Vec::from_iter(outputs_secrets.iter().map(|s| s.amount));
+            let blinding_factors =
+                Vec::from_iter(outputs_secrets.iter().map(|s| s.blinding_factor));
+            Some(AggregatedRangeProof::new(&amounts, &blinding_factors)?)
+        } else {
+            None
+        };
+        let aggregated_range_proof_hash = aggregated_range_proof.as_ref().map(|p| p.hash());
+
+        // Each output's asset is hidden among the tags of every input.
+        let mut inputs_by_name = Vec::from_iter(
+            self.inputs
+                .iter()
+                .map(|(dbc, secrets)| (dbc.name(), secrets)),
+        );
+        inputs_by_name.sort_by_key(|(name, _)| *name);
+        let ring = Vec::from_iter(
+            inputs_by_name
+                .iter()
+                .map(|(name, secrets)| (*name, secrets.asset_tag())),
+        );
+
+        let mut asset_surjection_proofs = HashMap::new();
+        let outputs_and_secrets = self
             .outputs
             .iter()
-            .enumerate()
-            .map(|(out_idx, output)| {
-                let blinding_factor = DbcContent::calc_blinding_factor(
-                    out_idx == self.outputs.len() - 1,
-                    inputs_bf_sum,
-                    outputs_bf_sum,
-                );
-                outputs_bf_sum += blinding_factor;
-
-                let dbc_content = DbcContent::new(
-                    parents.clone(),
-                    output.amount,
-                    output.owner,
-                    blinding_factor,
-                )?;
-                Ok((dbc_content, output.owner))
+            .zip(outputs_secrets)
+            .map(|((output, _), amount_secrets)| {
+                let mut dbc_content = match aggregated_range_proof_hash {
+                    Some(proof_hash) => DbcContent::new_with_aggregated_range_proof(
+                        parents.clone(),
+                        output.amount,
+                        output.owner,
+                        amount_secrets.blinding_factor,
+                        proof_hash,
+                    ),
+                    None if confidential => {
+                        DbcContent::new_with_asset(parents.clone(), output.owner, amount_secrets)?
+                    }
+                    None => DbcContent::new(
+                        parents.clone(),
+                        output.amount,
+                        output.owner,
+                        amount_secrets.blinding_factor,
+                    )?,
+                };
+                if let Some(time_lock) = &output.time_lock {
+                    dbc_content.set_time_lock(time_lock);
+                }
+                if let Some(memo) = &output.memo {
+                    dbc_content.set_memo(&output.owner, memo)?;
+                }
+                if let Some(condition) = &output.spending_condition {
+                    dbc_content.set_spending_condition(condition);
+                }
+                if confidential {
+                    let secret_index = inputs_by_name
+                        .iter()
+                        .position(|(_, input)| input.asset == amount_secrets.asset)
+                        .ok_or(Error::AssetNotInInputs(amount_secrets.asset))?;
+                    let proof = AssetSurjectionProof::new(
+                        &ring,
+                        &amount_secrets.asset_tag(),
+                        secret_index,
+                        amount_secrets.asset_blinding_factor
+                            - inputs_by_name[secret_index].1.asset_blinding_factor,
+                    );
+                    asset_surjection_proofs.insert(dbc_content.hash(), proof);
+                }
+                Ok((dbc_content, output.owner, amount_secrets))
             })
             .collect::<Result<Vec<_>>>()?;
 
-        let inputs = HashSet::from_iter(self.inputs.into_keys());
-        let output_owners = HashMap::from_iter(
-            outputs_and_owners
+        Ok(BuiltOutputs {
+            outputs_and_secrets,
+            aggregated_range_proof,
+            asset_surjection_proofs,
+        })
+    }
+}
+
+type OutputsAndSecrets = Vec<(DbcContent, blsttc::PublicKey, AmountSecrets)>;
+
+struct BuiltOutputs {
+    outputs_and_secrets: OutputsAndSecrets,
+    aggregated_range_proof: Option<AggregatedRangeProof>,
+    asset_surjection_proofs: HashMap<Hash, AssetSurjectionProof>,
+}
+
+fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Result<Amount> {
+    amounts
+        .into_iter()
+        .try_fold(0, Amount::checked_add)
+        .ok_or(Error::AmountOverflow)
+}
+
+fn output_amount_secrets(outputs_and_secrets: &OutputsAndSecrets) -> HashMap<Hash, AmountSecrets> {
+    HashMap::from_iter(
+        outputs_and_secrets
+            .iter()
+            .map(|(dbc_content, _, secrets)| (dbc_content.hash(), *secrets)),
+    )
+}
+
+/// What the owner of an input signs with, to prove ownership of the input.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
+pub enum OwnerSigner {
+    SecretKey(SecretKey),
+    SecretKeySet(SecretKeySet),
+    /// At least threshold + 1 shares of the owner's SecretKeySet.
+    SecretKeyShares(PublicKeySet, BTreeMap<usize, SecretKeyShare>),
+    /// At least threshold + 1 signature shares over the transaction, eg. collected
+    /// from owners who each hold one SecretKeyShare.
+    SignatureShares(PublicKeySet, BTreeMap<usize, SignatureShare>),
+    /// The owner's signature over the transaction, made elsewhere.
+    Signature(PublicKey, Signature),
+}
+
+impl OwnerSigner {
+    // The owner's key and signature over `msg_hash`.
+    fn sign(&self, msg_hash: &Hash) -> Result<(PublicKey, Signature)> {
+        match self {
+            Self::SecretKey(secret_key) => Ok((secret_key.public_key(), secret_key.sign(msg_hash))),
+            Self::SecretKeySet(secret_key_set) => Ok((
+                secret_key_set.public_keys().public_key(),
+                secret_key_set.secret_key().sign(msg_hash),
+            )),
+            Self::SecretKeyShares(public_key_set, secret_key_shares) => {
+                let sig_shares = BTreeMap::from_iter(
+                    secret_key_shares
+                        .iter()
+                        .map(|(index, share)| (*index, share.sign(msg_hash))),
+                );
+                let sig = public_key_set.combine_signatures(&sig_shares)?;
+                Ok((public_key_set.public_key(), sig))
+            }
+            Self::SignatureShares(public_key_set, sig_shares) => {
+                let sig = public_key_set.combine_signatures(sig_shares)?;
+                Ok((public_key_set.public_key(), sig))
+            }
+            Self::Signature(owner, sig) => Ok((*owner, sig.clone())),
+        }
+    }
+}
+
+/// A Builder for the ReissueRequest of a ReissueTransaction, signing as the owner
+/// of each input with the OwnerSigner given for it.
+pub struct ReissueRequestBuilder {
+    pub reissue_transaction: ReissueTransaction,
+    pub signers: HashMap<Hash, OwnerSigner>,
+    pub spending_proofs: HashMap<Hash, SpendingProof>,
+}
+
+impl ReissueRequestBuilder {
+    pub fn new(reissue_transaction: ReissueTransaction) -> Self {
+        Self {
+            reissue_transaction,
+            signers: Default::default(),
+            spending_proofs: Default::default(),
+        }
+    }
+
+    /// Sign as the owner of the input named `input` with `signer`.
+    pub fn add_signer(mut self, input: Hash, signer: OwnerSigner) -> Self {
+        self.signers.insert(input, signer);
+        self
+    }
+
+    pub fn add_signers(mut self, signers: impl IntoIterator<Item = (Hash, OwnerSigner)>) -> Self {
+        self.signers.extend(signers);
+        self
+    }
+
+    /// Spend the input named `input`, which has a SpendingCondition, with `proof`.
+    pub fn add_spending_proof(mut self, input: Hash, proof: SpendingProof) -> Self {
+        self.spending_proofs.insert(input, proof);
+        self
+    }
+
+    /// Build the ReissueRequest, checking that every input has an ownership proof
+    /// whose signature verifies and whose key unblinds the input's owner, or else a
+    /// spending proof which meets the condition the input's owner was blinded from.
+    pub fn build(self) -> Result<ReissueRequest> {
+        let transaction_hash = self.reissue_transaction.blinded().hash();
+
+        let inputs = BTreeMap::from_iter(
+            self.reissue_transaction
+                .inputs
                 .iter()
-                .map(|(dbc_content, owner)| (dbc_content.hash(), *owner)),
+                .map(|input| (input.name(), input)),
         );
-        let outputs = HashSet::from_iter(outputs_and_owners.into_iter().map(|(o, _)| o));
-        Ok((ReissueTransaction { inputs, outputs }, output_owners))
+        if self
+            .signers
+            .keys()
+            .chain(self.spending_proofs.keys())
+            .any(|name| !inputs.contains_key(name))
+        {
+            return Err(Error::UnknownInput);
+        }
+
+        let mut input_ownership_proofs = HashMap::new();
+        let mut input_spending_proofs = HashMap::new();
+        for (name, input) in inputs {
+            if let Some(proof) = self.spending_proofs.get(&name) {
+                proof.condition.validate_size()?;
+                input.content.validate_spender(&proof.condition)?;
+                if !proof.verify(&transaction_hash) {
+                    return Err(Error::SpendingConditionNotMet);
+                }
+                input_spending_proofs.insert(name, proof.clone());
+                continue;
+            }
+            let signer = self
+                .signers
+                .get(&name)
+                .ok_or(Error::MissingInputOwnerProof)?;
+            let (owner, sig) = signer.sign(&transaction_hash)?;
+            if !owner.verify(&sig, transaction_hash) {
+                return Err(Error::FailedSignature);
+            }
+            input
+                .content
+                .validate_spender(&SpendingCondition::Key(owner))?;
+            input_ownership_proofs.insert(name, (owner, sig));
+        }
+
+        Ok(ReissueRequest {
+            transaction: self.reissue_transaction,
+            input_ownership_proofs,
+            input_spending_proofs,
+        })
     }
 }
 
@@ -131,8 +649,9 @@ impl DbcBuilder {
 
     /// Build the output DBCs
     ///
-    /// Note that the result Vec may be empty if the ReissueTransaction
-    /// has not been set or no ReissueShare has been added.
+    /// The mint of each section signs only the inputs in its prefix, so the shares
+    /// of every section spending an input must be added.  The shares for an input
+    /// must all be under one PublicKeySet, and are combined into its signature.
     pub fn build(self) -> Result<Vec<Dbc>> {
         if self.reissue_shares.is_empty() {
             return Err(Error::NoReissueShares);
@@ -142,91 +661,615 @@ impl DbcBuilder {
             Some(rt) => rt,
             None => return Err(Error::NoReissueTransaction),
         };
+        let dbc_transaction = reissue_transaction.blinded();
 
-        let mut mint_sig_shares: Vec<NodeSignature> = Default::default();
-        let mut pk_set: HashSet<PublicKeySet> = Default::default();
+        let mut input_sig_shares: BTreeMap<
+            DbcContentHash,
+            (PublicKeySet, BTreeMap<u64, SignatureShare>),
+        > = Default::default();
 
         for rs in self.reissue_shares.iter() {
-            // Make a list of NodeSignature (sigshare from each Mint Node)
-            let mut node_shares: Vec<NodeSignature> = rs
-                .mint_node_signatures
-                .iter()
-                .map(|e| e.1 .1.clone())
-                .collect();
-            mint_sig_shares.append(&mut node_shares);
-
-            let pub_key_sets: HashSet<PublicKeySet> = rs
-                .mint_node_signatures
-                .iter()
-                .map(|e| e.1 .0.clone())
-                .collect();
-
-            // add pubkeyset to HashSet, so we can verify there is only one distinct PubKeySet
-            pk_set = &pk_set | &pub_key_sets; // union the sets together.
-
             // Verify transaction returned to us by the Mint matches our request
-            if reissue_transaction.blinded() != rs.dbc_transaction {
+            if rs.dbc_transaction != dbc_transaction {
                 return Err(Error::ReissueShareDbcTransactionMismatch);
             }
 
-            // Verify that mint sig count matches input count.
-            if rs.mint_node_signatures.len() != reissue_transaction.inputs.len() {
-                return Err(Error::ReissueShareMintNodeSignaturesLenMismatch);
-            }
+            for (input, (public_key_set, node_sig)) in rs.mint_node_signatures.iter() {
+                if !dbc_transaction.inputs.contains(input) {
+                    return Err(Error::UnknownInput);
+                }
 
-            // Verify that each input has a NodeSignature
-            for input in reissue_transaction.inputs.iter() {
-                if rs.mint_node_signatures.get(&input.name()).is_none() {
-                    return Err(Error::ReissueShareMintNodeSignatureNotFoundForInput);
+                // verify that the PublicKeySet for an input matches in all ReissueShare.
+                let (input_key_set, sig_shares) = input_sig_shares
+                    .entry(*input)
+                    .or_insert_with(|| (public_key_set.clone(), Default::default()));
+                if input_key_set != public_key_set {
+                    return Err(Error::ReissueSharePublicKeySetMismatch);
                 }
+
+                let (index, sig) = node_sig.threshold_crypto();
+                sig_shares.insert(index, sig.clone());
             }
         }
 
-        // verify that PublicKeySet for all Dbc in all ReissueShare match.
-        if pk_set.len() != 1 {
-            return Err(Error::ReissueSharePublicKeySetMismatch);
+        // Verify that each input has a NodeSignature
+        if input_sig_shares.len() != dbc_transaction.inputs.len() {
+            return Err(Error::ReissueShareMintNodeSignatureNotFoundForInput);
         }
-        let mint_public_key_set = match pk_set.iter().next() {
-            Some(pks) => pks,
-            None => return Err(Error::ReissueSharePublicKeySetMismatch),
-        };
 
-        // Transform Vec<NodeSignature> to Vec<u64, &SignatureShare>
-        let mint_sig_shares_ref: Vec<(u64, &SignatureShare)> = mint_sig_shares
-            .iter()
-            .map(|e| e.threshold_crypto())
-            .collect();
+        // Combine the signature shares for each input to obtain its Signature.
+        let transaction_sigs = input_sig_shares
+            .into_iter()
+            .map(|(input, (public_key_set, sig_shares))| {
+                let mint_sig = public_key_set.combine_signatures(&sig_shares)?;
+                Ok((input, (public_key_set.public_key(), mint_sig)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self::output_dbcs(
+            &reissue_transaction,
+            &dbc_transaction,
+            &transaction_sigs,
+        ))
+    }
+
+    /// Build the output DBCs, tolerating bad, duplicate or excess ReissueShares.
+    ///
+    /// A share is taken as coming from the mint node whose index it claims only once
+    /// its signatures verify against that node's key share in `mint_public_key_set`.
+    /// Shares which fail are forged, and discarded without blaming the node they name.
+    /// A node's own share which does not match the ReissueTransaction is discarded and
+    /// the node reported as misbehaving.  Shares repeating the index of a good share
+    /// are discarded.
+    ///
+    /// Shares are checked in the order they were added until threshold + 1 good shares
+    /// are found, so only the faults seen up to then are reported.  Returns the output
+    /// DBCs, the indices of the misbehaving nodes and the number of forged shares.
+    ///
+    /// Every share must sign every input, so this is for the nodes of a single mint
+    /// whose prefix covers all the inputs; see build() for shares from several sections.
+    pub fn build_robust(
+        self,
+        mint_public_key_set: &PublicKeySet,
+    ) -> Result<(Vec<Dbc>, BTreeSet<u64>, usize)> {
+        let reissue_transaction = match self.reissue_transaction {
+            Some(rt) => rt,
+            None => return Err(Error::NoReissueTransaction),
+        };
+        let dbc_transaction = reissue_transaction.blinded();
 
-        // Note: we can just use the first item because we already verified that
-        // all the ReissueShare match for dbc_transaction
-        let dbc_transaction = &self.reissue_shares[0].dbc_transaction;
+        let (transaction_sigs, misbehaving_nodes, forged_shares) =
+            robust_transaction_sigs(&dbc_transaction, &self.reissue_shares, mint_public_key_set)?;
+        let output_dbcs =
+            Self::output_dbcs(&reissue_transaction, &dbc_transaction, &transaction_sigs);
 
-        // Combine signatures from all the mint nodes to obtain Mint's Signature.
-        let mint_sig = mint_public_key_set.combine_signatures(mint_sig_shares_ref)?;
+        Ok((output_dbcs, misbehaving_nodes, forged_shares))
+    }
 
-        // Form the final output DBCs, with Mint's Signature for each.
+    // Form the final output DBCs, with Mint's Signature for each.
+    fn output_dbcs(
+        reissue_transaction: &ReissueTransaction,
+        dbc_transaction: &DbcTransaction,
+        transaction_sigs: &TransactionSigs,
+    ) -> Vec<Dbc> {
         let mut output_dbcs: Vec<Dbc> = reissue_transaction
             .outputs
             .iter()
             .map(|content| Dbc {
                 content: content.clone(),
                 transaction: dbc_transaction.clone(),
-                transaction_sigs: reissue_transaction
-                    .inputs
-                    .iter()
-                    .map(|input| {
-                        (
-                            input.name(),
-                            (mint_public_key_set.public_key(), mint_sig.clone()),
-                        )
-                    })
-                    .collect(),
+                transaction_sigs: transaction_sigs.clone(),
             })
             .collect();
 
         // sort outputs by name
         output_dbcs.sort_by_key(|d| d.name());
 
-        Ok(output_dbcs)
+        output_dbcs
+    }
+}
+
+/// Combine the mint's signature on each input of `dbc_transaction` from threshold + 1
+/// good `reissue_shares`, returning the signatures, the indices of the misbehaving
+/// nodes and the number of forged shares, see DbcBuilder::build_robust().
+pub(crate) fn robust_transaction_sigs(
+    dbc_transaction: &DbcTransaction,
+    reissue_shares: &[ReissueShare],
+    mint_public_key_set: &PublicKeySet,
+) -> Result<(TransactionSigs, BTreeSet<u64>, usize)> {
+    let required = mint_public_key_set.threshold() + 1;
+
+    let mut good_shares: BTreeMap<u64, BTreeMap<DbcContentHash, SignatureShare>> =
+        Default::default();
+    let mut misbehaving_nodes: BTreeSet<u64> = Default::default();
+    let mut forged_shares = 0;
+    for rs in reissue_shares.iter() {
+        if good_shares.len() >= required {
+            break;
+        }
+
+        // Only a share whose signatures all verify against the key share of the
+        // single index they claim is known to come from that node.
+        let indices = BTreeSet::from_iter(
+            rs.mint_node_signatures
+                .values()
+                .map(|(_, node_sig)| node_sig.threshold_crypto().0),
+        );
+        let index = match Vec::from_iter(indices).as_slice() {
+            [index] => *index,
+            _ => {
+                forged_shares += 1;
+                continue;
+            }
+        };
+        let key_share = mint_public_key_set.public_key_share(index);
+        let is_authentic = rs
+            .mint_node_signatures
+            .iter()
+            .all(|(input, (_, node_sig))| {
+                key_share.verify(
+                    node_sig.threshold_crypto().1,
+                    dbc_transaction.input_sig_hash(input),
+                )
+            });
+        if !is_authentic {
+            forged_shares += 1;
+            continue;
+        }
+        if good_shares.contains_key(&index) {
+            continue;
+        }
+
+        let is_good = &rs.dbc_transaction == dbc_transaction
+            && rs
+                .mint_node_signatures
+                .keys()
+                .eq(dbc_transaction.inputs.iter())
+            && rs
+                .mint_node_signatures
+                .values()
+                .all(|(pks, _)| pks == mint_public_key_set);
+        if is_good {
+            let sigs = rs
+                .mint_node_signatures
+                .iter()
+                .map(|(input, (_, node_sig))| (*input, node_sig.threshold_crypto().1.clone()));
+            good_shares.insert(index, BTreeMap::from_iter(sigs));
+        } else {
+            misbehaving_nodes.insert(index);
+        }
+    }
+
+    if good_shares.len() < required {
+        return Err(Error::NotEnoughValidReissueShares {
+            valid: good_shares.len(),
+            required,
+            misbehaving_nodes,
+            forged_shares,
+        });
+    }
+
+    let transaction_sigs = dbc_transaction
+        .inputs
+        .iter()
+        .map(|input| {
+            let sig_shares = good_shares
+                .iter()
+                .map(|(index, sigs)| (*index, &sigs[input]));
+            let mint_sig = mint_public_key_set.combine_signatures(sig_shares)?;
+            Ok((*input, (mint_public_key_set.public_key(), mint_sig)))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok((transaction_sigs, misbehaving_nodes, forged_shares))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        KeyManager, LargestFirst, MinimiseInputs, MintCluster, NodeSignature, Randomised,
+        ReissueRequest, SmallestFirst,
+    };
+
+    // A cluster which has issued genesis, a request splitting it, and every node's share.
+    fn reissue_shares(
+        n_nodes: usize,
+        threshold: usize,
+    ) -> Result<(MintCluster, ReissueTransaction, Vec<ReissueShare>)> {
+        let mut cluster = MintCluster::random(n_nodes, threshold);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let genesis_name = genesis.name();
+        let amount_secrets = cluster.decrypt_amount_secrets(&genesis.content)?;
+        let (transaction, _) = TransactionBuilder::default()
+            .add_input(genesis, amount_secrets)
+            .add_output(Output::new(
+                1000,
+                crate::bls_dkg_id().public_key_set.public_key(),
+            ))
+            .build()?;
+        let reissue_req = ReissueRequest {
+            transaction: transaction.clone(),
+            input_ownership_proofs: HashMap::from_iter([(
+                genesis_name,
+                cluster.sign_as_owner(&transaction.blinded().hash()),
+            )]),
+            input_spending_proofs: Default::default(),
+        };
+
+        let shares = (0..n_nodes)
+            .map(|i| {
+                let node = cluster.node_mut(i).unwrap();
+                node.reissue(reissue_req.clone())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok((cluster, transaction, shares))
+    }
+
+    #[test]
+    fn test_output_memo_reaches_owner() -> Result<()> {
+        let mut cluster = MintCluster::random(1, 0);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let genesis_name = genesis.name();
+        let amount_secrets = cluster.decrypt_amount_secrets(&genesis.content)?;
+        let payee = crate::bls_dkg_id();
+        let change = crate::bls_dkg_id();
+
+        let (transaction, receipt) = TransactionBuilder::default()
+            .add_input(genesis, amount_secrets)
+            .add_output(
+                Output::new(300, payee.public_key_set.public_key())
+                    .with_memo(b"invoice 42".to_vec()),
+            )
+            .add_output(Output::new(700, change.public_key_set.public_key()))
+            .build()?;
+        let dbcs = cluster.reissue(ReissueRequest {
+            input_ownership_proofs: HashMap::from_iter([(
+                genesis_name,
+                cluster.sign_as_owner(&transaction.blinded().hash()),
+            )]),
+            transaction,
+            input_spending_proofs: Default::default(),
+        })?;
+
+        for dbc in dbcs.iter() {
+            if receipt.output_owners[&dbc.name()] == payee.public_key_set.public_key() {
+                assert_eq!(
+                    crate::DbcHelper::decrypt_memo(&payee, &dbc.content)?,
+                    Some(b"invoice 42".to_vec())
+                );
+            } else {
+                assert_eq!(crate::DbcHelper::decrypt_memo(&change, &dbc.content)?, None);
+            }
+        }
+
+        // Oversized memos are refused when building.
+        assert!(matches!(
+            TransactionBuilder::default()
+                .add_output(
+                    Output::new(0, payee.public_key_set.public_key()).with_memo(vec![
+                        0;
+                        crate::MAX_MEMO_SIZE
+                            + 1
+                    ])
+                )
+                .build(),
+            Err(Error::MemoTooLong { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_robust_discards_bad_and_duplicate_shares() -> Result<()> {
+        let (cluster, transaction, shares) = reissue_shares(5, 2)?;
+        let public_key_set = cluster.public_key_set();
+
+        // Node 0 sends its share twice, node 1 signs another transaction,
+        // node 2 claims another public key set, and node 4's share is forged
+        // as node 3's.
+        let mut wrong_sig = shares[1].clone();
+        let other_tx_sig = cluster
+            .node(1)
+            .unwrap()
+            .key_manager()
+            .sign(&Hash::from([1; 32]))
+            .unwrap();
+        for (_, sig) in wrong_sig.mint_node_signatures.values_mut() {
+            *sig = other_tx_sig.clone();
+        }
+        let mut wrong_key_set = shares[2].clone();
+        for (pks, _) in wrong_key_set.mint_node_signatures.values_mut() {
+            *pks = MintCluster::random(1, 0).public_key_set();
+        }
+        let mut forged = shares[4].clone();
+        for (_, sig) in forged.mint_node_signatures.values_mut() {
+            *sig = NodeSignature::new(3, sig.threshold_crypto().1.clone());
+        }
+
+        let (output_dbcs, misbehaving_nodes, forged_shares) = DbcBuilder::new(transaction.clone())
+            .add_reissue_share(shares[0].clone())
+            .add_reissue_share(shares[0].clone())
+            .add_reissue_share(wrong_sig)
+            .add_reissue_share(forged)
+            .add_reissue_share(wrong_key_set.clone())
+            .add_reissue_share(shares[3].clone())
+            .add_reissue_share(shares[4].clone())
+            .build_robust(&public_key_set)?;
+
+        // Node 1's signature does not verify as its own, so it is not blamed, nor
+        // is node 3 for the share forged in its name.
+        assert_eq!(misbehaving_nodes, BTreeSet::from_iter([2]));
+        assert_eq!(forged_shares, 2);
+        assert_eq!(output_dbcs.len(), 1);
+        for node in cluster.nodes() {
+            output_dbcs[0].confirm_valid(node.key_manager())?;
+        }
+
+        // Whereas build() fails on any bad share.
+        assert!(matches!(
+            DbcBuilder::new(transaction)
+                .add_reissue_share(shares[0].clone())
+                .add_reissue_share(shares[3].clone())
+                .add_reissue_share(shares[4].clone())
+                .add_reissue_share(wrong_key_set)
+                .build(),
+            Err(Error::ReissueSharePublicKeySetMismatch)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reissue_request_builder_signers() -> Result<()> {
+        let mut cluster = MintCluster::random(3, 1);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let genesis_name = genesis.name();
+        let amount_secrets = cluster.decrypt_amount_secrets(&genesis.content)?;
+        let (transaction, _) = TransactionBuilder::default()
+            .add_input(genesis, amount_secrets)
+            .add_output(Output::new(
+                1000,
+                crate::bls_dkg_id().public_key_set.public_key(),
+            ))
+            .build()?;
+        let msg_hash = transaction.blinded().hash();
+
+        // The genesis DBC is owned by the mint's key, so may be signed for in every way.
+        let secret_key_set = cluster.secret_key_set().clone();
+        let public_key_set = cluster.public_key_set();
+        let secret_key_shares =
+            BTreeMap::from_iter((0..2).map(|i| (i, secret_key_set.secret_key_share(i))));
+        let sig_shares = BTreeMap::from_iter(
+            secret_key_shares
+                .iter()
+                .map(|(i, share)| (*i, share.sign(msg_hash))),
+        );
+        let signers = [
+            OwnerSigner::SecretKey(secret_key_set.secret_key()),
+            OwnerSigner::SecretKeySet(secret_key_set.clone()),
+            OwnerSigner::SecretKeyShares(public_key_set.clone(), secret_key_shares.clone()),
+            OwnerSigner::SignatureShares(public_key_set.clone(), sig_shares.clone()),
+        ];
+        let mut requests = signers
+            .iter()
+            .map(|signer| {
+                ReissueRequestBuilder::new(transaction.clone())
+                    .add_signer(genesis_name, signer.clone())
+                    .build()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for request in requests.iter() {
+            assert_eq!(
+                request.input_ownership_proofs[&genesis_name],
+                cluster.sign_as_owner(&msg_hash)
+            );
+        }
+        assert_eq!(cluster.reissue(requests.remove(0))?.len(), 1);
+
+        // Every input needs a signer, and every signer an input.
+        assert!(matches!(
+            ReissueRequestBuilder::new(transaction.clone()).build(),
+            Err(Error::MissingInputOwnerProof)
+        ));
+        assert!(matches!(
+            ReissueRequestBuilder::new(transaction.clone())
+                .add_signer(genesis_name, signers[0].clone())
+                .add_signer(Hash::from([1; 32]), signers[0].clone())
+                .build(),
+            Err(Error::UnknownInput)
+        ));
+
+        // Someone else's key does not unblind the owner.
+        let stranger = crate::bls_dkg_id();
+        assert!(matches!(
+            ReissueRequestBuilder::new(transaction.clone())
+                .add_signer(
+                    genesis_name,
+                    OwnerSigner::SecretKeyShares(
+                        stranger.public_key_set,
+                        BTreeMap::from_iter([(stranger.index, stranger.secret_key_share)]),
+                    ),
+                )
+                .build(),
+            Err(Error::FailedUnblinding)
+        ));
+
+        // Too few shares, or a share over something else, give no signature.
+        let mut too_few = secret_key_shares;
+        too_few.remove(&0);
+        assert!(ReissueRequestBuilder::new(transaction.clone())
+            .add_signer(
+                genesis_name,
+                OwnerSigner::SecretKeyShares(public_key_set.clone(), too_few),
+            )
+            .build()
+            .is_err());
+
+        let mut bad_share = sig_shares;
+        bad_share.insert(0, secret_key_set.secret_key_share(0).sign([1; 32]));
+        assert!(matches!(
+            ReissueRequestBuilder::new(transaction)
+                .add_signer(
+                    genesis_name,
+                    OwnerSigner::SignatureShares(public_key_set, bad_share),
+                )
+                .build(),
+            Err(Error::FailedSignature)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_inputs_adds_change() -> Result<()> {
+        let mut cluster = MintCluster::random(1, 0);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let genesis_name = genesis.name();
+        let genesis_secrets = cluster.decrypt_amount_secrets(&genesis.content)?;
+
+        // Split genesis into the payer's wallet of DBCs.
+        let payer = crate::bls_dkg_id();
+        let (split_tx, split_receipt) = TransactionBuilder::default()
+            .add_input(genesis, genesis_secrets)
+            .add_outputs(
+                [100, 200, 300, 400]
+                    .iter()
+                    .map(|amount| Output::new(*amount, payer.public_key_set.public_key())),
+            )
+            .build()?;
+        let wallet = Vec::from_iter(
+            cluster
+                .reissue(ReissueRequest {
+                    input_ownership_proofs: HashMap::from_iter([(
+                        genesis_name,
+                        cluster.sign_as_owner(&split_tx.blinded().hash()),
+                    )]),
+                    transaction: split_tx,
+                    input_spending_proofs: Default::default(),
+                })?
+                .into_iter()
+                .map(|dbc| {
+                    let secrets = split_receipt.output_amount_secrets[&dbc.name()];
+                    (dbc, secrets)
+                }),
+        );
+
+        let payee_key = crate::bls_dkg_id().public_key_set.public_key();
+        let change_key = crate::bls_dkg_id().public_key_set.public_key();
+        let payment = || TransactionBuilder::default().add_output(Output::new(450, payee_key));
+
+        let builder = payment().select_inputs(wallet.clone(), change_key, &MinimiseInputs)?;
+        assert_eq!(builder.inputs_amount_sum(), 500);
+        assert_eq!(builder.outputs_amount_sum(), 500);
+        let (payment_tx, receipt) = builder.build()?;
+        assert_eq!(receipt.amount_paid_to(&payee_key), 450);
+        assert_eq!(receipt.amount_paid_to(&change_key), 50);
+
+        // The payment balances, so the mint reissues it.
+        let input_ownership_proofs = HashMap::from_iter(payment_tx.inputs.iter().map(|dbc| {
+            let sig_share = payer.secret_key_share.sign(payment_tx.blinded().hash());
+            let sig = payer
+                .public_key_set
+                .combine_signatures([(payer.index, &sig_share)])
+                .unwrap();
+            (dbc.name(), (payer.public_key_set.public_key(), sig))
+        }));
+        cluster.reissue(ReissueRequest {
+            transaction: payment_tx,
+            input_ownership_proofs,
+            input_spending_proofs: Default::default(),
+        })?;
+
+        // Paying the exact amount of the inputs needs no change.
+        let builder = payment()
+            .add_output(Output::new(150, payee_key))
+            .select_inputs(wallet.clone(), change_key, &MinimiseInputs)?;
+        assert_eq!(builder.inputs_amount_sum(), 600);
+        assert_eq!(builder.outputs.len(), 2);
+
+        let builder = payment().select_inputs(wallet.clone(), change_key, &LargestFirst)?;
+        assert_eq!(builder.inputs_amount_sum(), 700);
+        assert_eq!(builder.outputs.len(), 2);
+
+        let builder = payment().select_inputs(wallet.clone(), change_key, &SmallestFirst)?;
+        assert_eq!(builder.inputs_amount_sum(), 600);
+        assert_eq!(builder.outputs.len(), 2);
+
+        assert!(matches!(
+            payment()
+                .add_output(Output::new(551, payee_key))
+                .select_inputs(wallet.clone(), change_key, &Randomised),
+            Err(Error::InsufficientFunds {
+                available: 1000,
+                required: 1001
+            })
+        ));
+
+        // A selector choosing candidates which do not exist is refused, not ignored.
+        struct PastTheEnd;
+        impl CoinSelector for PastTheEnd {
+            fn select(&self, candidates: &[Amount], _target: Amount) -> Vec<usize> {
+                Vec::from_iter(0..=candidates.len())
+            }
+        }
+        assert!(matches!(
+            payment().select_inputs(wallet.clone(), change_key, &PastTheEnd),
+            Err(Error::InvalidOperation(_))
+        ));
+
+        // Amounts whose sum overflows are refused, rather than wrapping around.
+        assert!(matches!(
+            payment()
+                .add_output(Output::new(Amount::MAX, payee_key))
+                .select_inputs(wallet.clone(), change_key, &LargestFirst),
+            Err(Error::AmountOverflow)
+        ));
+        assert!(matches!(
+            payment()
+                .fee(Amount::MAX - 449)
+                .select_inputs(wallet, change_key, &LargestFirst),
+            Err(Error::AmountOverflow)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_robust_needs_threshold_plus_one_good_shares() -> Result<()> {
+        let (cluster, transaction, shares) = reissue_shares(3, 1)?;
+        let public_key_set = cluster.public_key_set();
+
+        let mut other_tx = shares[1].clone();
+        other_tx.dbc_transaction.outputs.clear();
+
+        match DbcBuilder::new(transaction.clone())
+            .add_reissue_share(shares[0].clone())
+            .add_reissue_share(shares[0].clone())
+            .add_reissue_share(other_tx)
+            .build_robust(&public_key_set)
+        {
+            Err(Error::NotEnoughValidReissueShares {
+                valid: 1,
+                required: 2,
+                misbehaving_nodes,
+                forged_shares: 0,
+            }) => assert_eq!(misbehaving_nodes, BTreeSet::from_iter([1])),
+            result => panic!("Unexpected build result {:?}", result),
+        }
+
+        // Shares beyond threshold + 1 are not needed, and not checked.
+        let (_, misbehaving_nodes, forged_shares) = DbcBuilder::new(transaction)
+            .add_reissue_share(shares[2].clone())
+            .add_reissue_share(shares[0].clone())
+            .add_reissue_share(ReissueShare {
+                dbc_transaction: DbcTransaction::new(Default::default(), Default::default()),
+                mint_node_signatures: Default::default(),
+            })
+            .build_robust(&public_key_set)?;
+        assert!(misbehaving_nodes.is_empty());
+        assert_eq!(forged_shares, 0);
+
+        Ok(())
     }
 }
@@ -0,0 +1,176 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Strategies for choosing which DBCs to spend, see TransactionBuilder::select_inputs().
+
+use rand::seq::SliceRandom;
+use std::iter::FromIterator;
+
+use crate::Amount;
+
+/// Chooses which candidate inputs to spend to cover a target amount.
+pub trait CoinSelector {
+    /// The indices into `candidates` of the amounts to spend.  The chosen amounts
+    /// must sum to at least `target`, which the candidates together always cover.
+    fn select(&self, candidates: &[Amount], target: Amount) -> Vec<usize>;
+}
+
+/// Spends the largest amounts first.  Few inputs, but leaves small amounts unspent.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestFirst;
+
+/// Spends the smallest amounts first, consolidating small amounts into the change.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmallestFirst;
+
+/// Spends as few inputs as possible: the largest amounts, except that the last of them
+/// is swapped for the smallest amount which still covers the target, reducing the change.
+/// This is not always the choice of that many inputs leaving least change, finding
+/// which is a subset sum problem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinimiseInputs;
+
+/// Spends amounts in random order, so the choice of inputs reveals nothing about
+/// the payer's other DBCs or the payment amount beyond that they cover it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Randomised;
+
+impl CoinSelector for LargestFirst {
+    fn select(&self, candidates: &[Amount], target: Amount) -> Vec<usize> {
+        let mut order = Vec::from_iter(0..candidates.len());
+        order.sort_by_key(|i| std::cmp::Reverse(candidates[*i]));
+        take_until_covered(candidates, order, target)
+    }
+}
+
+impl CoinSelector for SmallestFirst {
+    fn select(&self, candidates: &[Amount], target: Amount) -> Vec<usize> {
+        let mut order = Vec::from_iter(0..candidates.len());
+        order.sort_by_key(|i| candidates[*i]);
+        take_until_covered(candidates, order, target)
+    }
+}
+
+impl CoinSelector for MinimiseInputs {
+    fn select(&self, candidates: &[Amount], target: Amount) -> Vec<usize> {
+        // No fewer inputs can cover the target than the largest do.
+        let mut selected = LargestFirst.select(candidates, target);
+        let last = match selected.pop() {
+            Some(last) => last,
+            None => return selected,
+        };
+
+        // Swap the last, smallest, input for the smallest unselected amount still covering.
+        let rest: Amount = selected.iter().map(|i| candidates[*i]).sum();
+        let needed = target.saturating_sub(rest);
+        let smallest_covering = (0..candidates.len())
+            .filter(|i| !selected.contains(i) && candidates[*i] >= needed)
+            .min_by_key(|i| candidates[*i])
+            .unwrap_or(last);
+        selected.push(smallest_covering);
+        selected
+    }
+}
+
+impl CoinSelector for Randomised {
+    fn select(&self, candidates: &[Amount], target: Amount) -> Vec<usize> {
+        let mut order = Vec::from_iter(0..candidates.len());
+        order.shuffle(&mut rand::thread_rng());
+        take_until_covered(candidates, order, target)
+    }
+}
+
+// The prefix of `order` whose amounts first cover `target`.
+fn take_until_covered(candidates: &[Amount], order: Vec<usize>, target: Amount) -> Vec<usize> {
+    let mut total: Amount = 0;
+    let mut selected = Vec::new();
+    for i in order {
+        if total >= target {
+            break;
+        }
+        total = total.saturating_add(candidates[i]);
+        selected.push(i);
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{TinyInt, TinyVec};
+    use quickcheck_macros::quickcheck;
+
+    fn selected_amounts(
+        selector: &dyn CoinSelector,
+        candidates: &[Amount],
+        target: Amount,
+    ) -> Vec<Amount> {
+        let mut amounts = Vec::from_iter(
+            selector
+                .select(candidates, target)
+                .into_iter()
+                .map(|i| candidates[i]),
+        );
+        amounts.sort_unstable();
+        amounts
+    }
+
+    #[test]
+    fn test_strategies() {
+        let candidates = [5, 1, 20, 7, 3, 12];
+
+        assert_eq!(
+            selected_amounts(&LargestFirst, &candidates, 25),
+            vec![12, 20]
+        );
+        assert_eq!(
+            selected_amounts(&SmallestFirst, &candidates, 10),
+            vec![1, 3, 5, 7]
+        );
+        assert_eq!(selected_amounts(&MinimiseInputs, &candidates, 6), vec![7]);
+        assert_eq!(
+            selected_amounts(&MinimiseInputs, &candidates, 25),
+            vec![5, 20]
+        );
+        assert_eq!(
+            selected_amounts(&Randomised, &candidates, 48),
+            vec![1, 3, 5, 7, 12, 20]
+        );
+
+        for selector in [
+            &LargestFirst as &dyn CoinSelector,
+            &SmallestFirst,
+            &MinimiseInputs,
+            &Randomised,
+        ] {
+            assert!(selector.select(&candidates, 0).is_empty());
+        }
+    }
+
+    #[quickcheck]
+    fn prop_selection_covers_target(
+        candidates: TinyVec<TinyInt>,
+        target_quarters: TinyInt,
+    ) -> bool {
+        let candidates = Vec::from_iter(candidates.into_iter().map(TinyInt::coerce::<Amount>));
+        let total: Amount = candidates.iter().sum();
+        // Anywhere from nothing to everything.
+        let target = total * target_quarters.coerce::<Amount>() / 4;
+
+        let selectors: [&dyn CoinSelector; 4] =
+            [&LargestFirst, &SmallestFirst, &MinimiseInputs, &Randomised];
+        let largest_first_len = LargestFirst.select(&candidates, target).len();
+
+        selectors.iter().all(|selector| {
+            let selected = selector.select(&candidates, target);
+            let distinct = std::collections::BTreeSet::from_iter(selected.iter());
+            distinct.len() == selected.len()
+                && selected.iter().map(|i| candidates[*i]).sum::<Amount>() >= target
+        }) && MinimiseInputs.select(&candidates, target).len() == largest_first_len
+    }
+}
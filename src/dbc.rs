@@ -7,9 +7,10 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{
-    DbcContent, DbcContentHash, DbcTransaction, Error, Hash, KeyManager, PublicKey, Result,
-    Signature,
+    AggregatedRangeProof, AmountSecrets, DbcContent, DbcContentHash, DbcContentHashVersion,
+    DbcTransaction, Error, Hash, KeyManager, PublicKey, Result, Signature, SpendingCondition,
 };
+use blsttc::SecretKey;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
@@ -21,29 +22,141 @@ pub struct Dbc {
 }
 
 impl Dbc {
+    /// The DBC name is the hash of its content under the hash version it was issued with.
     pub fn name(&self) -> Hash {
-        self.content.hash()
+        match self.hash_version() {
+            Some(version) => self.content.hash_with_version(version),
+            None => self.content.hash(),
+        }
+    }
+
+    /// The content hash version this DBC was issued under, ie. the newest
+    /// version whose content hash appears in the transaction outputs.
+    ///
+    /// DBCs issued before content hashes committed to every field are named by
+    /// their legacy hash, this lets them be recognised, eg. to look up their spend.
+    /// A legacy hash does not commit to the amount commitment, so a mint refuses to
+    /// reissue such DBCs with Error::LegacyDbcHashVersion, unless it is migrating them
+    /// to the latest version, see Mint::record_legacy_dbcs().
+    pub fn hash_version(&self) -> Option<DbcContentHashVersion> {
+        DbcContentHashVersion::ALL.iter().copied().find(|version| {
+            self.transaction
+                .outputs
+                .contains(&self.content.hash_with_version(*version))
+        })
     }
 
     // Check there exists a DbcTransaction with the output containing this Dbc
     // Check there DOES NOT exist a DbcTransaction with this Dbc as parent (already minted)
     pub fn confirm_valid<K: KeyManager>(&self, verifier: &K) -> Result<(), Error> {
+        self.confirm_transaction_sigs(verifier)?;
+        self.confirm_transaction()
+    }
+
+    /// Verify everything a holder can check about this DBC without asking the mint.
+    ///
+    /// Unlike confirm_valid(), every check is run and the outcome of each is
+    /// returned in a DbcVerificationReport, rather than stopping at the first error.
+    /// The owner checks are only made if the owner's secret key is supplied, and
+    /// pass for the key's holder whether they own the DBC or may reclaim it as the
+    /// refund key of its time lock.  For a DBC locked by a spending condition other
+    /// than the key alone, use verify_full_as_spender().
+    /// If the DBC's amount is proven by an AggregatedRangeProof, that proof must
+    /// be supplied for the range proof check to pass.
+    pub fn verify_full<K: KeyManager>(
+        &self,
+        verifier: &K,
+        owner_key: Option<&SecretKey>,
+        aggregated_range_proof: Option<&AggregatedRangeProof>,
+    ) -> DbcVerificationReport {
+        let owner = owner_key.map(|sk| (sk, SpendingCondition::Key(sk.public_key())));
+        self.verify_full_with_owner(verifier, owner, aggregated_range_proof)
+    }
+
+    /// As verify_full(), for the holder of `owner_key` who may spend the DBC on
+    /// `spender`, eg. the recipient of a HashTimeLock, its spending condition.
+    /// The amount secrets are decrypted with `owner_key`.
+    pub fn verify_full_as_spender<K: KeyManager>(
+        &self,
+        verifier: &K,
+        owner_key: &SecretKey,
+        spender: &SpendingCondition,
+        aggregated_range_proof: Option<&AggregatedRangeProof>,
+    ) -> DbcVerificationReport {
+        self.verify_full_with_owner(
+            verifier,
+            Some((owner_key, spender.clone())),
+            aggregated_range_proof,
+        )
+    }
+
+    fn verify_full_with_owner<K: KeyManager>(
+        &self,
+        verifier: &K,
+        owner: Option<(&SecretKey, SpendingCondition)>,
+        aggregated_range_proof: Option<&AggregatedRangeProof>,
+    ) -> DbcVerificationReport {
+        let range_proof = match (&self.content.aggregated_range_proof, aggregated_range_proof) {
+            (Some(_), Some(proof)) => self.content.verify_aggregated_range_proof(proof),
+            _ => self.content.verify_range_proof(),
+        };
+
+        let commitment = self
+            .content
+            .commitment
+            .decompress()
+            .map(|_| ())
+            .ok_or(Error::AmountCommitmentInvalid);
+
+        let amount_secrets = owner.as_ref().map(|(sk, _)| {
+            let secrets = self.content.amount_secret_by_secret_key(sk)?;
+            if self
+                .content
+                .confirm_provided_amount_matches_commitment(&secrets)
+            {
+                Ok(secrets)
+            } else {
+                Err(Error::AmountSecretsDoNotMatchCommitment)
+            }
+        });
+
+        let owner = owner.map(|(_, spender)| self.content.validate_spender(&spender));
+
+        DbcVerificationReport {
+            transaction_sigs: self.confirm_transaction_sigs(verifier),
+            transaction: self.confirm_transaction(),
+            commitment,
+            range_proof,
+            owner,
+            amount_secrets,
+        }
+    }
+
+    // Check each input has a mint signature spending it by our transaction, by a known key.
+    fn confirm_transaction_sigs<K: KeyManager>(&self, verifier: &K) -> Result<(), Error> {
         for (input, (mint_key, mint_sig)) in self.transaction_sigs.iter() {
             if !self.transaction.inputs.contains(input) {
                 return Err(Error::UnknownInput);
             }
 
             verifier
-                .verify(&self.transaction.hash(), mint_key, mint_sig)
+                .verify(&self.transaction.input_sig_hash(input), mint_key, mint_sig)
                 .map_err(|e| Error::Signing(e.to_string()))?;
         }
+        if self.transaction_sigs.len() < self.transaction.inputs.len() {
+            Err(Error::MissingSignatureForInput)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Check our content is consistent with the transaction that created it.
+    fn confirm_transaction(&self) -> Result<(), Error> {
         if self.transaction.inputs.is_empty() {
             Err(Error::TransactionMustHaveAnInput)
-        } else if self.transaction_sigs.len() < self.transaction.inputs.len() {
-            Err(Error::MissingSignatureForInput)
         } else if self.transaction.inputs != self.content.parents {
             Err(Error::DbcContentParentsDifferentFromTransactionInputs)
-        } else if !self.transaction.outputs.contains(&self.content.hash()) {
+        } else if self.hash_version().is_none() {
             Err(Error::DbcContentNotPresentInTransactionOutput)
         } else {
             Ok(())
@@ -51,6 +164,50 @@ impl Dbc {
     }
 }
 
+/// The outcome of each check made by Dbc::verify_full().
+#[derive(Debug)]
+pub struct DbcVerificationReport {
+    /// Mint signatures are by known keys and cover every transaction input.
+    pub transaction_sigs: Result<()>,
+    /// Content parents match the transaction inputs and content is a transaction output.
+    pub transaction: Result<()>,
+    /// The amount commitment is a valid curve point.
+    pub commitment: Result<()>,
+    /// The range proof shows the committed amount is a u64.
+    pub range_proof: Result<()>,
+    /// The supplied owner may spend the content, as its owner or the refund key of its
+    /// time lock.  None if no owner key was supplied.
+    pub owner: Option<Result<()>>,
+    /// The decrypted AmountSecrets open the amount commitment.  None if no owner key was supplied.
+    pub amount_secrets: Option<Result<AmountSecrets>>,
+}
+
+impl DbcVerificationReport {
+    /// True if every check that was made passed.
+    pub fn is_valid(&self) -> bool {
+        self.transaction_sigs.is_ok()
+            && self.transaction.is_ok()
+            && self.commitment.is_ok()
+            && self.range_proof.is_ok()
+            && !matches!(self.owner, Some(Err(_)))
+            && !matches!(self.amount_secrets, Some(Err(_)))
+    }
+
+    /// The first failed check, in the order checks are listed in the report.
+    pub fn first_error(&self) -> Option<&Error> {
+        [
+            self.transaction_sigs.as_ref().err(),
+            self.transaction.as_ref().err(),
+            self.commitment.as_ref().err(),
+            self.range_proof.as_ref().err(),
+            self.owner.as_ref().and_then(|r| r.as_ref().err()),
+            self.amount_secrets.as_ref().and_then(|r| r.as_ref().err()),
+        ]
+        .iter()
+        .find_map(|e| *e)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,7 +224,7 @@ mod tests {
     };
 
     fn divide(amount: Amount, n_ways: u8) -> impl Iterator<Item = Amount> {
-        (0..n_ways).into_iter().map(move |i| {
+        (0..n_ways).map(move |i| {
             let equal_parts = amount / (n_ways as Amount);
             let leftover = amount % (n_ways as Amount);
 
@@ -87,16 +244,12 @@ mod tests {
         let (reissue_tx, _) = crate::TransactionBuilder::default()
             .add_input(dbc.clone(), amount_secrets)
             .add_outputs(
-                divide(amount_secrets.amount, n_ways).map(|amount| crate::Output {
-                    amount,
-                    owner: output_owner.public_key(),
-                }),
+                divide(amount_secrets.amount, n_ways)
+                    .map(|amount| crate::Output::new(amount, output_owner.public_key())),
             )
             .build()?;
 
-        let sig_share = dbc_owner
-            .secret_key_share
-            .sign(&reissue_tx.blinded().hash());
+        let sig_share = dbc_owner.secret_key_share.sign(reissue_tx.blinded().hash());
 
         let sig = dbc_owner
             .public_key_set
@@ -108,6 +261,7 @@ mod tests {
         let request = ReissueRequest {
             transaction: reissue_tx,
             input_ownership_proofs: HashMap::from_iter([(dbc.name(), (dbc_owner_key, sig))]),
+            input_spending_proofs: Default::default(),
         };
 
         Ok(request)
@@ -126,10 +280,7 @@ mod tests {
 
         let dbc = Dbc {
             content: input_content,
-            transaction: DbcTransaction {
-                inputs: BTreeSet::new(),
-                outputs: input_content_hashes,
-            },
+            transaction: DbcTransaction::new(BTreeSet::new(), input_content_hashes),
             transaction_sigs: Default::default(),
         };
 
@@ -147,6 +298,334 @@ mod tests {
         Ok(())
     }
 
+    /// A DBC content for `amount`, parented by the genesis input.
+    fn genesis_child_content(amount: Amount, owner: blsttc::PublicKey) -> Result<DbcContent> {
+        DbcContent::new(
+            BTreeSet::from_iter([crate::GENESIS_DBC_INPUT]),
+            amount,
+            owner,
+            DbcContent::random_blinding_factor(),
+        )
+    }
+
+    /// Mint `content` as a DBC whose transaction names the content with `version`.
+    fn mint_dbc_with_hash_version(
+        key_manager: &SimpleKeyManager,
+        content: DbcContent,
+        version: DbcContentHashVersion,
+    ) -> Result<Dbc, Error> {
+        let transaction = DbcTransaction::new(
+            content.parents.clone(),
+            BTreeSet::from_iter([content.hash_with_version(version)]),
+        );
+
+        let mint_key_set = key_manager.public_key_set()?;
+        let transaction_sigs = transaction
+            .inputs
+            .iter()
+            .map(|input| {
+                let sig_share = key_manager.sign(&transaction.input_sig_hash(input))?;
+                let mint_sig =
+                    mint_key_set.combine_signatures(vec![sig_share.threshold_crypto()])?;
+                Ok((*input, (mint_key_set.public_key(), mint_sig)))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Dbc {
+            content,
+            transaction_sigs,
+            transaction,
+        })
+    }
+
+    #[test]
+    fn test_dbc_named_by_legacy_hash_is_recognised() -> Result<(), Error> {
+        let id = crate::bls_dkg_id();
+        let key_manager = SimpleKeyManager::new(
+            SimpleSigner::from(id.clone()),
+            id.public_key_set.public_key(),
+        );
+
+        let owner = crate::bls_dkg_id().public_key_set.public_key();
+
+        let legacy_dbc = mint_dbc_with_hash_version(
+            &key_manager,
+            genesis_child_content(100, owner)?,
+            DbcContentHashVersion::V0,
+        )?;
+        assert_eq!(legacy_dbc.hash_version(), Some(DbcContentHashVersion::V0));
+        assert_eq!(
+            legacy_dbc.name(),
+            legacy_dbc
+                .content
+                .hash_with_version(DbcContentHashVersion::V0)
+        );
+        assert!(legacy_dbc.confirm_valid(&key_manager).is_ok());
+
+        let dbc = mint_dbc_with_hash_version(
+            &key_manager,
+            genesis_child_content(100, owner)?,
+            DbcContentHashVersion::LATEST,
+        )?;
+        assert_eq!(dbc.hash_version(), Some(DbcContentHashVersion::LATEST));
+        assert_eq!(dbc.name(), dbc.content.hash());
+        assert!(dbc.confirm_valid(&key_manager).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dbc_with_swapped_commitment_is_invalid() -> Result<(), Error> {
+        let id = crate::bls_dkg_id();
+        let key_manager = SimpleKeyManager::new(
+            SimpleSigner::from(id.clone()),
+            id.public_key_set.public_key(),
+        );
+
+        let owner = crate::bls_dkg_id().public_key_set.public_key();
+        let mut dbc = mint_dbc_with_hash_version(
+            &key_manager,
+            genesis_child_content(100, owner)?,
+            DbcContentHashVersion::LATEST,
+        )?;
+        let other = genesis_child_content(1_000_000, owner)?;
+        dbc.content.commitment = other.commitment;
+        dbc.content.range_proof_bytes = other.range_proof_bytes;
+
+        assert_eq!(dbc.hash_version(), None);
+        assert!(matches!(
+            dbc.confirm_valid(&key_manager),
+            Err(Error::DbcContentNotPresentInTransactionOutput)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mint_refuses_legacy_dbc_with_swapped_commitment() -> Result<(), Error> {
+        let id = crate::bls_dkg_id();
+        let key_manager = SimpleKeyManager::new(
+            SimpleSigner::from(id.clone()),
+            id.public_key_set.public_key(),
+        );
+        let mut mint = Mint::new(key_manager.clone(), SimpleSpendBook::new());
+
+        let owner_sk = blsttc::SecretKey::random();
+        let mut legacy_dbc = mint_dbc_with_hash_version(
+            &key_manager,
+            genesis_child_content(100, owner_sk.public_key())?,
+            DbcContentHashVersion::V0,
+        )?;
+
+        // Even once the mint has recorded the DBC it issued...
+        mint.record_legacy_dbcs([&legacy_dbc.content]);
+
+        // ...the legacy name does not cover the commitment, so the swap goes unnoticed...
+        let blinding_factor = DbcContent::random_blinding_factor();
+        let other = DbcContent::new(
+            legacy_dbc.content.parents.clone(),
+            1_000_000,
+            owner_sk.public_key(),
+            blinding_factor,
+        )?;
+        legacy_dbc.content.commitment = other.commitment;
+        legacy_dbc.content.range_proof_bytes = other.range_proof_bytes;
+        assert!(legacy_dbc.confirm_valid(&key_manager).is_ok());
+
+        // ...until the DBC is reissued.
+        let (reissue_tx, _) = crate::TransactionBuilder::default()
+            .add_input(
+                legacy_dbc.clone(),
+                AmountSecrets::new(1_000_000, blinding_factor),
+            )
+            .add_output(crate::Output::new(1_000_000, owner_sk.public_key()))
+            .build()?;
+        let reissue_req = crate::ReissueRequestBuilder::new(reissue_tx)
+            .add_signer(legacy_dbc.name(), crate::OwnerSigner::SecretKey(owner_sk))
+            .build()?;
+        assert!(matches!(
+            mint.reissue(reissue_req),
+            Err(Error::LegacyDbcHashVersion(name)) if name == legacy_dbc.name()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recorded_legacy_dbc_is_migrated_once() -> Result<(), Error> {
+        let id = crate::bls_dkg_id();
+        let key_manager = SimpleKeyManager::new(
+            SimpleSigner::from(id.clone()),
+            id.public_key_set.public_key(),
+        );
+        let mut mint = Mint::new(key_manager.clone(), SimpleSpendBook::new());
+
+        let owner_sk = blsttc::SecretKey::random();
+        let blinding_factor = DbcContent::random_blinding_factor();
+        let legacy_dbc = mint_dbc_with_hash_version(
+            &key_manager,
+            DbcContent::new(
+                BTreeSet::from_iter([crate::GENESIS_DBC_INPUT]),
+                100,
+                owner_sk.public_key(),
+                blinding_factor,
+            )?,
+            DbcContentHashVersion::V0,
+        )?;
+
+        let (reissue_tx, _) = crate::TransactionBuilder::default()
+            .add_input(legacy_dbc.clone(), AmountSecrets::new(100, blinding_factor))
+            .add_output(crate::Output::new(100, owner_sk.public_key()))
+            .build()?;
+        let reissue_req = crate::ReissueRequestBuilder::new(reissue_tx.clone())
+            .add_signer(legacy_dbc.name(), crate::OwnerSigner::SecretKey(owner_sk))
+            .build()?;
+
+        // Refused until the mint has a record of the DBC it issued...
+        assert!(matches!(
+            mint.reissue(reissue_req.clone()),
+            Err(Error::LegacyDbcHashVersion(_))
+        ));
+
+        mint.record_legacy_dbcs([&legacy_dbc.content]);
+        let share = mint.reissue(reissue_req.clone())?;
+        for output in reissue_tx.outputs.iter() {
+            assert!(share.dbc_transaction.outputs.contains(&output.hash()));
+        }
+
+        // ...and then migrated only once.
+        assert!(matches!(
+            mint.reissue(reissue_req),
+            Err(Error::DbcAlreadySpent(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_full_reports_every_check() -> Result<(), Error> {
+        let id = crate::bls_dkg_id();
+        let key_manager = SimpleKeyManager::new(
+            SimpleSigner::from(id.clone()),
+            id.public_key_set.public_key(),
+        );
+        let owner_sk = blsttc::SecretKey::random();
+
+        let dbc = mint_dbc_with_hash_version(
+            &key_manager,
+            genesis_child_content(100, owner_sk.public_key())?,
+            DbcContentHashVersion::LATEST,
+        )?;
+
+        // Without the owner key only the mint and content checks are made.
+        let report = dbc.verify_full(&key_manager, None, None);
+        assert!(report.is_valid());
+        assert!(report.owner.is_none());
+        assert!(report.amount_secrets.is_none());
+
+        // With the owner key the amount secrets are recovered and checked too.
+        let report = dbc.verify_full(&key_manager, Some(&owner_sk), None);
+        assert!(report.is_valid());
+        assert!(matches!(report.owner, Some(Ok(()))));
+        assert!(matches!(report.amount_secrets, Some(Ok(s)) if s.amount == 100));
+
+        // The wrong owner key fails both owner checks, but nothing else.
+        let report = dbc.verify_full(&key_manager, Some(&blsttc::SecretKey::random()), None);
+        assert!(!report.is_valid());
+        assert!(report.transaction_sigs.is_ok());
+        assert!(report.range_proof.is_ok());
+        assert!(matches!(report.owner, Some(Err(Error::FailedUnblinding))));
+        assert!(matches!(report.amount_secrets, Some(Err(_))));
+
+        // A corrupt range proof is reported alongside the transaction mismatch it causes.
+        let mut corrupt_dbc = dbc.clone();
+        corrupt_dbc.content.range_proof_bytes[0] ^= 0xff;
+        let report = corrupt_dbc.verify_full(&key_manager, Some(&owner_sk), None);
+        assert!(!report.is_valid());
+        assert!(report.transaction_sigs.is_ok());
+        assert!(matches!(
+            report.transaction,
+            Err(Error::DbcContentNotPresentInTransactionOutput)
+        ));
+        assert!(matches!(report.range_proof, Err(Error::RangeProof(_))));
+        assert!(matches!(report.owner, Some(Ok(()))));
+        assert!(matches!(
+            report.first_error(),
+            Some(Error::DbcContentNotPresentInTransactionOutput)
+        ));
+
+        // A hash time locked DBC is owned by its recipient, on the lock's spending
+        // condition, and may be reclaimed by its refund key.
+        let recipient_sk = blsttc::SecretKey::random();
+        let refund_sk = blsttc::SecretKey::random();
+        let lock = crate::HashTimeLock {
+            hash: SpendingCondition::preimage_hash(b"preimage"),
+            recipient: recipient_sk.public_key(),
+            refund: refund_sk.public_key(),
+            refund_after: 10,
+        };
+        let mut content = genesis_child_content(100, recipient_sk.public_key())?;
+        content.set_spending_condition(&lock.spending_condition());
+        content.set_time_lock(&lock.time_lock());
+        let locked_dbc =
+            mint_dbc_with_hash_version(&key_manager, content, DbcContentHashVersion::LATEST)?;
+
+        let report = locked_dbc.verify_full_as_spender(
+            &key_manager,
+            &recipient_sk,
+            &lock.spending_condition(),
+            None,
+        );
+        assert!(report.is_valid());
+        assert!(matches!(report.amount_secrets, Some(Ok(s)) if s.amount == 100));
+
+        let report = locked_dbc.verify_full(&key_manager, Some(&refund_sk), None);
+        assert!(matches!(report.owner, Some(Ok(()))));
+
+        // The recipient key alone does not meet the lock's spending condition.
+        let report = locked_dbc.verify_full(&key_manager, Some(&recipient_sk), None);
+        assert!(matches!(report.owner, Some(Err(Error::FailedUnblinding))));
+        assert!(matches!(report.amount_secrets, Some(Ok(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_full_detects_amount_secrets_not_matching_commitment() -> Result<(), Error> {
+        let id = crate::bls_dkg_id();
+        let key_manager = SimpleKeyManager::new(
+            SimpleSigner::from(id.clone()),
+            id.public_key_set.public_key(),
+        );
+        let owner_sk = blsttc::SecretKey::random();
+        let owner_pk = owner_sk.public_key();
+
+        // Encrypt a claimed amount which differs from the committed amount.
+        let mut content = genesis_child_content(100, owner_pk)?;
+        let secrets = content.amount_secret_by_secret_key(&owner_sk)?;
+        let fudged_secrets = AmountSecrets {
+            amount: secrets.amount * 2,
+            ..secrets
+        };
+        content.amount_secrets_cipher = owner_pk.encrypt(fudged_secrets.to_bytes().as_slice());
+
+        let dbc = mint_dbc_with_hash_version(&key_manager, content, DbcContentHashVersion::LATEST)?;
+
+        // The mint-accessible checks all pass, only the owner can detect the mismatch.
+        assert!(dbc.confirm_valid(&key_manager).is_ok());
+        assert!(dbc.verify_full(&key_manager, None, None).is_valid());
+
+        let report = dbc.verify_full(&key_manager, Some(&owner_sk), None);
+        assert!(!report.is_valid());
+        assert!(matches!(report.owner, Some(Ok(()))));
+        assert!(matches!(
+            report.amount_secrets,
+            Some(Err(Error::AmountSecretsDoNotMatchCommitment))
+        ));
+
+        Ok(())
+    }
+
     #[allow(clippy::too_many_arguments)]
     #[quickcheck]
     fn prop_dbc_validation(
@@ -193,12 +672,7 @@ mod tests {
             &input_owner.public_key_set,
         )?;
 
-        let split_reissue_share = genesis_node
-            .reissue(
-                reissue_request.clone(),
-                reissue_request.transaction.blinded().inputs,
-            )
-            .unwrap();
+        let split_reissue_share = genesis_node.reissue(reissue_request.clone()).unwrap();
 
         let mut dbc_builder = DbcBuilder::new(reissue_request.transaction);
         dbc_builder = dbc_builder.add_reissue_share(split_reissue_share);
@@ -213,15 +687,15 @@ mod tests {
 
         let (reissue_tx, _) = crate::TransactionBuilder::default()
             .add_inputs(inputs)
-            .add_output(crate::Output {
+            .add_output(crate::Output::new(
                 amount,
-                owner: crate::bls_dkg_id().public_key_set.public_key(),
-            })
+                crate::bls_dkg_id().public_key_set.public_key(),
+            ))
             .build()?;
 
         let sig_share = input_owner
             .secret_key_share
-            .sign(&reissue_tx.blinded().hash());
+            .sign(reissue_tx.blinded().hash());
 
         let input_owner_key_set = input_owner.public_key_set.clone();
         let sig = input_owner_key_set
@@ -238,14 +712,10 @@ mod tests {
         let reissue_request = ReissueRequest {
             transaction: reissue_tx,
             input_ownership_proofs,
+            input_spending_proofs: Default::default(),
         };
 
-        let reissue_share = genesis_node
-            .reissue(
-                reissue_request.clone(),
-                reissue_request.transaction.blinded().inputs,
-            )
-            .unwrap();
+        let reissue_share = genesis_node.reissue(reissue_request.clone()).unwrap();
         assert_eq!(
             reissue_request.transaction.blinded(),
             reissue_share.dbc_transaction
@@ -266,9 +736,7 @@ mod tests {
                 .skip(n_drop_parents.coerce()) // drop some parents
                 .chain(
                     // add some random parents
-                    (0..n_add_random_parents.coerce())
-                        .into_iter()
-                        .map(|_| rand::random()),
+                    (0..n_add_random_parents.coerce()).map(|_| rand::random()),
                 ),
         );
 
@@ -287,7 +755,12 @@ mod tests {
                 .mint_node_signatures
                 .iter()
                 .take(n_valid_sigs.coerce())
-                .map(|(in_hash, _)| (*in_hash, (genesis_key, mint_sig.clone()))),
+                .map(|(in_hash, (mint_key_set, mint_sig_share))| {
+                    let mint_sig = mint_key_set
+                        .combine_signatures(vec![mint_sig_share.threshold_crypto()])
+                        .unwrap();
+                    (*in_hash, (genesis_key, mint_sig))
+                }),
         );
         let mut repeating_inputs = reissue_request
             .transaction
@@ -304,7 +777,7 @@ mod tests {
                 let key_manager =
                     SimpleKeyManager::new(SimpleSigner::from(id.clone()), genesis_key);
                 let trans_sig_share = key_manager
-                    .sign(&reissue_share.dbc_transaction.hash())
+                    .sign(&reissue_share.dbc_transaction.input_sig_hash(&input.name()))
                     .unwrap();
                 let trans_sig = id
                     .public_key_set
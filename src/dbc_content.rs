@@ -11,7 +11,7 @@ use blsttc::{
     Ciphertext, DecryptionShare, PublicKey, PublicKeySet, SecretKey, SecretKeySet, SecretKeyShare,
 };
 use bulletproofs::{BulletproofGens, PedersenGens, RangeProof};
-use curve25519_dalek_ng::ristretto::CompressedRistretto;
+use curve25519_dalek_ng::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek_ng::scalar::Scalar;
 use merlin::Transcript;
 use rand8::rngs::OsRng;
@@ -19,18 +19,29 @@ use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use tiny_keccak::{Hasher, Sha3};
 
-use crate::{DbcContentHash, Error, Hash};
+use crate::asset::native_asset;
+use crate::{
+    blinded_asset_tag, AssetId, BlindedTimeLock, DbcContentHash, Epoch, Error, Hash,
+    RangeProofGens, SpendingCondition, TimeLock, NATIVE_ASSET,
+};
 
 pub(crate) const RANGE_PROOF_BITS: usize = 64; // note: Range Proof max-bits is 64. allowed are: 8, 16, 32, 64 (only)
                                                //       This limits our amount field to 64 bits also.
 pub(crate) const RANGE_PROOF_PARTIES: usize = 1; // The maximum number of parties that can produce an aggregated proof
+pub(crate) const MAX_AGGREGATED_RANGE_PROOF_PARTIES: usize = 1024; // The maximum number of values, after padding, in an AggregatedRangeProof
 pub(crate) const MERLIN_TRANSCRIPT_LABEL: &[u8] = b"SN_DBC";
+const CONTENT_HASH_DOMAIN: &[u8] = b"SN_DBC_CONTENT";
+const SPENDING_CONDITION_DOMAIN: &[u8] = b"SN_DBC_SPENDING_CONDITION";
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct BlindedOwner(Hash);
 
 const AMT_SIZE: usize = 8; // Amount size: 8 bytes (u64)
 const BF_SIZE: usize = 32; // Blinding factor size: 32 bytes (Scalar)
+const ASSET_SIZE: usize = 32; // Asset id size: 32 bytes (Hash)
+
+/// The largest memo a DbcContent may carry, in bytes.
+pub const MAX_MEMO_SIZE: usize = 256;
 
 pub type Amount = u64;
 
@@ -48,6 +59,25 @@ impl BlindedOwner {
         sha3.finalize(&mut hash);
         Self(Hash(hash))
     }
+
+    /// Blind the bytes of a SpendingCondition, see SpendingCondition::blinded_owner().
+    pub(crate) fn new_from_condition(
+        condition_bytes: &[u8],
+        parents: &BTreeSet<DbcContentHash>,
+    ) -> Self {
+        let mut sha3 = Sha3::v256();
+
+        sha3.update(SPENDING_CONDITION_DOMAIN);
+        for parent in parents.iter() {
+            sha3.update(parent);
+        }
+
+        sha3.update(condition_bytes);
+
+        let mut hash = [0; 32];
+        sha3.finalize(&mut hash);
+        Self(Hash(hash))
+    }
 }
 
 /// Contains amount and Pedersen Commitment blinding factor which
@@ -56,14 +86,66 @@ impl BlindedOwner {
 pub struct AmountSecrets {
     pub amount: Amount,
     pub blinding_factor: Scalar,
+    /// The asset the amount is of, and the factor blinding its asset tag.
+    #[serde(default = "native_asset")]
+    pub asset: AssetId,
+    #[serde(default)]
+    pub asset_blinding_factor: Scalar,
 }
 
 impl AmountSecrets {
+    /// Secrets of an amount of the native asset, which has no asset tag.
+    pub fn new(amount: Amount, blinding_factor: Scalar) -> Self {
+        Self {
+            amount,
+            blinding_factor,
+            asset: NATIVE_ASSET,
+            asset_blinding_factor: Scalar::zero(),
+        }
+    }
+
+    /// Whether this is the native asset with no asset tag.
+    pub fn is_untagged(&self) -> bool {
+        self.asset == NATIVE_ASSET && self.asset_blinding_factor == Scalar::zero()
+    }
+
+    /// The blinded asset tag, which takes the place of the value generator in the commitment.
+    pub fn asset_tag(&self) -> RistrettoPoint {
+        blinded_asset_tag(&self.asset, self.asset_blinding_factor)
+    }
+
+    /// The amount commitment, `amount·asset_tag + blinding_factor·H`.
+    pub fn commitment(&self) -> RistrettoPoint {
+        self.pedersen_gens()
+            .commit(Scalar::from(self.amount), self.blinding_factor)
+    }
+
+    /// The factor blinding the commitment once the asset tag's own blinding is
+    /// included, ie. `blinding_factor + amount·asset_blinding_factor`.  These sum to
+    /// the same over the inputs and outputs of a balanced transaction.
+    pub fn total_blinding_factor(&self) -> Scalar {
+        self.blinding_factor + Scalar::from(self.amount) * self.asset_blinding_factor
+    }
+
+    fn pedersen_gens(&self) -> PedersenGens {
+        PedersenGens {
+            B: self.asset_tag(),
+            B_blinding: PedersenGens::default().B_blinding,
+        }
+    }
+
     /// Convert to bytes
+    ///
+    /// Secrets of the native asset with no asset tag have the same bytes as before
+    /// assets, so are readable by earlier versions.
     pub fn to_bytes(self) -> Vec<u8> {
         let mut v: Vec<u8> = Default::default();
         v.extend(&self.amount.to_le_bytes());
         v.extend(&self.blinding_factor.to_bytes());
+        if !self.is_untagged() {
+            v.extend(&self.asset.0);
+            v.extend(&self.asset_blinding_factor.to_bytes());
+        }
         v
     }
 
@@ -79,15 +161,14 @@ impl AmountSecrets {
             b.copy_from_slice(&bytes[AMT_SIZE..]);
             b
         });
-        Self {
-            amount,
-            blinding_factor,
-        }
+        Self::new(amount, blinding_factor)
     }
 
     /// build AmountSecrets from byte array reference
     pub fn from_bytes_ref(bytes: &[u8]) -> Result<Self, Error> {
-        if bytes.len() != AMT_SIZE + BF_SIZE {
+        const UNTAGGED_SIZE: usize = AMT_SIZE + BF_SIZE;
+        const TAGGED_SIZE: usize = UNTAGGED_SIZE + ASSET_SIZE + BF_SIZE;
+        if bytes.len() != UNTAGGED_SIZE && bytes.len() != TAGGED_SIZE {
             return Err(Error::AmountSecretsBytesInvalid);
         }
         let amount = Amount::from_le_bytes({
@@ -97,23 +178,64 @@ impl AmountSecrets {
         });
         let blinding_factor = Scalar::from_bytes_mod_order({
             let mut b = [0u8; BF_SIZE];
-            b.copy_from_slice(&bytes[AMT_SIZE..]);
+            b.copy_from_slice(&bytes[AMT_SIZE..UNTAGGED_SIZE]);
             b
         });
-        Ok(Self {
-            amount,
-            blinding_factor,
-        })
+        let mut secrets = Self::new(amount, blinding_factor);
+
+        if bytes.len() == TAGGED_SIZE {
+            secrets.asset = Hash({
+                let mut b = [0u8; ASSET_SIZE];
+                b.copy_from_slice(&bytes[UNTAGGED_SIZE..UNTAGGED_SIZE + ASSET_SIZE]);
+                b
+            });
+            secrets.asset_blinding_factor = Scalar::from_bytes_mod_order({
+                let mut b = [0u8; BF_SIZE];
+                b.copy_from_slice(&bytes[UNTAGGED_SIZE + ASSET_SIZE..]);
+                b
+            });
+        }
+        Ok(secrets)
     }
 }
 
+/// Version of the scheme used to hash DbcContent, and hence to name a DBC.
+///
+/// V0 hashed only the parents, amount secrets ciphertext and owner.
+/// V1 additionally commits to the amount commitment and range proof.
+///
+/// Fields added to DbcContent since V1, such as an aggregated range proof or a memo,
+/// are hashed under a label of their own and only when set, so content without
+/// them hashes, and its DBC is named, exactly as V1 first did.  A change to the
+/// hash of content which V1 already names needs a new version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DbcContentHashVersion {
+    V0 = 0,
+    V1 = 1,
+}
+
+impl DbcContentHashVersion {
+    /// The version used to name all newly created DBCs.
+    pub const LATEST: Self = Self::V1;
+
+    /// All versions, newest first.
+    pub const ALL: [Self; 2] = [Self::V1, Self::V0];
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct DbcContent {
     pub parents: BTreeSet<DbcContentHash>, // Parent DBC's, acts as a nonce
     pub amount_secrets_cipher: Ciphertext,
     pub commitment: CompressedRistretto,
     pub range_proof_bytes: Vec<u8>, // RangeProof::to_bytes() -> (2 lg n + 9) 32-byte elements, where n is # of secret bits, or 64 in our case. Gives 21 32-byte elements.
+    pub aggregated_range_proof: Option<Hash>, // When set, range_proof_bytes is empty and the amount is proven by the AggregatedRangeProof with this hash.
     pub owner: BlindedOwner,
+    #[serde(default)]
+    pub asset_tag: Option<CompressedRistretto>, // The blinded asset tag, see AmountSecrets::asset_tag(). Content without one is of the native asset.
+    #[serde(default)]
+    pub memo_cipher: Option<Ciphertext>, // A memo for the owner, eg. a payment reference, encrypted to the owner key. See set_memo().
+    #[serde(default)]
+    pub time_lock: Option<BlindedTimeLock>, // When the DBC may be reissued, and by whom. See validate_spend().
 }
 
 /// Represents the content of a DBC.
@@ -124,26 +246,72 @@ impl DbcContent {
         amount: Amount,
         owner_key: PublicKey,
         blinding_factor: Scalar,
+    ) -> Result<Self, Error> {
+        Self::new_with_gens(
+            parents,
+            amount,
+            owner_key,
+            blinding_factor,
+            &RangeProofGens::shared(RANGE_PROOF_PARTIES),
+        )
+    }
+
+    /// Create a new DbcContent for signing, proving its amount with the given generators.
+    pub fn new_with_gens(
+        parents: BTreeSet<DbcContentHash>,
+        amount: Amount,
+        owner_key: PublicKey,
+        blinding_factor: Scalar,
+        gens: &RangeProofGens,
+    ) -> Result<Self, Error> {
+        Self::new_with_secrets(
+            parents,
+            owner_key,
+            AmountSecrets::new(amount, blinding_factor),
+            None,
+            &gens.pc_gens,
+            &gens.bullet_gens,
+        )
+    }
+
+    /// Create a new DbcContent for signing, of the asset in `amount_secrets` and
+    /// with its blinded asset tag.
+    pub fn new_with_asset(
+        parents: BTreeSet<DbcContentHash>,
+        owner_key: PublicKey,
+        amount_secrets: AmountSecrets,
+    ) -> Result<Self, Error> {
+        let asset_tag = amount_secrets.asset_tag().compress();
+        Self::new_with_secrets(
+            parents,
+            owner_key,
+            amount_secrets,
+            Some(asset_tag),
+            &amount_secrets.pedersen_gens(),
+            &RangeProofGens::shared(RANGE_PROOF_PARTIES).bullet_gens,
+        )
+    }
+
+    fn new_with_secrets(
+        parents: BTreeSet<DbcContentHash>,
+        owner_key: PublicKey,
+        amount_secrets: AmountSecrets,
+        asset_tag: Option<CompressedRistretto>,
+        pc_gens: &PedersenGens,
+        bullet_gens: &BulletproofGens,
     ) -> Result<Self, Error> {
         let owner = BlindedOwner::new(&owner_key, &parents);
-        let secret = amount;
 
-        let pc_gens = PedersenGens::default();
-        let bullet_gens = BulletproofGens::new(RANGE_PROOF_BITS, RANGE_PROOF_PARTIES);
         let mut prover_ts = Transcript::new(MERLIN_TRANSCRIPT_LABEL);
         let (proof, commitment) = RangeProof::prove_single(
-            &bullet_gens,
-            &pc_gens,
+            bullet_gens,
+            pc_gens,
             &mut prover_ts,
-            secret,
-            &blinding_factor,
+            amount_secrets.amount,
+            &amount_secrets.blinding_factor,
             RANGE_PROOF_BITS,
         )?;
 
-        let amount_secrets = AmountSecrets {
-            amount,
-            blinding_factor,
-        };
         let amount_secrets_cipher = owner_key.encrypt(amount_secrets.to_bytes().as_slice());
 
         Ok(DbcContent {
@@ -152,11 +320,48 @@ impl DbcContent {
             owner,
             commitment,
             range_proof_bytes: proof.to_bytes(),
+            aggregated_range_proof: None,
+            asset_tag,
+            memo_cipher: None,
+            time_lock: None,
         })
     }
 
+    /// Create a new DbcContent whose amount is proven by an AggregatedRangeProof
+    /// shared with the other outputs of its transaction, rather than its own range proof.
+    ///
+    /// The proof is referenced by `aggregated_range_proof_hash` and must cover the
+    /// commitment to `amount` and `blinding_factor`.
+    pub fn new_with_aggregated_range_proof(
+        parents: BTreeSet<DbcContentHash>,
+        amount: Amount,
+        owner_key: PublicKey,
+        blinding_factor: Scalar,
+        aggregated_range_proof_hash: Hash,
+    ) -> Self {
+        let owner = BlindedOwner::new(&owner_key, &parents);
+        let commitment = PedersenGens::default()
+            .commit(Scalar::from(amount), blinding_factor)
+            .compress();
+
+        let amount_secrets = AmountSecrets::new(amount, blinding_factor);
+        let amount_secrets_cipher = owner_key.encrypt(amount_secrets.to_bytes().as_slice());
+
+        DbcContent {
+            parents,
+            amount_secrets_cipher,
+            owner,
+            commitment,
+            range_proof_bytes: Default::default(),
+            aggregated_range_proof: Some(aggregated_range_proof_hash),
+            asset_tag: None,
+            memo_cipher: None,
+            time_lock: None,
+        }
+    }
+
     pub fn random_blinding_factor() -> Scalar {
-        let mut csprng: OsRng = OsRng::default();
+        let mut csprng: OsRng = OsRng;
         Scalar::random(&mut csprng)
     }
 
@@ -169,7 +374,88 @@ impl DbcContent {
         }
     }
 
+    /// Checks `spender` may reissue this content at some epoch, as its owner or the
+    /// refund key of its time lock.  A key spends as SpendingCondition::Key.
+    pub fn validate_spender(&self, spender: &SpendingCondition) -> Result<(), Error> {
+        let spender = spender.blinded_owner(&self.parents);
+        match self
+            .time_lock
+            .as_ref()
+            .and_then(|lock| lock.refund.as_ref())
+        {
+            Some((_, refund_owner)) if *refund_owner == spender => Ok(()),
+            _ => self.validate_blinded_owner(&spender),
+        }
+    }
+
+    /// Checks `spender` may reissue this content at `epoch`, or at the epoch unknown if
+    /// None, in which case content with a time lock may not be reissued.
+    pub fn validate_spend(
+        &self,
+        spender: &SpendingCondition,
+        epoch: Option<Epoch>,
+    ) -> Result<(), Error> {
+        let spender = spender.blinded_owner(&self.parents);
+        let time_lock = match &self.time_lock {
+            Some(time_lock) => time_lock,
+            None => return self.validate_blinded_owner(&spender),
+        };
+        let epoch = epoch.ok_or(Error::NoEpochSource)?;
+
+        if let Some(not_before) = time_lock.not_before {
+            if epoch < not_before {
+                return Err(Error::DbcTimeLocked { not_before, epoch });
+            }
+        }
+        match &time_lock.refund {
+            Some((after, refund_owner)) if epoch >= *after => {
+                if *refund_owner == spender {
+                    Ok(())
+                } else {
+                    Err(Error::FailedUnblinding)
+                }
+            }
+            _ => self.validate_blinded_owner(&spender),
+        }
+    }
+
+    fn validate_blinded_owner(&self, blinded: &BlindedOwner) -> Result<(), Error> {
+        if *blinded == self.owner {
+            Ok(())
+        } else {
+            Err(Error::FailedUnblinding)
+        }
+    }
+
+    /// Make this content spendable on `condition`, instead of by its owner key.  The
+    /// amount remains encrypted to the owner key.
+    pub fn set_spending_condition(&mut self, condition: &SpendingCondition) {
+        self.owner = condition.blinded_owner(&self.parents);
+    }
+
+    /// Set the time lock.  Like the memo, it is covered by the content's hash.
+    pub fn set_time_lock(&mut self, time_lock: &TimeLock) {
+        self.time_lock = Some(time_lock.blind(&self.parents));
+    }
+
+    /// Content hash under the latest hash version, committing to every field.
     pub fn hash(&self) -> DbcContentHash {
+        self.hash_with_version(DbcContentHashVersion::LATEST)
+    }
+
+    /// Content hash under the given hash version.
+    ///
+    /// Only needed to recognise DBCs issued under an earlier version,
+    /// new DBCs should always be named by hash().
+    pub fn hash_with_version(&self, version: DbcContentHashVersion) -> DbcContentHash {
+        match version {
+            DbcContentHashVersion::V0 => self.hash_v0(),
+            DbcContentHashVersion::V1 => self.hash_v1(),
+        }
+    }
+
+    // Legacy hash.  Does not commit to the amount commitment or range proof.
+    fn hash_v0(&self) -> DbcContentHash {
         let mut sha3 = Sha3::v256();
 
         for parent in self.parents.iter() {
@@ -184,6 +470,88 @@ impl DbcContent {
         Hash(hash)
     }
 
+    fn hash_v1(&self) -> DbcContentHash {
+        let mut sha3 = Sha3::v256();
+
+        // Domain separate from the legacy hash, and length prefix the variable
+        // sized fields so that no two distinct contents share a preimage.
+        sha3.update(CONTENT_HASH_DOMAIN);
+        sha3.update(&[DbcContentHashVersion::V1 as u8]);
+
+        sha3.update(&(self.parents.len() as u64).to_le_bytes());
+        for parent in self.parents.iter() {
+            sha3.update(parent);
+        }
+
+        let cipher_bytes = self.amount_secrets_cipher.to_bytes();
+        sha3.update(&(cipher_bytes.len() as u64).to_le_bytes());
+        sha3.update(&cipher_bytes);
+
+        sha3.update(self.commitment.as_bytes());
+
+        sha3.update(&(self.range_proof_bytes.len() as u64).to_le_bytes());
+        sha3.update(&self.range_proof_bytes);
+
+        sha3.update(&self.owner.0);
+
+        // Content with its own range proof hashes as it did before aggregated proofs.
+        if let Some(proof_hash) = &self.aggregated_range_proof {
+            sha3.update(b"aggregated_range_proof");
+            sha3.update(proof_hash);
+        }
+
+        // Likewise content of the native asset, without a tag.
+        if let Some(asset_tag) = &self.asset_tag {
+            sha3.update(b"asset_tag");
+            sha3.update(asset_tag.as_bytes());
+        }
+
+        // Likewise content without a memo.
+        if let Some(memo_cipher) = &self.memo_cipher {
+            let memo_bytes = memo_cipher.to_bytes();
+            sha3.update(b"memo");
+            sha3.update(&(memo_bytes.len() as u64).to_le_bytes());
+            sha3.update(&memo_bytes);
+        }
+
+        if let Some(time_lock) = &self.time_lock {
+            sha3.update(b"time_lock");
+            match time_lock.not_before {
+                Some(epoch) => {
+                    sha3.update(&[1]);
+                    sha3.update(&epoch.to_be_bytes());
+                }
+                None => sha3.update(&[0]),
+            }
+            match &time_lock.refund {
+                Some((after, refund_owner)) => {
+                    sha3.update(&[1]);
+                    sha3.update(&after.to_be_bytes());
+                    sha3.update(&refund_owner.0);
+                }
+                None => sha3.update(&[0]),
+            }
+        }
+
+        let mut hash = [0; 32];
+        sha3.finalize(&mut hash);
+        Hash(hash)
+    }
+
+    /// The generators of the amount commitment: the asset tag, or for content without
+    /// one the default value generator, and the blinding generator.
+    pub fn pedersen_gens(&self) -> Result<PedersenGens, Error> {
+        match &self.asset_tag {
+            None => Ok(PedersenGens::default()),
+            Some(asset_tag) => Ok(PedersenGens {
+                B: asset_tag
+                    .decompress()
+                    .ok_or(Error::AmountCommitmentInvalid)?,
+                B_blinding: PedersenGens::default().B_blinding,
+            }),
+        }
+    }
+
     /// Decrypt AmountSecrets using a SecretKey
     pub fn amount_secret_by_secret_key(
         &self,
@@ -232,16 +600,92 @@ impl DbcContent {
         AmountSecrets::from_bytes_ref(&bytes_vec)
     }
 
+    /// Set the memo, encrypted to `owner_key` which must be the owner of this content.
+    ///
+    /// The memo is covered by the content's hash, so must be set before the content
+    /// is added to a transaction.
+    pub fn set_memo(&mut self, owner_key: &PublicKey, memo: &[u8]) -> Result<(), Error> {
+        if memo.len() > MAX_MEMO_SIZE {
+            return Err(Error::MemoTooLong {
+                size: memo.len(),
+                max: MAX_MEMO_SIZE,
+            });
+        }
+        self.validate_unblinding(owner_key)?;
+        self.memo_cipher = Some(owner_key.encrypt(memo));
+        Ok(())
+    }
+
+    /// Check the memo, if any, is no larger than MAX_MEMO_SIZE.
+    pub fn validate_memo(&self) -> Result<(), Error> {
+        // The ciphertext is the memo following two curve points.
+        let overhead = blsttc::PK_SIZE + blsttc::SIG_SIZE;
+        match &self.memo_cipher {
+            Some(memo_cipher) if memo_cipher.to_bytes().len() > overhead + MAX_MEMO_SIZE => {
+                Err(Error::MemoTooLong {
+                    size: memo_cipher.to_bytes().len() - overhead,
+                    max: MAX_MEMO_SIZE,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Decrypt the memo, if any, using the owner's SecretKey
+    pub fn memo_by_secret_key(&self, secret_key: &SecretKey) -> Result<Option<Vec<u8>>, Error> {
+        self.memo_cipher
+            .as_ref()
+            .map(|memo_cipher| {
+                secret_key
+                    .decrypt(memo_cipher)
+                    .ok_or(Error::DecryptionBySecretKeyFailed)
+            })
+            .transpose()
+    }
+
+    /// Decrypt the memo, if any, using threshold+1 of the owner's SecretKeyShares
+    pub fn memo_by_secret_key_shares(
+        &self,
+        public_key_set: &PublicKeySet,
+        secret_key_shares: &BTreeMap<usize, SecretKeyShare>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let memo_cipher = match &self.memo_cipher {
+            Some(memo_cipher) => memo_cipher,
+            None => return Ok(None),
+        };
+        let mut decryption_shares: BTreeMap<usize, DecryptionShare> = Default::default();
+        for (idx, sec_share) in secret_key_shares.iter() {
+            let share = sec_share.decrypt_share_no_verify(memo_cipher);
+            decryption_shares.insert(*idx, share);
+        }
+        Ok(Some(
+            public_key_set.decrypt(&decryption_shares, memo_cipher)?,
+        ))
+    }
+
     /// Verifies range proof, ie that the committed amount is a non-negative u64.
+    ///
+    /// Content whose amount is proven by an AggregatedRangeProof must instead
+    /// be verified with verify_aggregated_range_proof().
     pub fn verify_range_proof(&self) -> Result<(), Error> {
-        let bullet_gens = BulletproofGens::new(RANGE_PROOF_BITS, RANGE_PROOF_PARTIES);
-        let pc_gens = PedersenGens::default();
+        self.verify_range_proof_with_gens(&RangeProofGens::shared(RANGE_PROOF_PARTIES))
+    }
+
+    /// Verifies range proof using the given generators.
+    pub fn verify_range_proof_with_gens(&self, gens: &RangeProofGens) -> Result<(), Error> {
+        if self.aggregated_range_proof.is_some() {
+            return Err(Error::AggregatedRangeProofNotFound);
+        }
 
         let mut verifier_ts = Transcript::new(MERLIN_TRANSCRIPT_LABEL);
         let proof = RangeProof::from_bytes(&self.range_proof_bytes)?;
+        let pc_gens = match self.asset_tag {
+            None => gens.pc_gens,
+            Some(_) => self.pedersen_gens()?,
+        };
 
         Ok(proof.verify_single(
-            &bullet_gens,
+            &gens.bullet_gens,
             &pc_gens,
             &mut verifier_ts,
             &self.commitment,
@@ -249,6 +693,13 @@ impl DbcContent {
         )?)
     }
 
+    /// Verifies this content's amount commitment is covered by `proof`, and that
+    /// `proof` is the AggregatedRangeProof this content references.
+    pub fn verify_aggregated_range_proof(&self, proof: &AggregatedRangeProof) -> Result<(), Error> {
+        proof.confirm_covers(self)?;
+        proof.verify()
+    }
+
     /// Checks if the secret (encrypted) amount matches the amount commitment.
     /// returns true if they match, false if not, or an error if decryption fails.
     pub fn confirm_amount_matches_commitment(
@@ -262,11 +713,14 @@ impl DbcContent {
     }
 
     /// Checks if the provided AmountSecrets matches the amount commitment.
-    /// note that both the amount and blinding_factor must be correct.
+    /// note that both the amount and blinding_factor must be correct, as must
+    /// the asset and its blinding factor.
     pub fn confirm_provided_amount_matches_commitment(&self, amount: &AmountSecrets) -> bool {
-        let commitment =
-            PedersenGens::default().commit(Scalar::from(amount.amount), amount.blinding_factor);
-        self.commitment == commitment.compress()
+        let asset_tag_matches = match &self.asset_tag {
+            None => amount.is_untagged(),
+            Some(asset_tag) => *asset_tag == amount.asset_tag().compress(),
+        };
+        asset_tag_matches && self.commitment == amount.commitment().compress()
     }
 
     /// Calculates the blinding factor for the next output, typically used inside a loop.
@@ -285,3 +739,239 @@ impl DbcContent {
         }
     }
 }
+
+/// A single range proof covering the amount commitments of every output of a transaction.
+///
+/// Bulletproofs can only aggregate a power of two number of values, so the
+/// commitments may be padded with commitments to zero which belong to no output.
+/// Each output references the proof by its hash, so it is stored only once.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+pub struct AggregatedRangeProof {
+    pub commitments: Vec<CompressedRistretto>,
+    pub proof_bytes: Vec<u8>,
+}
+
+impl AggregatedRangeProof {
+    /// Prove that each of `amounts`, committed to with the matching `blinding_factors`, is a u64.
+    pub fn new(amounts: &[Amount], blinding_factors: &[Scalar]) -> Result<Self, Error> {
+        let n_parties = Self::n_parties(amounts.len())?;
+        Self::new_with_gens(
+            amounts,
+            blinding_factors,
+            &RangeProofGens::shared(n_parties),
+        )
+    }
+
+    /// As new(), but using the given generators which must have capacity for the padded amounts.
+    pub fn new_with_gens(
+        amounts: &[Amount],
+        blinding_factors: &[Scalar],
+        gens: &RangeProofGens,
+    ) -> Result<Self, Error> {
+        let n_parties = Self::n_parties(amounts.len())?;
+
+        let mut values = amounts.to_vec();
+        let mut blindings = blinding_factors.to_vec();
+        values.resize(n_parties, 0);
+        blindings.resize_with(n_parties, DbcContent::random_blinding_factor);
+
+        let mut prover_ts = Transcript::new(MERLIN_TRANSCRIPT_LABEL);
+        let (proof, commitments) = RangeProof::prove_multiple(
+            &gens.bullet_gens,
+            &gens.pc_gens,
+            &mut prover_ts,
+            &values,
+            &blindings,
+            RANGE_PROOF_BITS,
+        )?;
+
+        Ok(Self {
+            commitments,
+            proof_bytes: proof.to_bytes(),
+        })
+    }
+
+    pub fn hash(&self) -> Hash {
+        let mut sha3 = Sha3::v256();
+
+        sha3.update(&(self.commitments.len() as u64).to_le_bytes());
+        for commitment in self.commitments.iter() {
+            sha3.update(commitment.as_bytes());
+        }
+        sha3.update(&self.proof_bytes);
+
+        let mut hash = [0; 32];
+        sha3.finalize(&mut hash);
+        Hash(hash)
+    }
+
+    // Number of parties, ie. values, an aggregated proof over `n_values` values is padded to.
+    // Bounded so that an untrusted proof can not make us build (and cache) huge generators.
+    fn n_parties(n_values: usize) -> Result<usize, Error> {
+        let n_parties = n_values.next_power_of_two();
+        if n_parties > MAX_AGGREGATED_RANGE_PROOF_PARTIES {
+            return Err(Error::RangeProof(
+                bulletproofs::ProofError::InvalidAggregation,
+            ));
+        }
+        Ok(n_parties)
+    }
+
+    /// Checks `content` references this proof, and that the proof covers its commitment.
+    pub(crate) fn confirm_covers(&self, content: &DbcContent) -> Result<(), Error> {
+        if content.aggregated_range_proof != Some(self.hash()) {
+            Err(Error::AggregatedRangeProofNotFound)
+        } else if content.asset_tag.is_some() || !self.commitments.contains(&content.commitment) {
+            // Aggregated proofs are made with the default generators, so prove no asset tag.
+            Err(Error::AggregatedRangeProofDoesNotCoverOutput)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Verifies every committed amount is a non-negative u64.
+    pub fn verify(&self) -> Result<(), Error> {
+        let n_parties = Self::n_parties(self.commitments.len())?;
+        self.verify_with_gens(&RangeProofGens::shared(n_parties))
+    }
+
+    /// Verifies every committed amount is a non-negative u64, using the given generators.
+    pub fn verify_with_gens(&self, gens: &RangeProofGens) -> Result<(), Error> {
+        let mut verifier_ts = Transcript::new(MERLIN_TRANSCRIPT_LABEL);
+        let proof = RangeProof::from_bytes(&self.proof_bytes)?;
+
+        Ok(proof.verify_multiple(
+            &gens.bullet_gens,
+            &gens.pc_gens,
+            &mut verifier_ts,
+            &self.commitments,
+            RANGE_PROOF_BITS,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::iter::FromIterator;
+
+    #[test]
+    fn test_hash_commits_to_commitment_and_range_proof() -> Result<(), Error> {
+        let owner = SecretKey::random().public_key();
+        let parents = BTreeSet::from_iter([rand::random()]);
+
+        let content = DbcContent::new(
+            parents.clone(),
+            100,
+            owner,
+            DbcContent::random_blinding_factor(),
+        )?;
+        let other = DbcContent::new(parents, 200, owner, DbcContent::random_blinding_factor())?;
+
+        let mut swapped = content.clone();
+        swapped.commitment = other.commitment;
+        swapped.range_proof_bytes = other.range_proof_bytes;
+
+        // The legacy hash can not tell the two apart, the latest hash can.
+        assert_eq!(
+            swapped.hash_with_version(DbcContentHashVersion::V0),
+            content.hash_with_version(DbcContentHashVersion::V0)
+        );
+        assert_ne!(swapped.hash(), content.hash());
+        assert_eq!(
+            content.hash(),
+            content.hash_with_version(DbcContentHashVersion::LATEST)
+        );
+        assert_ne!(
+            content.hash_with_version(DbcContentHashVersion::V0),
+            content.hash_with_version(DbcContentHashVersion::V1)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_v1_hash_of_content_without_later_fields_is_unchanged() -> Result<(), Error> {
+        let content = DbcContent::new(
+            BTreeSet::from_iter([rand::random()]),
+            100,
+            SecretKey::random().public_key(),
+            DbcContent::random_blinding_factor(),
+        )?;
+
+        // The V1 hash as first released, before any optional fields were added.
+        let mut sha3 = Sha3::v256();
+        sha3.update(CONTENT_HASH_DOMAIN);
+        sha3.update(&[1]);
+        sha3.update(&(content.parents.len() as u64).to_le_bytes());
+        for parent in content.parents.iter() {
+            sha3.update(parent);
+        }
+        let cipher_bytes = content.amount_secrets_cipher.to_bytes();
+        sha3.update(&(cipher_bytes.len() as u64).to_le_bytes());
+        sha3.update(&cipher_bytes);
+        sha3.update(content.commitment.as_bytes());
+        sha3.update(&(content.range_proof_bytes.len() as u64).to_le_bytes());
+        sha3.update(&content.range_proof_bytes);
+        sha3.update(&content.owner.0);
+        let mut hash = [0; 32];
+        sha3.finalize(&mut hash);
+
+        assert_eq!(
+            content.hash_with_version(DbcContentHashVersion::V1),
+            Hash(hash)
+        );
+
+        let mut aggregated = content.clone();
+        aggregated.range_proof_bytes = Default::default();
+        aggregated.aggregated_range_proof = Some(Hash(rand::random()));
+        assert_ne!(aggregated.hash(), content.hash());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memo_is_encrypted_to_owner_and_hashed() -> Result<(), Error> {
+        let owner_sk = SecretKey::random();
+        let owner = owner_sk.public_key();
+        let mut content = DbcContent::new(
+            BTreeSet::from_iter([rand::random()]),
+            100,
+            owner,
+            DbcContent::random_blinding_factor(),
+        )?;
+        let unmemoed_hash = content.hash();
+        assert_eq!(content.memo_by_secret_key(&owner_sk)?, None);
+
+        // Only the owner's key may set the memo, and only up to MAX_MEMO_SIZE bytes.
+        assert!(matches!(
+            content.set_memo(&SecretKey::random().public_key(), b"invoice 42"),
+            Err(Error::FailedUnblinding)
+        ));
+        assert!(matches!(
+            content.set_memo(&owner, &[0; MAX_MEMO_SIZE + 1]),
+            Err(Error::MemoTooLong { .. })
+        ));
+
+        content.set_memo(&owner, b"invoice 42")?;
+        assert_eq!(
+            content.memo_by_secret_key(&owner_sk)?,
+            Some(b"invoice 42".to_vec())
+        );
+        content.validate_memo()?;
+        let memo_hash = content.hash();
+        assert_ne!(memo_hash, unmemoed_hash);
+
+        content.set_memo(&owner, b"invoice 43")?;
+        assert_ne!(content.hash(), memo_hash);
+
+        // A memo set around set_memo() is still limited.
+        content.memo_cipher = Some(owner.encrypt([0; MAX_MEMO_SIZE + 1]));
+        assert!(matches!(
+            content.validate_memo(),
+            Err(Error::MemoTooLong { size, max: MAX_MEMO_SIZE }) if size == MAX_MEMO_SIZE + 1
+        ));
+
+        Ok(())
+    }
+}
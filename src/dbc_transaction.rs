@@ -6,11 +6,13 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{DbcContentHash, Hash};
+use crate::{Amount, DbcContentHash, Hash};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
 use tiny_keccak::{Hasher, Sha3};
 
+const INPUT_SIG_DOMAIN: &[u8] = b"SN_DBC_TRANSACTION_INPUT";
+
 /// The spent identifier of the outputs created from this input
 /// Note these are hashes and not identifiers as the Dbc is not addressable on the network.
 /// i.e. a Dbc can be stored anywhere, even offline.
@@ -18,11 +20,19 @@ use tiny_keccak::{Hasher, Sha3};
 pub struct DbcTransaction {
     pub inputs: BTreeSet<DbcContentHash>,
     pub outputs: BTreeSet<DbcContentHash>,
+    /// The amount burned by the transaction, public unlike the amounts of the inputs and
+    /// outputs.  No output pays it to the mint, it leaves the supply of DBCs.
+    #[serde(default)]
+    pub fee: Amount,
 }
 
 impl DbcTransaction {
     pub fn new(inputs: BTreeSet<DbcContentHash>, outputs: BTreeSet<DbcContentHash>) -> Self {
-        Self { inputs, outputs }
+        Self {
+            inputs,
+            outputs,
+            fee: 0,
+        }
     }
 
     pub fn hash(&self) -> Hash {
@@ -35,6 +45,26 @@ impl DbcTransaction {
             sha3.update(output);
         }
 
+        // Transactions without a fee hash as they did before fees.
+        if self.fee != 0 {
+            sha3.update(b"fee");
+            sha3.update(&self.fee.to_be_bytes());
+        }
+
+        let mut hash = [0; 32];
+        sha3.finalize(&mut hash);
+        Hash(hash)
+    }
+
+    /// The message a mint signs to spend `input` by this transaction.
+    ///
+    /// It names the input, so that the signature of the mint whose prefix holds one
+    /// input cannot be passed off as the signature spending an input of another prefix.
+    pub fn input_sig_hash(&self, input: &DbcContentHash) -> Hash {
+        let mut sha3 = Sha3::v256();
+        sha3.update(INPUT_SIG_DOMAIN);
+        sha3.update(input);
+        sha3.update(&self.hash());
         let mut hash = [0; 32];
         sha3.finalize(&mut hash);
         Hash(hash)
@@ -76,4 +106,17 @@ mod tests {
 
         assert_eq!(forward_hash, reverse_hash);
     }
+
+    #[quickcheck]
+    fn prop_hash_covers_fee(inputs: Vec<u64>, fee: u64) -> bool {
+        let input_hashes = inputs
+            .iter()
+            .map(|i| Hash(sha3_256(&i.to_be_bytes())))
+            .collect();
+        let mut transaction = DbcTransaction::new(input_hashes, Default::default());
+        let no_fee_hash = transaction.hash();
+        transaction.fee = fee;
+
+        (transaction.hash() == no_fee_hash) == (fee == 0)
+    }
 }
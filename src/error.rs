@@ -5,7 +5,6 @@
 // under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
-use std::collections::BTreeMap;
 use std::io;
 use thiserror::Error;
 
@@ -23,28 +22,59 @@ pub enum Error {
     InvalidOperation(String),
     #[error("This input has a signature, but it doesn't appear in the transaction")]
     UnknownInput,
-    #[error("Filtered input doesn't appear in the transaction")]
-    FilteredInputNotPresent,
+    #[error("No input of the transaction is in this mint's prefix")]
+    NoInputInMintPrefix,
+    #[error("None of the given prefixes cover input {0:?}")]
+    NoPrefixForInput(crate::DbcContentHash),
     #[error("Failed signature check.")]
     FailedSignature,
     #[error("Unrecognised authority.")]
     UnrecognisedAuthority,
+    #[error("Key rotation is not signed by the previous key.")]
+    InvalidKeyRotation,
+    #[error("Key chain does not extend the known key chain.")]
+    KeyChainMismatch,
     #[error("At least one transaction input is missing a signature.")]
     MissingSignatureForInput,
     #[error("At least one input is missing an ownership proof")]
     MissingInputOwnerProof,
+    #[error("Signature shares for input {0:?} are from different owner key sets")]
+    InputOwnerKeySetMismatch(crate::DbcContentHash),
+    #[error("The partially signed reissues are for different transactions")]
+    PartiallySignedReissueMismatch,
+    #[error("Insufficient funds: {available} available, {required} required")]
+    InsufficientFunds {
+        available: crate::Amount,
+        required: crate::Amount,
+    },
+    #[error("The sum of the amounts overflows")]
+    AmountOverflow,
     #[error("Mint request doesn't balance out sum(input) == sum(output)")]
     DbcReissueRequestDoesNotBalance,
+    #[error("Transaction burns a fee of {fee}, below the mint's minimum fee of {required}")]
+    FeeTooLow {
+        fee: crate::Amount,
+        required: crate::Amount,
+    },
     #[error("Failed to unblind an input DBC")]
     FailedUnblinding,
-    #[error("DBC already spent in transaction: {transaction:?}")]
-    DbcAlreadySpent {
-        transaction: crate::DbcTransaction,
-        transaction_sigs:
-            BTreeMap<crate::DbcContentHash, (crate::PublicKeySet, crate::NodeSignature)>,
-    },
+    #[error("DBC already spent in transaction: {:?}", .0.transaction)]
+    DbcAlreadySpent(Box<crate::SpentProofShare>),
+    #[error("No spent proof shares")]
+    NoSpentProofShares,
+    #[error("Spent proof shares differ in DBC, transaction or PublicKeySet")]
+    SpentProofShareMismatch,
+    #[error("The spent proof transaction does not spend the DBC")]
+    SpentProofInputNotInTransaction,
     #[error("Genesis Input has already been spent in a different transaction")]
     GenesisInputAlreadySpent,
+    #[error("Genesis DBC proposal does not spend the genesis input to its content")]
+    InvalidGenesisProposal,
+    #[error("Genesis DBC proposal is for {proposed}, expected {expected}")]
+    GenesisAmountMismatch {
+        expected: crate::Amount,
+        proposed: crate::Amount,
+    },
     #[error("This node is not a genesis node")]
     NotGenesisNode,
     #[error("The DBC transaction must have at least one input")]
@@ -53,6 +83,8 @@ pub enum Error {
     DbcContentNotPresentInTransactionOutput,
     #[error("Dbc Content parents is not the same transaction inputs")]
     DbcContentParentsDifferentFromTransactionInputs,
+    #[error("DBC {0:?} is named by a legacy content hash, which does not commit to its amount")]
+    LegacyDbcHashVersion(crate::DbcContentHash),
 
     #[error("The PublicKeySet differs between ReissueShare entries")]
     ReissueSharePublicKeySetMismatch,
@@ -72,6 +104,17 @@ pub enum Error {
     #[error("No reissue transaction")]
     NoReissueTransaction,
 
+    #[error("Only {valid} valid reissue shares, at least {required} are needed. Misbehaving mint nodes: {misbehaving_nodes:?}, forged shares: {forged_shares}")]
+    NotEnoughValidReissueShares {
+        valid: usize,
+        required: usize,
+        misbehaving_nodes: std::collections::BTreeSet<u64>,
+        forged_shares: usize,
+    },
+
+    #[error("Only {responding} mint nodes responded, at least {required} are needed")]
+    NotEnoughMintNodes { responding: usize, required: usize },
+
     #[error("RangeProof error: {0}")]
     RangeProof(#[from] bulletproofs::ProofError),
 
@@ -87,6 +130,49 @@ pub enum Error {
     #[error("Invalid Amount Commitment")]
     AmountCommitmentInvalid,
 
+    #[error("Excess commitment is not proven to commit to an amount of zero")]
+    InvalidExcessCommitment,
+
+    #[error("AmountSecrets do not match the Amount Commitment")]
+    AmountSecretsDoNotMatchCommitment,
+
+    #[error("DBC {0:?} is not an output of the payment receipt's transaction")]
+    DbcNotInPaymentReceipt(crate::DbcContentHash),
+
+    #[error("The aggregated range proof referenced by a DBC output was not found")]
+    AggregatedRangeProofNotFound,
+
+    #[error("The aggregated range proof does not cover the DBC output commitment")]
+    AggregatedRangeProofDoesNotCoverOutput,
+
+    #[error("DBC output {0:?} has no valid proof that its asset is one of the inputs'")]
+    InvalidAssetSurjectionProof(crate::DbcContentHash),
+
+    #[error("No input is of the asset {0:?} of an output")]
+    AssetNotInInputs(crate::AssetId),
+
+    #[error("Memo of {size} bytes exceeds the maximum of {max} bytes")]
+    MemoTooLong { size: usize, max: usize },
+
+    #[error("DBC is time locked until epoch {not_before}, the mint's epoch is {epoch}")]
+    DbcTimeLocked {
+        not_before: crate::Epoch,
+        epoch: crate::Epoch,
+    },
+
+    #[error("The mint has no epoch source to check time locked DBCs against")]
+    NoEpochSource,
+
+    #[error("The spending proof does not meet its spending condition")]
+    SpendingConditionNotMet,
+    #[error("Spending condition is nested too deep or has too many conditions")]
+    SpendingConditionTooLarge,
+    #[error("Hash lock preimage is larger than MAX_PREIMAGE_SIZE")]
+    PreimageTooLarge,
+
+    #[error("DBC is not locked by the expected hash time lock")]
+    HashTimeLockMismatch,
+
     /// I/O error.
     #[error("I/O error: {0}")]
     Io(#[from] io::Error),
@@ -95,6 +181,10 @@ pub enum Error {
     JsonSerialisation(#[from] serde_json::Error),
     #[error("SpendBook error {0}")]
     SpendBook(String),
+    #[error("SpendBook log is corrupted at offset {0}")]
+    SpendBookCorrupted(u64),
+    #[error("SpendBook log {0:?} is already open in another FileSpendBook")]
+    SpendBookLocked(std::path::PathBuf),
 
     #[error("Infallible.  Can never fail")]
     Infallible(#[from] std::convert::Infallible),
@@ -0,0 +1,621 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+// A SpendBook that persists every logged spend to an append-only file.
+//
+// Each record on disk is laid out as:
+//
+//   [payload len: u32 LE][sha3-256 of payload: 32 bytes][payload: JSON]
+//
+// where the payload is the list of spends logged together in one batch, or if
+// the spends revealed any hash lock preimages, an object of the spends and the
+// preimages.  Every call to log() or log_batch() appends exactly one record and fsyncs
+// before returning, so a spend is only acknowledged once it is durable, and
+// the checksum ensures a batch is replayed either in full or not at all.
+//
+// On open the log is replayed into memory.  A record at the tail of the log
+// which is incomplete or fails its checksum is the signature of a write torn
+// by a crash, it is discarded and the file truncated back to the last good
+// record.  A bad record followed by any good one, even one which a corrupt
+// length field makes appear to lie inside the bad record, means the log itself
+// is corrupt and open() fails, rather than discarding good records.
+//
+// The log is locked exclusively while open, so that no two spendbooks, in this
+// process or another, append to it at once.  A FileSpendBook is deliberately not
+// Clone: a clone would hold its own copy of the spends, and could accept a spend
+// of a DBC which the other copy has already logged.
+
+use crate::{DbcContentHash, DbcTransaction, Error, Hash, Result, SpendBook};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tiny_keccak::{Hasher, Sha3};
+
+const LEN_SIZE: usize = 4; // Payload length: 4 bytes (u32)
+const CHECKSUM_SIZE: usize = 32; // Payload checksum: 32 bytes (sha3-256)
+const HEADER_SIZE: usize = LEN_SIZE + CHECKSUM_SIZE;
+
+type Spends = Vec<(DbcContentHash, DbcTransaction)>;
+
+// The payload of a record.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Record {
+    Revealing {
+        spends: Spends,
+        preimages: Vec<(Hash, Vec<u8>)>,
+    },
+    // As every record was before preimages were logged.
+    Spends(Spends),
+}
+
+#[derive(Debug)]
+pub struct FileSpendBook {
+    path: PathBuf,
+    // The log, held open for appending and locked for as long as the spendbook is alive.
+    file: File,
+    transactions: BTreeMap<DbcContentHash, DbcTransaction>,
+    preimages: BTreeMap<Hash, Vec<u8>>,
+    discarded_tail_bytes: u64,
+}
+
+impl FileSpendBook {
+    /// Open the spendbook log at `path`, creating it if it does not exist,
+    /// and replay all records found in it.
+    ///
+    /// Fails with Error::SpendBookLocked if the log is already open, by this
+    /// process or another.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let created = !path.exists();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        if file.try_lock_exclusive().is_err() {
+            return Err(Error::SpendBookLocked(path));
+        }
+        if created {
+            Self::sync_created(&file, &path)?;
+        }
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut spendbook = Self {
+            path,
+            file,
+            transactions: Default::default(),
+            preimages: Default::default(),
+            discarded_tail_bytes: 0,
+        };
+        let valid_len = spendbook.replay(&bytes)?;
+
+        spendbook.discarded_tail_bytes = (bytes.len() - valid_len) as u64;
+        if spendbook.discarded_tail_bytes > 0 {
+            spendbook.file.set_len(valid_len as u64)?;
+            spendbook.file.sync_all()?;
+        }
+
+        Ok(spendbook)
+    }
+
+    /// Path of the underlying log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of bytes of torn or corrupt tail record discarded when the log was opened.
+    pub fn discarded_tail_bytes(&self) -> u64 {
+        self.discarded_tail_bytes
+    }
+
+    fn sync_created(file: &File, path: &Path) -> Result<()> {
+        file.sync_all()?;
+
+        // fsync the parent directory so the new directory entry is itself durable.
+        #[cfg(unix)]
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Replays the log into this spendbook, returning the length of the valid prefix.
+    fn replay(&mut self, bytes: &[u8]) -> Result<usize> {
+        let mut offset = 0;
+        // Offset of the first bad record, from which on the log is scanned a byte at
+        // a time to check no good record follows it.
+        let mut torn = None;
+
+        while offset < bytes.len() {
+            match Self::record_at(&bytes[offset..]) {
+                Some((payload, record_len)) => {
+                    // A torn write is the last write, so no good record follows it.
+                    if let Some(torn) = torn {
+                        return Err(Error::SpendBookCorrupted(torn as u64));
+                    }
+                    let record: Record = serde_json::from_slice(payload)
+                        .map_err(|_| Error::SpendBookCorrupted(offset as u64))?;
+                    self.apply(record);
+                    offset += record_len;
+                }
+                None => {
+                    torn.get_or_insert(offset);
+                    offset += 1;
+                }
+            }
+        }
+
+        Ok(torn.unwrap_or(offset))
+    }
+
+    /// The payload and length of the complete record, passing its checksum, at the
+    /// start of `bytes`, if there is one.
+    fn record_at(bytes: &[u8]) -> Option<(&[u8], usize)> {
+        if bytes.len() < HEADER_SIZE {
+            return None;
+        }
+        let mut len = [0u8; LEN_SIZE];
+        len.copy_from_slice(&bytes[..LEN_SIZE]);
+        let record_len = HEADER_SIZE + u32::from_le_bytes(len) as usize;
+        if bytes.len() < record_len {
+            return None;
+        }
+        let checksum = &bytes[LEN_SIZE..HEADER_SIZE];
+        let payload = &bytes[HEADER_SIZE..record_len];
+        if checksum != sha3_256(payload) {
+            return None;
+        }
+        Some((payload, record_len))
+    }
+
+    fn apply(&mut self, record: Record) {
+        let (spends, preimages) = match record {
+            Record::Revealing { spends, preimages } => (spends, preimages),
+            Record::Spends(spends) => (spends, vec![]),
+        };
+        self.transactions.extend(spends);
+        for (hash, preimage) in preimages {
+            self.preimages.entry(hash).or_insert(preimage);
+        }
+    }
+
+    #[cfg(test)]
+    fn encode_record(spends: &[(DbcContentHash, DbcTransaction)]) -> Result<Vec<u8>> {
+        Self::encode(&Record::Spends(spends.to_vec()))
+    }
+
+    fn encode(record: &Record) -> Result<Vec<u8>> {
+        let payload = serde_json::to_vec(record)?;
+
+        let mut record = Vec::with_capacity(HEADER_SIZE + payload.len());
+        record.extend(&(payload.len() as u32).to_le_bytes());
+        record.extend(&sha3_256(&payload));
+        record.extend(&payload);
+        Ok(record)
+    }
+
+    // Swaps the log for a read only handle, so that appends fail as they would on
+    // a full disk, or back again.
+    #[cfg(test)]
+    pub(crate) fn set_read_only(&mut self, read_only: bool) -> Result<()> {
+        self.file = OpenOptions::new()
+            .read(true)
+            .append(!read_only)
+            .open(&self.path)?;
+        self.file.try_lock_exclusive()?;
+        Ok(())
+    }
+
+    fn append(&self, record: &[u8]) -> Result<()> {
+        self.append_with(record, |file, record| {
+            file.write_all(record)?;
+            file.sync_data()
+        })
+    }
+
+    // Appends `record` with `write`.  If that fails part way, the partial record is
+    // cut off again, so that the next record appended follows the last good one.
+    fn append_with(
+        &self,
+        record: &[u8],
+        write: impl FnOnce(&mut &File, &[u8]) -> io::Result<()>,
+    ) -> Result<()> {
+        let mut file = &self.file;
+        let len = file.metadata()?.len();
+        if let Err(e) = write(&mut file, record) {
+            file.set_len(len)?;
+            file.sync_all()?;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+impl SpendBook for FileSpendBook {
+    type Error = Error;
+
+    fn lookup(&self, dbc_hash: &DbcContentHash) -> Result<Option<&DbcTransaction>> {
+        Ok(self.transactions.get(dbc_hash))
+    }
+
+    fn log(&mut self, dbc_hash: DbcContentHash, transaction: DbcTransaction) -> Result<()> {
+        self.log_batch(vec![(dbc_hash, transaction)])
+    }
+
+    fn log_batch_with_preimages(
+        &mut self,
+        spends: Vec<(DbcContentHash, DbcTransaction)>,
+        preimages: BTreeMap<Hash, Vec<u8>>,
+    ) -> Result<()> {
+        let record = if preimages.is_empty() {
+            Record::Spends(spends)
+        } else {
+            Record::Revealing {
+                spends,
+                preimages: preimages.into_iter().collect(),
+            }
+        };
+
+        // The spends are only recorded in memory once they are durable on disk.
+        self.append(&Self::encode(&record)?)?;
+        self.apply(record);
+        Ok(())
+    }
+
+    fn lookup_preimage(&self, hash: &Hash) -> Result<Option<&[u8]>> {
+        Ok(self.preimages.get(hash).map(Vec::as_slice))
+    }
+}
+
+impl<'a> IntoIterator for &'a FileSpendBook {
+    type Item = (&'a DbcContentHash, &'a DbcTransaction);
+    type IntoIter = std::collections::btree_map::Iter<'a, DbcContentHash, DbcTransaction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.transactions.iter()
+    }
+}
+
+fn sha3_256(input: &[u8]) -> [u8; 32] {
+    let mut sha3 = Sha3::v256();
+    let mut output = [0; 32];
+    sha3.update(input);
+    sha3.finalize(&mut output);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::iter::FromIterator;
+
+    use crate::{Mint, SimpleKeyManager, SimpleSigner};
+
+    fn tmp_log_path() -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "sn_dbc_spendbook_{}.log",
+            hex::encode(rand::random::<[u8; 16]>())
+        ))
+    }
+
+    fn random_spend() -> (DbcContentHash, DbcTransaction) {
+        let input = rand::random();
+        let transaction = DbcTransaction::new(
+            BTreeSet::from_iter([input]),
+            BTreeSet::from_iter([rand::random(), rand::random()]),
+        );
+        (input, transaction)
+    }
+
+    #[test]
+    fn test_spends_are_replayed_on_open() -> Result<()> {
+        let path = tmp_log_path();
+        let spends = Vec::from_iter((0..5).map(|_| random_spend()));
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        for (dbc_hash, tx) in spends.iter() {
+            spendbook.log(*dbc_hash, tx.clone())?;
+        }
+        drop(spendbook);
+
+        let spendbook = FileSpendBook::open(&path)?;
+        assert_eq!(spendbook.discarded_tail_bytes(), 0);
+        for (dbc_hash, tx) in spends.iter() {
+            assert_eq!(spendbook.lookup(dbc_hash)?, Some(tx));
+        }
+        assert_eq!(spendbook.into_iter().count(), spends.len());
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_is_locked_while_open() -> Result<()> {
+        let path = tmp_log_path();
+        let (dbc_hash, tx) = random_spend();
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        assert!(matches!(
+            FileSpendBook::open(&path),
+            Err(Error::SpendBookLocked(locked)) if locked == path
+        ));
+
+        // The lock is held until the spendbook is dropped.
+        spendbook.log(dbc_hash, tx.clone())?;
+        assert!(matches!(
+            FileSpendBook::open(&path),
+            Err(Error::SpendBookLocked(_))
+        ));
+        drop(spendbook);
+
+        let spendbook = FileSpendBook::open(&path)?;
+        assert_eq!(spendbook.lookup(&dbc_hash)?, Some(&tx));
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_torn_tail_record_is_discarded() -> Result<()> {
+        let path = tmp_log_path();
+        let (first_hash, first_tx) = random_spend();
+        let (torn_hash, torn_tx) = random_spend();
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        spendbook.log(first_hash, first_tx.clone())?;
+        let good_len = fs::metadata(&path)?.len();
+
+        // Simulate a crash part way through writing the second record.
+        let record = FileSpendBook::encode_record(&[(torn_hash, torn_tx.clone())])?;
+        spendbook.append(&record[..record.len() / 2])?;
+        drop(spendbook);
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        assert_eq!(spendbook.discarded_tail_bytes(), (record.len() / 2) as u64);
+        assert_eq!(fs::metadata(&path)?.len(), good_len);
+        assert_eq!(spendbook.lookup(&first_hash)?, Some(&first_tx));
+        assert_eq!(spendbook.lookup(&torn_hash)?, None);
+
+        // The log must remain appendable after recovery.
+        spendbook.log(torn_hash, torn_tx.clone())?;
+        drop(spendbook);
+        let spendbook = FileSpendBook::open(&path)?;
+        assert_eq!(spendbook.discarded_tail_bytes(), 0);
+        assert_eq!(spendbook.lookup(&torn_hash)?, Some(&torn_tx));
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_torn_batch_is_discarded_in_full() -> Result<()> {
+        let path = tmp_log_path();
+        let batch = Vec::from_iter((0..5).map(|_| random_spend()));
+
+        let spendbook = FileSpendBook::open(&path)?;
+        let record = FileSpendBook::encode_record(&batch)?;
+
+        // Simulate a crash after most, but not all, of the batch reached the disk.
+        spendbook.append(&record[..record.len() - 1])?;
+        drop(spendbook);
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        assert_eq!(spendbook.discarded_tail_bytes(), (record.len() - 1) as u64);
+        for (dbc_hash, _) in batch.iter() {
+            assert_eq!(spendbook.lookup(dbc_hash)?, None);
+        }
+
+        spendbook.log_batch(batch.clone())?;
+        drop(spendbook);
+        let spendbook = FileSpendBook::open(&path)?;
+        for (dbc_hash, tx) in batch.iter() {
+            assert_eq!(spendbook.lookup(dbc_hash)?, Some(tx));
+        }
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_append_is_cut_off() -> Result<()> {
+        let path = tmp_log_path();
+        let (first_hash, first_tx) = random_spend();
+        let (failed_hash, failed_tx) = random_spend();
+        let (next_hash, next_tx) = random_spend();
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        spendbook.log(first_hash, first_tx.clone())?;
+        let good_len = fs::metadata(&path)?.len();
+
+        // Simulate the disk filling up part way through writing the second record.
+        let record = FileSpendBook::encode_record(&[(failed_hash, failed_tx)])?;
+        let result = spendbook.append_with(&record, |file, record| {
+            file.write_all(&record[..record.len() / 2])?;
+            Err(io::Error::other("no space left on device"))
+        });
+        assert!(matches!(result, Err(Error::Io(_))));
+        assert_eq!(fs::metadata(&path)?.len(), good_len);
+
+        // So a later record follows the last good one, and the log still opens.
+        spendbook.log(next_hash, next_tx.clone())?;
+        drop(spendbook);
+        let spendbook = FileSpendBook::open(&path)?;
+        assert_eq!(spendbook.discarded_tail_bytes(), 0);
+        assert_eq!(spendbook.lookup(&first_hash)?, Some(&first_tx));
+        assert_eq!(spendbook.lookup(&failed_hash)?, None);
+        assert_eq!(spendbook.lookup(&next_hash)?, Some(&next_tx));
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_batch_is_not_logged() -> Result<()> {
+        let path = tmp_log_path();
+        let (first_hash, first_tx) = random_spend();
+        let batch = Vec::from_iter((0..3).map(|_| random_spend()));
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        spendbook.log(first_hash, first_tx.clone())?;
+        let good_len = fs::metadata(&path)?.len();
+
+        spendbook.set_read_only(true)?;
+        assert!(matches!(
+            spendbook.log_batch(batch.clone()),
+            Err(Error::Io(_))
+        ));
+        assert_eq!(fs::metadata(&path)?.len(), good_len);
+        assert_eq!(spendbook.into_iter().count(), 1);
+        for (dbc_hash, _) in batch.iter() {
+            assert_eq!(spendbook.lookup(dbc_hash)?, None);
+        }
+        drop(spendbook);
+
+        let spendbook = FileSpendBook::open(&path)?;
+        assert_eq!(spendbook.discarded_tail_bytes(), 0);
+        assert_eq!(spendbook.lookup(&first_hash)?, Some(&first_tx));
+        assert_eq!(spendbook.into_iter().count(), 1);
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_tail_checksum_is_discarded() -> Result<()> {
+        let path = tmp_log_path();
+        let (first_hash, first_tx) = random_spend();
+        let (corrupt_hash, corrupt_tx) = random_spend();
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        spendbook.log(first_hash, first_tx.clone())?;
+        spendbook.log(corrupt_hash, corrupt_tx)?;
+        drop(spendbook);
+
+        let mut bytes = fs::read(&path)?;
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, &bytes)?;
+
+        let spendbook = FileSpendBook::open(&path)?;
+        assert!(spendbook.discarded_tail_bytes() > 0);
+        assert_eq!(spendbook.lookup(&first_hash)?, Some(&first_tx));
+        assert_eq!(spendbook.lookup(&corrupt_hash)?, None);
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_record_before_tail_is_an_error() -> Result<()> {
+        let path = tmp_log_path();
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        for (dbc_hash, tx) in (0..3).map(|_| random_spend()) {
+            spendbook.log(dbc_hash, tx)?;
+        }
+        drop(spendbook);
+
+        let mut bytes = fs::read(&path)?;
+        bytes[HEADER_SIZE] ^= 0xff; // first byte of the first payload
+        fs::write(&path, &bytes)?;
+
+        assert!(matches!(
+            FileSpendBook::open(&path),
+            Err(Error::SpendBookCorrupted(0))
+        ));
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_preimages_are_replayed_and_never_replaced() -> Result<()> {
+        let path = tmp_log_path();
+        let hash: Hash = rand::random();
+        let (spent_hash, spent_tx) = random_spend();
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        spendbook.log_batch_with_preimages(
+            vec![(spent_hash, spent_tx.clone())],
+            BTreeMap::from_iter([(hash, b"first".to_vec())]),
+        )?;
+        spendbook.log_batch_with_preimages(
+            vec![random_spend()],
+            BTreeMap::from_iter([(hash, b"second".to_vec())]),
+        )?;
+        assert_eq!(spendbook.lookup_preimage(&hash)?, Some(&b"first"[..]));
+        drop(spendbook);
+
+        let spendbook = FileSpendBook::open(&path)?;
+        assert_eq!(spendbook.lookup(&spent_hash)?, Some(&spent_tx));
+        assert_eq!(spendbook.lookup_preimage(&hash)?, Some(&b"first"[..]));
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_length_before_tail_is_an_error() -> Result<()> {
+        let path = tmp_log_path();
+        let spends = Vec::from_iter((0..3).map(|_| random_spend()));
+
+        let mut spendbook = FileSpendBook::open(&path)?;
+        for (dbc_hash, tx) in spends.iter() {
+            spendbook.log(*dbc_hash, tx.clone())?;
+        }
+        drop(spendbook);
+
+        // A length running past the end of the log makes the first record look torn,
+        // but the records after it are good, so they must not be discarded.
+        let mut bytes = fs::read(&path)?;
+        bytes[..LEN_SIZE].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &bytes)?;
+
+        assert!(matches!(
+            FileSpendBook::open(&path),
+            Err(Error::SpendBookCorrupted(0))
+        ));
+        assert_eq!(fs::read(&path)?, bytes);
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_genesis_spend_survives_restart() -> Result<()> {
+        let path = tmp_log_path();
+        let genesis_owner = crate::bls_dkg_id();
+        let key_manager = SimpleKeyManager::new(
+            SimpleSigner::from(genesis_owner.clone()),
+            genesis_owner.public_key_set.public_key(),
+        );
+
+        let mut genesis_node = Mint::new(key_manager.clone(), FileSpendBook::open(&path)?);
+        genesis_node.issue_genesis_dbc(1000)?;
+        drop(genesis_node);
+
+        // A restarted mint must not issue a second, different, genesis DBC.
+        let mut restarted_node = Mint::new(key_manager, FileSpendBook::open(&path)?);
+        assert!(restarted_node.is_spent(crate::GENESIS_DBC_INPUT)?);
+        assert!(matches!(
+            restarted_node.issue_genesis_dbc(1000),
+            Err(Error::GenesisInputAlreadySpent)
+        ));
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
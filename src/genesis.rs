@@ -0,0 +1,289 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::iter::FromIterator;
+
+use crate::{
+    asset_issuance_input, builder::robust_transaction_sigs, Amount, AmountSecrets, AssetId, Dbc,
+    DbcContent, DbcTransaction, Error, NodeSignature, PublicKey, PublicKeySet, ReissueShare,
+    Result, NATIVE_ASSET,
+};
+
+/// The genesis DBC, as proposed by one party to every node of a mint.
+///
+/// Each node validates the proposal with Mint::sign_genesis_dbc(), logs its
+/// transaction as spending the genesis input and signs it.  Since every node signs
+/// the same content and transaction, the signature shares combine into the genesis
+/// Dbc, and the genesis spendbook entry is identical on every node.
+///
+/// The genesis DBC is owned by the mint's key.  Its amount is public, so the
+/// AmountSecrets are disclosed for nodes to check the amount commitment against.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GenesisDbcProposal {
+    pub content: DbcContent,
+    pub transaction: DbcTransaction,
+    pub amount_secrets: AmountSecrets,
+}
+
+impl GenesisDbcProposal {
+    /// Propose a genesis DBC of `amount`, owned by `mint_key`.
+    pub fn new(amount: Amount, mint_key: PublicKey) -> Result<Self> {
+        Self::new_asset(NATIVE_ASSET, amount, mint_key)
+    }
+
+    /// Propose issuing `amount` of `asset`, owned by `mint_key`.
+    ///
+    /// The issuance spends the asset's own pseudo input, see asset_issuance_input(),
+    /// so the genesis DBC is the issuance of the native asset.  Other assets' DBCs
+    /// have an unblinded asset tag, as the asset issued is public anyway.
+    pub fn new_asset(asset: AssetId, amount: Amount, mint_key: PublicKey) -> Result<Self> {
+        let issuance_input = BTreeSet::from_iter([asset_issuance_input(&asset)]);
+        let amount_secrets = AmountSecrets {
+            asset,
+            ..AmountSecrets::new(amount, DbcContent::random_blinding_factor())
+        };
+        let content = if asset == NATIVE_ASSET {
+            DbcContent::new(
+                issuance_input.clone(),
+                amount,
+                mint_key,
+                amount_secrets.blinding_factor,
+            )?
+        } else {
+            DbcContent::new_with_asset(issuance_input.clone(), mint_key, amount_secrets)?
+        };
+        let transaction =
+            DbcTransaction::new(issuance_input, BTreeSet::from_iter([content.hash()]));
+
+        Ok(Self {
+            content,
+            transaction,
+            amount_secrets,
+        })
+    }
+
+    /// Check the proposal is for a genesis DBC of `amount`, owned by `mint_key`.
+    ///
+    /// Nodes cannot decrypt the content's amount secrets on their own, so a proposer
+    /// can make the genesis DBC unspendable, but cannot change its amount or owner,
+    /// nor add a time lock, whose refund key could spend it, or a memo.
+    pub fn validate(&self, amount: Amount, mint_key: &PublicKey) -> Result<()> {
+        self.validate_asset(NATIVE_ASSET, amount, mint_key)
+    }
+
+    /// Check the proposal is to issue `amount` of `asset`, owned by `mint_key`.
+    pub fn validate_asset(
+        &self,
+        asset: AssetId,
+        amount: Amount,
+        mint_key: &PublicKey,
+    ) -> Result<()> {
+        let genesis_input = BTreeSet::from_iter([asset_issuance_input(&asset)]);
+        if self.content.parents != genesis_input
+            || self.transaction.inputs != genesis_input
+            || self.transaction.outputs != BTreeSet::from_iter([self.content.hash()])
+            || !self.content.amount_secrets_cipher.verify()
+            || self.content.time_lock.is_some()
+            || self.content.memo_cipher.is_some()
+            || self.amount_secrets.asset != asset
+        {
+            return Err(Error::InvalidGenesisProposal);
+        }
+
+        self.content.validate_unblinding(mint_key)?;
+
+        if self.amount_secrets.amount != amount {
+            return Err(Error::GenesisAmountMismatch {
+                expected: amount,
+                proposed: self.amount_secrets.amount,
+            });
+        }
+        if !self
+            .content
+            .confirm_provided_amount_matches_commitment(&self.amount_secrets)
+        {
+            return Err(Error::AmountSecretsDoNotMatchCommitment);
+        }
+
+        self.content.verify_range_proof()
+    }
+
+    /// Combine the nodes' signature shares over the proposal into the genesis Dbc.
+    ///
+    /// Shares which do not verify are discarded as in DbcBuilder::build_robust(), so
+    /// any threshold + 1 good shares give the genesis Dbc.
+    pub fn into_dbc(
+        self,
+        mint_public_key_set: &PublicKeySet,
+        sig_shares: impl IntoIterator<Item = NodeSignature>,
+    ) -> Result<Dbc> {
+        let reissue_shares = Vec::from_iter(sig_shares.into_iter().map(|sig| {
+            ReissueShare {
+                dbc_transaction: self.transaction.clone(),
+                mint_node_signatures: self
+                    .transaction
+                    .inputs
+                    .iter()
+                    .map(|input| (*input, (mint_public_key_set.clone(), sig.clone())))
+                    .collect(),
+            }
+        }));
+        let (transaction_sigs, _misbehaving_nodes, _forged_shares) =
+            robust_transaction_sigs(&self.transaction, &reissue_shares, mint_public_key_set)?;
+
+        Ok(Dbc {
+            content: self.content,
+            transaction: self.transaction,
+            transaction_sigs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Mint, SimpleKeyManager, SimpleSigner, SimpleSpendBook, SpendBook, TimeLock,
+        GENESIS_DBC_INPUT,
+    };
+    use blsttc::SecretKeySet;
+
+    fn mint_nodes(
+        secret_key_set: &SecretKeySet,
+        n_nodes: u64,
+    ) -> Vec<Mint<SimpleKeyManager, SimpleSpendBook>> {
+        let public_key_set = secret_key_set.public_keys();
+        Vec::from_iter((0..n_nodes).map(|i| {
+            let signer = SimpleSigner::new(
+                public_key_set.clone(),
+                (i, secret_key_set.secret_key_share(i)),
+            );
+            let key_manager = SimpleKeyManager::new(signer, public_key_set.public_key());
+            Mint::new(key_manager, SimpleSpendBook::new())
+        }))
+    }
+
+    #[test]
+    fn test_genesis_ceremony() -> Result<()> {
+        let secret_key_set = SecretKeySet::random(2, &mut rand::thread_rng());
+        let public_key_set = secret_key_set.public_keys();
+        let mut nodes = mint_nodes(&secret_key_set, 4);
+
+        let proposal = GenesisDbcProposal::new(1000, public_key_set.public_key())?;
+        let sig_shares = nodes
+            .iter_mut()
+            .map(|node| Ok(node.sign_genesis_dbc(1000, &proposal)?.1))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Every node has logged the same genesis spend.
+        for node in nodes.iter() {
+            assert_eq!(
+                node.spendbook.lookup(&GENESIS_DBC_INPUT)?,
+                Some(&proposal.transaction)
+            );
+        }
+
+        // Any threshold + 1 shares give the genesis DBC.
+        let genesis_dbc = proposal
+            .clone()
+            .into_dbc(&public_key_set, sig_shares[1..].iter().cloned())?;
+        for node in nodes.iter() {
+            genesis_dbc.confirm_valid(node.key_manager())?;
+        }
+        assert_eq!(
+            genesis_dbc
+                .content
+                .amount_secrets_by_secret_key_set(&secret_key_set)?,
+            proposal.amount_secrets
+        );
+
+        // Too few shares do not.
+        assert!(proposal
+            .clone()
+            .into_dbc(&public_key_set, sig_shares[2..].iter().cloned())
+            .is_err());
+
+        // Signing again is harmless, but no node signs a second genesis DBC.
+        nodes[0].sign_genesis_dbc(1000, &proposal)?;
+        let other_proposal = GenesisDbcProposal::new(1000, public_key_set.public_key())?;
+        assert!(matches!(
+            nodes[0].sign_genesis_dbc(1000, &other_proposal),
+            Err(Error::GenesisInputAlreadySpent)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_genesis_proposals_are_not_signed() -> Result<()> {
+        let secret_key_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let mint_key = secret_key_set.public_keys().public_key();
+        let mut node = mint_nodes(&secret_key_set, 1).remove(0);
+
+        let proposal = GenesisDbcProposal::new(1000, mint_key)?;
+        assert!(matches!(
+            node.sign_genesis_dbc(2000, &proposal),
+            Err(Error::GenesisAmountMismatch {
+                expected: 2000,
+                proposed: 1000
+            })
+        ));
+
+        let mut understated = proposal.clone();
+        understated.amount_secrets.amount = 2000;
+        assert!(matches!(
+            node.sign_genesis_dbc(2000, &understated),
+            Err(Error::AmountSecretsDoNotMatchCommitment)
+        ));
+
+        let stranger_key = SecretKeySet::random(0, &mut rand::thread_rng())
+            .public_keys()
+            .public_key();
+        assert!(matches!(
+            node.sign_genesis_dbc(1000, &GenesisDbcProposal::new(1000, stranger_key)?),
+            Err(Error::FailedUnblinding)
+        ));
+
+        let mut other_output = proposal.clone();
+        other_output.transaction.outputs = BTreeSet::from_iter([rand::random::<[u8; 32]>().into()]);
+        assert!(matches!(
+            node.sign_genesis_dbc(1000, &other_output),
+            Err(Error::InvalidGenesisProposal)
+        ));
+
+        // Nor may the proposer add a time lock refunding the genesis DBC to itself,
+        // or a memo, even with the transaction naming the changed content.
+        let proposer_key = crate::bls_dkg_id().public_key_set.public_key();
+        let mut refundable = proposal.clone();
+        refundable.content.set_time_lock(&TimeLock {
+            not_before: None,
+            refund: Some((0, proposer_key)),
+        });
+        refundable.transaction.outputs = BTreeSet::from_iter([refundable.content.hash()]);
+        assert!(matches!(
+            node.sign_genesis_dbc(1000, &refundable),
+            Err(Error::InvalidGenesisProposal)
+        ));
+
+        let mut with_memo = proposal.clone();
+        with_memo.content.set_memo(&mint_key, b"genesis")?;
+        with_memo.transaction.outputs = BTreeSet::from_iter([with_memo.content.hash()]);
+        assert!(matches!(
+            node.sign_genesis_dbc(1000, &with_memo),
+            Err(Error::InvalidGenesisProposal)
+        ));
+
+        // None of the rejected proposals spent the genesis input.
+        assert!(!node.is_spent(GENESIS_DBC_INPUT)?);
+        node.sign_genesis_dbc(1000, &proposal)?;
+
+        Ok(())
+    }
+}
@@ -11,6 +11,9 @@ use blsttc::{serde_impl::SerdeSecret, SecretKeyShare, SignatureShare};
 pub use blsttc::{PublicKey, PublicKeySet, Signature};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use tiny_keccak::{Hasher, Sha3};
+
+const KEY_ROTATION_DOMAIN: &[u8] = b"SN_DBC_KEY_ROTATION";
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Deserialize, Serialize)]
 pub struct NodeSignature {
@@ -41,6 +44,108 @@ pub trait KeyManager {
     fn verify_known_key(&self, key: &PublicKey) -> Result<(), Self::Error>;
 }
 
+/// The history of a mint's section keys, from the genesis key onwards.
+///
+/// Each key after genesis is appended together with a signature over it by the
+/// key before it, so every key in a valid chain is provably descended from genesis.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyChain {
+    genesis_key: PublicKey,
+    rotations: Vec<(PublicKey, Signature)>,
+}
+
+impl KeyChain {
+    pub fn new(genesis_key: PublicKey) -> Self {
+        Self {
+            genesis_key,
+            rotations: Vec::new(),
+        }
+    }
+
+    /// The message `prev_key` must sign to rotate to `new_key`, as the key at `index`
+    /// of the chain, the genesis key being at index 0.
+    ///
+    /// It names the previous key and the index, so that a rotation signature cannot be
+    /// replayed into another chain, nor into another place in this one.
+    pub fn rotation_hash(prev_key: &PublicKey, index: u64, new_key: &PublicKey) -> Hash {
+        let mut sha3 = Sha3::v256();
+        sha3.update(KEY_ROTATION_DOMAIN);
+        sha3.update(&prev_key.to_bytes());
+        sha3.update(&index.to_le_bytes());
+        sha3.update(&new_key.to_bytes());
+
+        let mut hash = [0; 32];
+        sha3.finalize(&mut hash);
+        Hash(hash)
+    }
+
+    /// The message the current last key must sign to rotate to `new_key`.
+    pub fn next_rotation_hash(&self, new_key: &PublicKey) -> Hash {
+        Self::rotation_hash(self.last_key(), self.rotations.len() as u64 + 1, new_key)
+    }
+
+    pub fn genesis_key(&self) -> &PublicKey {
+        &self.genesis_key
+    }
+
+    /// The most recent key, ie. the current section key.
+    pub fn last_key(&self) -> &PublicKey {
+        self.rotations
+            .last()
+            .map(|(key, _)| key)
+            .unwrap_or(&self.genesis_key)
+    }
+
+    /// Every key in the chain, oldest first.
+    pub fn keys(&self) -> impl Iterator<Item = &PublicKey> {
+        std::iter::once(&self.genesis_key).chain(self.rotations.iter().map(|(key, _)| key))
+    }
+
+    pub fn contains(&self, key: &PublicKey) -> bool {
+        self.keys().any(|k| k == key)
+    }
+
+    /// Appends `new_key`, which must be signed by the last key, see next_rotation_hash().
+    pub fn rotate(&mut self, new_key: PublicKey, signature: Signature) -> Result<()> {
+        if !self
+            .last_key()
+            .verify(&signature, self.next_rotation_hash(&new_key))
+        {
+            return Err(Error::InvalidKeyRotation);
+        }
+        self.rotations.push((new_key, signature));
+        Ok(())
+    }
+
+    /// Checks every rotation is signed by the key before it.
+    /// Chains received from elsewhere should be verified before being trusted.
+    pub fn verify(&self) -> Result<()> {
+        let mut chain = Self::new(self.genesis_key);
+        for (key, signature) in self.rotations.iter() {
+            chain.rotate(*key, signature.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Adopts `other` if it is a valid chain which extends this one, eg. a chain
+    /// learnt from a mint which has seen more churn than us.
+    pub fn update(&mut self, other: KeyChain) -> Result<()> {
+        if other.genesis_key != self.genesis_key {
+            return Err(Error::KeyChainMismatch);
+        }
+        if self.rotations.starts_with(&other.rotations) {
+            // Nothing we don't already know.
+            return Ok(());
+        }
+        if !other.rotations.starts_with(&self.rotations) {
+            return Err(Error::KeyChainMismatch);
+        }
+        other.verify()?;
+        *self = other;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimpleSigner {
     public_key_set: PublicKeySet,
@@ -81,7 +186,7 @@ impl SimpleSigner {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimpleKeyManager {
     signer: SimpleSigner,
-    genesis_key: PublicKey,
+    key_chain: KeyChain,
     cache: Keys,
 }
 
@@ -93,10 +198,29 @@ impl SimpleKeyManager {
         cache.add_known_key(public_key_set.public_key());
         Self {
             signer,
-            genesis_key,
+            key_chain: KeyChain::new(genesis_key),
             cache,
         }
     }
+
+    pub fn key_chain(&self) -> &KeyChain {
+        &self.key_chain
+    }
+
+    /// Switch to signing with `signer` after churn.  `signature` is the signature
+    /// of the current section key over KeyChain::next_rotation_hash() of the new key.
+    pub fn rotate(&mut self, signer: SimpleSigner, signature: Signature) -> Result<()> {
+        let new_key = signer.public_key_set().public_key();
+        self.key_chain.rotate(new_key, signature)?;
+        self.cache.add_known_key(new_key);
+        self.signer = signer;
+        Ok(())
+    }
+
+    /// Learn of key rotations from a chain which extends ours.
+    pub fn update_key_chain(&mut self, key_chain: KeyChain) -> Result<()> {
+        self.key_chain.update(key_chain)
+    }
 }
 
 impl KeyManager for SimpleKeyManager {
@@ -114,11 +238,20 @@ impl KeyManager for SimpleKeyManager {
     }
 
     fn verify(&self, msg_hash: &Hash, key: &PublicKey, signature: &Signature) -> Result<()> {
-        self.cache.verify(msg_hash, key, signature)
+        self.verify_known_key(key)?;
+        if key.verify(signature, msg_hash) {
+            Ok(())
+        } else {
+            Err(Error::FailedSignature)
+        }
     }
 
     fn verify_known_key(&self, key: &PublicKey) -> Result<()> {
-        self.cache.verify_known_key(key)
+        if self.key_chain.contains(key) {
+            Ok(())
+        } else {
+            self.cache.verify_known_key(key)
+        }
     }
 }
 
@@ -136,15 +269,6 @@ impl Keys {
         self.0.insert(key);
     }
 
-    fn verify(&self, msg: &Hash, key: &PublicKey, sig: &Signature) -> Result<()> {
-        self.verify_known_key(key)?;
-        if key.verify(sig, msg) {
-            Ok(())
-        } else {
-            Err(Error::FailedSignature)
-        }
-    }
-
     fn verify_known_key(&self, key: &PublicKey) -> Result<()> {
         if self.0.contains(key) {
             Ok(())
@@ -153,3 +277,114 @@ impl Keys {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blsttc::SecretKey;
+
+    fn rotate(chain: &mut KeyChain, signer: &SecretKey) -> Result<SecretKey> {
+        let new_key = SecretKey::random();
+        let sig = signer.sign(chain.next_rotation_hash(&new_key.public_key()));
+        chain.rotate(new_key.public_key(), sig)?;
+        Ok(new_key)
+    }
+
+    #[test]
+    fn test_key_chain_rotations_must_be_signed_by_previous_key() -> Result<()> {
+        let genesis = SecretKey::random();
+        let mut chain = KeyChain::new(genesis.public_key());
+
+        let second = rotate(&mut chain, &genesis)?;
+        assert_eq!(chain.last_key(), &second.public_key());
+
+        // Genesis is no longer the last key, so may not rotate the chain.
+        assert!(matches!(
+            rotate(&mut chain, &genesis),
+            Err(Error::InvalidKeyRotation)
+        ));
+
+        let third = rotate(&mut chain, &second)?;
+        assert!(chain.verify().is_ok());
+        assert_eq!(
+            chain.keys().collect::<Vec<_>>(),
+            vec![
+                &genesis.public_key(),
+                &second.public_key(),
+                &third.public_key()
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_chain_update_only_accepts_valid_extensions() -> Result<()> {
+        let genesis = SecretKey::random();
+        let mut chain = KeyChain::new(genesis.public_key());
+        let second = rotate(&mut chain, &genesis)?;
+
+        let mut longer = chain.clone();
+        rotate(&mut longer, &second)?;
+
+        let mut fork = chain.clone();
+        rotate(&mut fork, &second)?;
+
+        // A chain we already know of changes nothing.
+        let mut known = longer.clone();
+        known.update(chain.clone())?;
+        assert_eq!(known, longer);
+
+        chain.update(longer.clone())?;
+        assert_eq!(chain, longer);
+
+        assert!(matches!(chain.update(fork), Err(Error::KeyChainMismatch)));
+        assert!(matches!(
+            chain.update(KeyChain::new(SecretKey::random().public_key())),
+            Err(Error::KeyChainMismatch)
+        ));
+
+        // A chain whose rotation is not signed by the key before it is rejected.
+        let mut forged = longer.clone();
+        let forged_key = SecretKey::random().public_key();
+        let forged_sig = SecretKey::random().sign(longer.next_rotation_hash(&forged_key));
+        forged.rotations.push((forged_key, forged_sig));
+        assert!(matches!(
+            chain.update(forged),
+            Err(Error::InvalidKeyRotation)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_chain_rotation_signature_is_bound_to_its_place() -> Result<()> {
+        let genesis = SecretKey::random();
+        let second = SecretKey::random();
+        let mut chain = KeyChain::new(genesis.public_key());
+        let sig = genesis.sign(chain.next_rotation_hash(&second.public_key()));
+        chain.rotate(second.public_key(), sig.clone())?;
+
+        // The section returns to its genesis key...
+        let back = second.sign(chain.next_rotation_hash(&genesis.public_key()));
+        chain.rotate(genesis.public_key(), back)?;
+
+        // ...but the genesis key's signature rotates only the first key of the chain.
+        assert!(matches!(
+            chain.rotate(second.public_key(), sig.clone()),
+            Err(Error::InvalidKeyRotation)
+        ));
+
+        // Nor another chain, in which the genesis key is the last key but not the first.
+        let other_genesis = SecretKey::random();
+        let mut other = KeyChain::new(other_genesis.public_key());
+        let to_genesis = other_genesis.sign(other.next_rotation_hash(&genesis.public_key()));
+        other.rotate(genesis.public_key(), to_genesis)?;
+        assert!(matches!(
+            other.rotate(second.public_key(), sig),
+            Err(Error::InvalidKeyRotation)
+        ));
+
+        Ok(())
+    }
+}
@@ -16,29 +16,67 @@ use tiny_keccak::{Hasher, Sha3};
 #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Hash([u8; 32]);
 pub(crate) type DbcContentHash = Hash;
+mod asset;
+mod atomic_swap;
 mod builder;
+mod coin_selection;
 mod dbc;
 mod dbc_content;
 mod dbc_transaction;
 mod error;
+mod file_spendbook;
+mod genesis;
 mod key_manager;
 mod mint;
+mod mint_cluster;
+mod parallel;
+mod partially_signed_reissue;
+mod payment_receipt;
+mod range_proof_gens;
+mod spending_condition;
+mod spent_proof;
+mod time_lock;
+mod transaction_contribution;
 
 pub use crate::{
-    builder::{DbcBuilder, Output, TransactionBuilder},
-    dbc::Dbc,
-    dbc_content::{Amount, AmountSecrets, BlindedOwner, DbcContent},
+    asset::{
+        asset_generator, asset_issuance_input, blinded_asset_tag, AssetId, AssetSurjectionProof,
+        NATIVE_ASSET,
+    },
+    atomic_swap::HashTimeLock,
+    builder::{DbcBuilder, Output, OwnerSigner, ReissueRequestBuilder, TransactionBuilder},
+    coin_selection::{CoinSelector, LargestFirst, MinimiseInputs, Randomised, SmallestFirst},
+    dbc::{Dbc, DbcVerificationReport},
+    dbc_content::{
+        AggregatedRangeProof, Amount, AmountSecrets, BlindedOwner, DbcContent,
+        DbcContentHashVersion, MAX_MEMO_SIZE,
+    },
     dbc_transaction::DbcTransaction,
     error::{Error, Result},
+    file_spendbook::FileSpendBook,
+    genesis::GenesisDbcProposal,
     key_manager::{
-        KeyManager, NodeSignature, PublicKey, PublicKeySet, Signature, SimpleKeyManager,
+        KeyChain, KeyManager, NodeSignature, PublicKey, PublicKeySet, Signature, SimpleKeyManager,
         SimpleSigner,
     },
     mint::{
         Mint, MintNodeSignatures, ReissueRequest, ReissueShare, ReissueTransaction,
         SimpleSpendBook, SpendBook, GENESIS_DBC_INPUT,
     },
+    mint_cluster::{MintCluster, MintNodeState},
+    parallel::MaybeSync,
+    partially_signed_reissue::PartiallySignedReissue,
+    payment_receipt::PaymentReceipt,
+    range_proof_gens::RangeProofGens,
+    spending_condition::{
+        SpendingCondition, SpendingProof, MAX_PREIMAGE_SIZE, MAX_SPENDING_CONDITIONS,
+        MAX_SPENDING_CONDITION_DEPTH,
+    },
+    spent_proof::{SpentProof, SpentProofShare},
+    time_lock::{BlindedTimeLock, Epoch, EpochSource, SimpleEpochSource, TimeLock},
+    transaction_contribution::{ExcessCommitment, TransactionContribution},
 };
+pub use xor_name::{Prefix, XorName};
 
 impl From<[u8; 32]> for Hash {
     fn from(val: [u8; 32]) -> Hash {
@@ -46,6 +84,13 @@ impl From<[u8; 32]> for Hash {
     }
 }
 
+// DBCs are placed in the xor name space by their name.
+impl From<Hash> for XorName {
+    fn from(hash: Hash) -> XorName {
+        XorName(hash.0)
+    }
+}
+
 // Display Hash value as hex in Debug output.  consolidates 36 lines to 3 for pretty output
 // and the hex value is the same as sn_dbc_mint display of DBC IDs.
 impl fmt::Debug for Hash {
@@ -129,6 +174,17 @@ impl DbcHelper {
     ) -> Result<Amount, Error> {
         Ok(Self::decrypt_amount_secrets(owner, dbcc)?.amount)
     }
+
+    pub fn decrypt_memo(
+        owner: &bls_dkg::outcome::Outcome,
+        dbcc: &DbcContent,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let mut shares: std::collections::BTreeMap<usize, bls_dkg::SecretKeyShare> =
+            Default::default();
+        shares.insert(owner.index, owner.secret_key_share.clone());
+
+        dbcc.memo_by_secret_key_shares(&owner.public_key_set, &shares)
+    }
 }
 
 #[cfg(test)]
@@ -145,6 +201,72 @@ mod tests {
     use super::*;
     use core::num::NonZeroU8;
     use quickcheck::{Arbitrary, Gen};
+    use std::iter::FromIterator;
+
+    // A transaction spending `dbc`, whose AmountSecrets are `secrets`, in full to `owner`.
+    pub fn spend_to(
+        dbc: &Dbc,
+        secrets: AmountSecrets,
+        owner: PublicKey,
+    ) -> Result<ReissueTransaction> {
+        let (transaction, _) = TransactionBuilder::default()
+            .add_input(dbc.clone(), secrets)
+            .add_output(Output::new(secrets.amount, owner))
+            .build()?;
+        Ok(transaction)
+    }
+
+    // DBCs of `amounts` owned by `owner`, split from the genesis DBC issued by
+    // `cluster`, with their AmountSecrets.
+    pub fn split_genesis<S: SpendBook>(
+        cluster: &mut MintCluster<S>,
+        amounts: &[Amount],
+        owner: PublicKey,
+    ) -> Result<Vec<(Dbc, AmountSecrets)>> {
+        let genesis = cluster.issue_genesis_dbc(amounts.iter().sum())?;
+        let genesis_name = genesis.name();
+        let genesis_secrets = cluster.decrypt_amount_secrets(&genesis.content)?;
+        let (transaction, receipt) = TransactionBuilder::default()
+            .add_input(genesis, genesis_secrets)
+            .add_outputs(amounts.iter().map(|amount| Output::new(*amount, owner)))
+            .build()?;
+        let reissue_req = ReissueRequestBuilder::new(transaction)
+            .add_signer(
+                genesis_name,
+                OwnerSigner::SecretKeySet(cluster.secret_key_set().clone()),
+            )
+            .build()?;
+        Ok(cluster
+            .reissue(reissue_req)?
+            .into_iter()
+            .map(|dbc| {
+                let secrets = receipt.output_amount_secrets[&dbc.name()];
+                (dbc, secrets)
+            })
+            .collect())
+    }
+
+    // A request spending `inputs`, each owned by `owner`, in full to `recipient`.
+    pub fn merge_request(
+        inputs: impl IntoIterator<Item = (Dbc, AmountSecrets)>,
+        owner: &blsttc::SecretKey,
+        recipient: PublicKey,
+    ) -> Result<ReissueRequest> {
+        let inputs = Vec::from_iter(inputs);
+        let amount = inputs.iter().map(|(_, secrets)| secrets.amount).sum();
+        let names = Vec::from_iter(inputs.iter().map(|(dbc, _)| dbc.name()));
+        let (transaction, _) = TransactionBuilder::default()
+            .add_inputs(inputs)
+            .add_output(Output::new(amount, recipient))
+            .build()?;
+        ReissueRequestBuilder::new(transaction)
+            .add_signers(
+                names
+                    .into_iter()
+                    .map(|name| (name, OwnerSigner::SecretKey(owner.clone()))),
+            )
+            .build()
+    }
 
     #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
     pub struct TinyInt(u8);
@@ -167,7 +289,7 @@ mod tests {
         }
 
         fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
-            Box::new((0..(self.0)).into_iter().rev().map(Self))
+            Box::new((0..(self.0)).rev().map(Self))
         }
     }
 
@@ -196,7 +318,6 @@ mod tests {
         fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
             Box::new(
                 (1..(self.0.get()))
-                    .into_iter()
                     .rev()
                     .filter_map(NonZeroU8::new)
                     .map(Self),
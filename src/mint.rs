@@ -14,21 +14,26 @@
 // Outputs <= input value
 
 use crate::{
-    Amount, Dbc, DbcContent, DbcContentHash, DbcTransaction, Error, Hash, KeyManager,
-    NodeSignature, PublicKeySet, Result,
+    asset_issuance_input, parallel, spent_proof, AggregatedRangeProof, Amount, AssetId,
+    AssetSurjectionProof, Dbc, DbcContent, DbcContentHash, DbcContentHashVersion, DbcTransaction,
+    Epoch, EpochSource, Error, ExcessCommitment, GenesisDbcProposal, Hash, KeyManager, MaybeSync,
+    NodeSignature, Prefix, PublicKeySet, Result, SpendingCondition, SpendingProof, SpentProofShare,
+    XorName, NATIVE_ASSET,
 };
-use curve25519_dalek_ng::ristretto::RistrettoPoint;
+use bulletproofs::PedersenGens;
+use curve25519_dalek_ng::{ristretto::RistrettoPoint, scalar::Scalar};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     iter::FromIterator,
+    sync::Arc,
 };
 
 pub type MintNodeSignatures = BTreeMap<DbcContentHash, (PublicKeySet, NodeSignature)>;
 
 pub const GENESIS_DBC_INPUT: Hash = Hash([0u8; 32]);
 
-pub trait SpendBook: std::fmt::Debug + Clone {
+pub trait SpendBook: std::fmt::Debug {
     type Error: std::error::Error;
 
     fn lookup(&self, dbc_hash: &DbcContentHash) -> Result<Option<&DbcTransaction>, Self::Error>;
@@ -37,11 +42,35 @@ pub trait SpendBook: std::fmt::Debug + Clone {
         dbc_hash: DbcContentHash,
         transaction: DbcTransaction,
     ) -> Result<(), Self::Error>;
+
+    /// Log a batch of spends atomically: either every spend in the batch is
+    /// logged or, if an error is returned, none of them are.
+    fn log_batch(
+        &mut self,
+        spends: Vec<(DbcContentHash, DbcTransaction)>,
+    ) -> Result<(), Self::Error> {
+        self.log_batch_with_preimages(spends, Default::default())
+    }
+
+    /// Log a batch of spends atomically, as log_batch() does, along with the hash
+    /// lock preimages, by their hash, which the spends revealed.  The preimages are
+    /// logged with the spends or not at all, and a preimage already logged for a
+    /// hash is kept, not replaced.
+    fn log_batch_with_preimages(
+        &mut self,
+        spends: Vec<(DbcContentHash, DbcTransaction)>,
+        preimages: BTreeMap<Hash, Vec<u8>>,
+    ) -> Result<(), Self::Error>;
+
+    /// The preimage of the hash lock `hash`, if a logged spend revealed it.
+    fn lookup_preimage(&self, hash: &Hash) -> Result<Option<&[u8]>, Self::Error>;
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct SimpleSpendBook {
     pub transactions: BTreeMap<DbcContentHash, DbcTransaction>,
+    #[serde(default)]
+    pub preimages: BTreeMap<Hash, Vec<u8>>,
 }
 
 impl SpendBook for SimpleSpendBook {
@@ -59,6 +88,22 @@ impl SpendBook for SimpleSpendBook {
         self.transactions.insert(dbc_hash, transaction);
         Ok(())
     }
+
+    fn log_batch_with_preimages(
+        &mut self,
+        spends: Vec<(DbcContentHash, DbcTransaction)>,
+        preimages: BTreeMap<Hash, Vec<u8>>,
+    ) -> Result<(), Self::Error> {
+        self.transactions.extend(spends);
+        for (hash, preimage) in preimages {
+            self.preimages.entry(hash).or_insert(preimage);
+        }
+        Ok(())
+    }
+
+    fn lookup_preimage(&self, hash: &Hash) -> Result<Option<&[u8]>, Self::Error> {
+        Ok(self.preimages.get(hash).map(Vec::as_slice))
+    }
 }
 
 impl<'a> IntoIterator for &'a SimpleSpendBook {
@@ -83,6 +128,7 @@ impl SimpleSpendBook {
     pub fn new() -> Self {
         Self {
             transactions: Default::default(),
+            preimages: Default::default(),
         }
     }
 }
@@ -91,6 +137,14 @@ impl SimpleSpendBook {
 pub struct ReissueTransaction {
     pub inputs: HashSet<Dbc>,
     pub outputs: HashSet<DbcContent>,
+    // Range proof shared by outputs which reference it, rather than carrying their own.
+    pub aggregated_range_proof: Option<AggregatedRangeProof>,
+    // One for each party to a transaction built by several, see TransactionContribution.
+    pub excess_commitments: Vec<ExcessCommitment>,
+    /// The amount burned, which the inputs cover on top of the outputs.  See DbcTransaction::fee.
+    pub fee: Amount,
+    // Proof for each output with an asset tag that it is of the asset of one of the inputs.
+    pub asset_surjection_proofs: HashMap<DbcContentHash, AssetSurjectionProof>,
 }
 
 impl ReissueTransaction {
@@ -98,17 +152,37 @@ impl ReissueTransaction {
         DbcTransaction {
             inputs: BTreeSet::from_iter(self.inputs.iter().map(|i| i.name())),
             outputs: BTreeSet::from_iter(self.outputs.iter().map(|i| i.hash())),
+            fee: self.fee,
         }
     }
 
-    pub fn validate<K: KeyManager>(&self, verifier: &K) -> Result<()> {
-        self.validate_balance()?;
-        self.validate_input_dbcs(verifier)?;
+    pub fn validate<K: KeyManager + MaybeSync>(&self, verifier: &K) -> Result<()> {
+        self.validate_with_legacy_contents(verifier, &BTreeMap::new())
+    }
+
+    // As validate(), but inputs named by a legacy content hash are accepted if their
+    // content is that recorded for them in `legacy_contents`, see Mint::record_legacy_dbcs().
+    fn validate_with_legacy_contents<K: KeyManager + MaybeSync>(
+        &self,
+        verifier: &K,
+        legacy_contents: &BTreeMap<DbcContentHash, DbcContentHash>,
+    ) -> Result<()> {
+        parallel::check_both(
+            || self.validate_balance(),
+            || self.validate_input_dbcs(verifier, legacy_contents),
+        )?;
         self.validate_outputs()?;
         Ok(())
     }
 
-    fn validate_balance(&self) -> Result<()> {
+    // Inputs ordered by name, so checks over them fail the same way on every run.
+    fn inputs_by_name(&self) -> Vec<(DbcContentHash, &Dbc)> {
+        let mut inputs = Vec::from_iter(self.inputs.iter().map(|input| (input.name(), input)));
+        inputs.sort_by_key(|(name, _)| *name);
+        inputs
+    }
+
+    pub(crate) fn validate_balance(&self) -> Result<()> {
         // Calculate sum(input_commitments) and sum(output_commitments)
         let inputs: RistrettoPoint = self
             .inputs
@@ -131,45 +205,107 @@ impl ReissueTransaction {
                     .ok_or(Error::AmountCommitmentInvalid)
             })
             .sum::<Result<RistrettoPoint, _>>()?;
+        let excess: RistrettoPoint = self
+            .excess_commitments
+            .iter()
+            .map(ExcessCommitment::verify)
+            .sum::<Result<RistrettoPoint, _>>()?;
+        let fee = PedersenGens::default().commit(Scalar::from(self.fee), Scalar::zero());
 
         // Verify the range proof for each output.  (bulletproof)
         // This validates that the committed amount is a positive value.
         // (somewhere in the range 0..u64::max)
         //
-        // TODO: investigate is there some way we could use RangeProof::verify_multiple() instead?
-        // batched verifications should be faster.  It would seem to require that client call
-        // RangeProof::prove_multiple() over all output DBC amounts. But then where to store the aggregated
-        // RangeProof?  It corresponds to a set of outputs, not a single DBC. Would it make sense to store
-        // a dup copy in each?  Unlike eg Monero we do not have a long-lived Transaction to store such data.
-        for output in self.outputs.iter() {
-            output.verify_range_proof()?;
+        // Outputs either carry their own range proof, or reference the aggregated
+        // range proof of this transaction which covers them all and is verified just once.
+        let (aggregated_outputs, individual_outputs): (Vec<_>, Vec<_>) = self
+            .outputs
+            .iter()
+            .partition(|output| output.aggregated_range_proof.is_some());
+        parallel::check_all(&individual_outputs, |output| output.verify_range_proof())?;
+
+        if !aggregated_outputs.is_empty() {
+            let proof = self
+                .aggregated_range_proof
+                .as_ref()
+                .ok_or(Error::AggregatedRangeProofNotFound)?;
+            for output in aggregated_outputs {
+                proof.confirm_covers(output)?;
+            }
+            proof.verify()?;
         }
 
-        if inputs != outputs {
+        self.validate_asset_tags()?;
+
+        if inputs != outputs + excess + fee {
             Err(Error::DbcReissueRequestDoesNotBalance)
         } else {
             Ok(())
         }
     }
 
-    fn validate_input_dbcs<K: KeyManager>(&self, verifier: &K) -> Result<()> {
+    // Each output's asset tag must re-blind an input's, or outputs could be of an
+    // asset never issued, or hide an amount of one asset as an amount of another.
+    fn validate_asset_tags(&self) -> Result<()> {
+        let input_tags = self
+            .inputs_by_name()
+            .into_iter()
+            .map(|(name, input)| Ok((name, input.content.pedersen_gens()?.B)))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        for output in self.outputs.iter() {
+            let output_tag = match output.asset_tag {
+                Some(_) => output.pedersen_gens()?.B,
+                None => continue,
+            };
+            let name = output.hash();
+            let proof = self
+                .asset_surjection_proofs
+                .get(&name)
+                .ok_or(Error::InvalidAssetSurjectionProof(name))?;
+            let ring = proof
+                .inputs
+                .iter()
+                .map(|input| input_tags.get(input).copied())
+                .collect::<Option<Vec<_>>>()
+                .ok_or(Error::InvalidAssetSurjectionProof(name))?;
+            if !proof.verify(&ring, &output_tag) {
+                return Err(Error::InvalidAssetSurjectionProof(name));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_input_dbcs<K: KeyManager + MaybeSync>(
+        &self,
+        verifier: &K,
+        legacy_contents: &BTreeMap<DbcContentHash, DbcContentHash>,
+    ) -> Result<()> {
         if self.inputs.is_empty() {
             return Err(Error::TransactionMustHaveAnInput);
         }
 
-        for input in self.inputs.iter() {
+        parallel::check_all(&self.inputs_by_name(), |(name, input)| {
             input.confirm_valid(verifier)?;
-        }
-
-        Ok(())
+            // Under a legacy hash the holder could swap in a commitment to another amount.
+            if input.hash_version() != Some(DbcContentHashVersion::LATEST)
+                && legacy_contents.get(name) != Some(&input.content.hash())
+            {
+                return Err(Error::LegacyDbcHashVersion(*name));
+            }
+            Ok(())
+        })
     }
 
-    fn validate_outputs(&self) -> Result<()> {
+    pub(crate) fn validate_outputs(&self) -> Result<()> {
         // Validate output parents match the blinded inputs
         let inputs = self.blinded().inputs;
         if self.outputs.iter().any(|o| o.parents != inputs) {
             return Err(Error::DbcContentParentsDifferentFromTransactionInputs);
         }
+        for output in self.outputs.iter() {
+            output.validate_memo()?;
+        }
 
         Ok(())
     }
@@ -180,6 +316,32 @@ pub struct ReissueRequest {
     pub transaction: ReissueTransaction,
     // Signatures from the owners of each input, signing `self.transaction.blinded().hash()`
     pub input_ownership_proofs: HashMap<DbcContentHash, (blsttc::PublicKey, blsttc::Signature)>,
+    // Proofs for inputs spendable on a SpendingCondition, rather than by an owner's signature.
+    #[serde(default)]
+    pub input_spending_proofs: HashMap<DbcContentHash, SpendingProof>,
+}
+
+impl ReissueRequest {
+    /// Splits this request's inputs between the mints of the given `prefixes`,
+    /// giving the inputs the mint of each will spend when sent this request.
+    /// Each input goes to the longest prefix matching its name.
+    pub fn split_by_prefix<'a>(
+        &self,
+        prefixes: impl IntoIterator<Item = &'a Prefix>,
+    ) -> Result<BTreeMap<Prefix, BTreeSet<DbcContentHash>>> {
+        let prefixes = Vec::from_iter(prefixes);
+        let mut split: BTreeMap<Prefix, BTreeSet<DbcContentHash>> = BTreeMap::new();
+        for input in self.transaction.inputs.iter().map(Dbc::name) {
+            let name = XorName::from(input);
+            let prefix = prefixes
+                .iter()
+                .filter(|prefix| prefix.matches(&name))
+                .max_by_key(|prefix| prefix.bit_count())
+                .ok_or(Error::NoPrefixForInput(input))?;
+            split.entry(**prefix).or_default().insert(input);
+        }
+        Ok(split)
+    }
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Deserialize, Serialize)]
@@ -196,61 +358,175 @@ where
 {
     pub(crate) key_manager: K,
     pub spendbook: S,
+    // The part of the DBC name space whose inputs this mint may spend.
+    #[serde(default)]
+    prefix: Prefix,
+    // The least fee this mint requires to be burned to reissue.
+    #[serde(default)]
+    min_fee: Amount,
+    // The current epoch, for time locked inputs.  Without one they are not reissued.
+    #[serde(skip)]
+    epoch_source: Option<Arc<dyn EpochSource>>,
+    // The latest hash of the content of each legacy DBC this mint issued, by its
+    // legacy name.  See record_legacy_dbcs().
+    #[serde(default)]
+    legacy_contents: BTreeMap<DbcContentHash, DbcContentHash>,
 }
 
 impl<K: KeyManager, S: SpendBook> Mint<K, S> {
+    /// A mint for the whole DBC name space.
     pub fn new(key_manager: K, spendbook: S) -> Self {
+        Self::new_with_prefix(key_manager, spendbook, Prefix::default())
+    }
+
+    /// A mint which only spends inputs whose names match `prefix`.
+    pub fn new_with_prefix(key_manager: K, spendbook: S, prefix: Prefix) -> Self {
         Self {
             key_manager,
             spendbook,
+            prefix,
+            min_fee: 0,
+            epoch_source: None,
+            legacy_contents: Default::default(),
         }
     }
 
+    pub fn prefix(&self) -> &Prefix {
+        &self.prefix
+    }
+
+    /// Change the prefix, eg. after the mint's section splits.
+    pub fn set_prefix(&mut self, prefix: Prefix) {
+        self.prefix = prefix;
+    }
+
+    pub fn min_fee(&self) -> Amount {
+        self.min_fee
+    }
+
+    /// Refuse to reissue transactions burning less than `min_fee`.  No fee is required by default.
+    pub fn set_min_fee(&mut self, min_fee: Amount) {
+        self.min_fee = min_fee;
+    }
+
+    /// Check time locked inputs against `epoch_source`.  The epoch source is not
+    /// serialised with the mint, so must be set again after deserialising.
+    pub fn set_epoch_source(&mut self, epoch_source: impl EpochSource + 'static) {
+        self.epoch_source = Some(Arc::new(epoch_source));
+    }
+
+    /// Record the content of DBCs this mint issued before DbcContentHashVersion::V1,
+    /// as found in the outputs of the reissues which issued them, so that they can be
+    /// migrated to the latest hash version.
+    ///
+    /// A legacy name does not commit to the amount commitment, nor to any field added
+    /// since, so a legacy input is only reissued if its content is exactly that recorded
+    /// for it.  Its outputs are named by the latest hash, and it is logged as spent under
+    /// its legacy name, so each legacy DBC is migrated only once.
+    pub fn record_legacy_dbcs<'a>(&mut self, contents: impl IntoIterator<Item = &'a DbcContent>) {
+        self.legacy_contents
+            .extend(contents.into_iter().map(|content| {
+                (
+                    content.hash_with_version(DbcContentHashVersion::V0),
+                    content.hash(),
+                )
+            }));
+    }
+
+    /// The current epoch, if this mint has an epoch source.
+    pub fn current_epoch(&self) -> Option<Epoch> {
+        self.epoch_source
+            .as_ref()
+            .map(|epoch_source| epoch_source.current_epoch())
+    }
+
+    /// The preimage of the hash lock `hash`, if it was revealed to spend an input of this
+    /// mint.  This is how the other party to an atomic swap learns the preimage, see
+    /// HashTimeLock.  Preimages are logged in the spendbook with the spends revealing them.
+    pub fn revealed_preimage(&self, hash: &Hash) -> Result<Option<&[u8]>> {
+        self.spendbook
+            .lookup_preimage(hash)
+            .map_err(|e| Error::SpendBook(e.to_string()))
+    }
+
+    /// Issues the genesis DBC, owned by this mint's key.
+    ///
+    /// Only suitable for a mint of one node: every node of a multi-node mint must
+    /// sign the same genesis DBC, see GenesisDbcProposal.
     pub fn issue_genesis_dbc(
         &mut self,
         amount: Amount,
     ) -> Result<(DbcContent, DbcTransaction, (PublicKeySet, NodeSignature))> {
-        let parents = BTreeSet::from_iter([GENESIS_DBC_INPUT]);
-        let content = DbcContent::new(
-            parents,
-            amount,
-            self.key_manager
-                .public_key_set()
-                .map_err(|e| Error::Signing(e.to_string()))?
-                .public_key(),
-            DbcContent::random_blinding_factor(),
-        )?;
-        let transaction = DbcTransaction {
-            inputs: BTreeSet::from_iter([GENESIS_DBC_INPUT]),
-            outputs: BTreeSet::from_iter([content.hash()]),
-        };
+        let mint_key = self
+            .key_manager
+            .public_key_set()
+            .map_err(|e| Error::Signing(e.to_string()))?
+            .public_key();
+        let proposal = GenesisDbcProposal::new(amount, mint_key)?;
+        let transaction_sig = self.sign_genesis_dbc(amount, &proposal)?;
+
+        Ok((proposal.content, proposal.transaction, transaction_sig))
+    }
+
+    /// Validates a proposed genesis DBC of `amount`, and if valid logs the genesis
+    /// input as spent by the proposed transaction and signs it.
+    ///
+    /// Fails with GenesisInputAlreadySpent if a different genesis DBC was signed before.
+    pub fn sign_genesis_dbc(
+        &mut self,
+        amount: Amount,
+        proposal: &GenesisDbcProposal,
+    ) -> Result<(PublicKeySet, NodeSignature)> {
+        self.sign_asset_issuance(NATIVE_ASSET, amount, proposal)
+    }
+
+    /// Validates a proposal to issue `amount` of `asset`, see GenesisDbcProposal::new_asset(),
+    /// and if valid logs the asset's issuance input as spent by it and signs it.
+    ///
+    /// Each asset is issued once: fails with GenesisInputAlreadySpent if a different
+    /// issuance of `asset` was signed before.
+    pub fn sign_asset_issuance(
+        &mut self,
+        asset: AssetId,
+        amount: Amount,
+        proposal: &GenesisDbcProposal,
+    ) -> Result<(PublicKeySet, NodeSignature)> {
+        let public_key_set = self
+            .key_manager
+            .public_key_set()
+            .map_err(|e| Error::Signing(e.to_string()))?;
+        proposal.validate_asset(asset, amount, &public_key_set.public_key())?;
+        self.sign_genesis_transaction(asset_issuance_input(&asset), &proposal.transaction)
+    }
 
+    // Logs the genesis `input` as spent by `transaction` and signs it.
+    fn sign_genesis_transaction(
+        &mut self,
+        input: DbcContentHash,
+        transaction: &DbcTransaction,
+    ) -> Result<(PublicKeySet, NodeSignature)> {
         match self
             .spendbook
-            .lookup(&GENESIS_DBC_INPUT)
+            .lookup(&input)
             .map_err(|e| Error::SpendBook(e.to_string()))?
         {
-            Some(tx) if tx != &transaction => return Err(Error::GenesisInputAlreadySpent),
+            Some(tx) if tx != transaction => return Err(Error::GenesisInputAlreadySpent),
             _ => (),
         }
 
         self.spendbook
-            .log(GENESIS_DBC_INPUT, transaction.clone())
+            .log(input, transaction.clone())
             .map_err(|e| Error::SpendBook(e.to_string()))?;
         let transaction_sig = self
             .key_manager
-            .sign(&transaction.hash())
+            .sign(&transaction.input_sig_hash(&input))
             .map_err(|e| Error::Signing(e.to_string()))?;
 
         Ok((
-            content,
-            transaction,
-            (
-                self.key_manager
-                    .public_key_set()
-                    .map_err(|e| Error::Signing(e.to_string()))?,
-                transaction_sig,
-            ),
+            self.key_manager
+                .public_key_set()
+                .map_err(|e| Error::Signing(e.to_string()))?,
+            transaction_sig,
         ))
     }
 
@@ -262,62 +538,128 @@ impl<K: KeyManager, S: SpendBook> Mint<K, S> {
             .is_some())
     }
 
+    /// This node's share of a SpentProof for the DBC `dbc_name`, or None if it is unspent.
+    pub fn spent_proof_share(&self, dbc_name: DbcContentHash) -> Result<Option<SpentProofShare>> {
+        let transaction = match self
+            .spendbook
+            .lookup(&dbc_name)
+            .map_err(|e| Error::SpendBook(e.to_string()))?
+        {
+            Some(transaction) => transaction.clone(),
+            None => return Ok(None),
+        };
+
+        let node_sig = self
+            .key_manager
+            .sign(&spent_proof::spent_proof_hash(&dbc_name, &transaction))
+            .map_err(|e| Error::Signing(e.to_string()))?;
+        let public_key_set = self
+            .key_manager
+            .public_key_set()
+            .map_err(|e| Error::Signing(e.to_string()))?;
+
+        Ok(Some(SpentProofShare {
+            dbc_name,
+            transaction,
+            public_key_set,
+            node_sig,
+        }))
+    }
+
     pub fn key_manager(&self) -> &K {
         &self.key_manager
     }
 
-    pub fn reissue(
-        &mut self,
-        reissue_req: ReissueRequest,
-        inputs_belonging_to_mint: BTreeSet<DbcContentHash>,
-    ) -> Result<ReissueShare> {
-        reissue_req.transaction.validate(self.key_manager())?;
+    pub fn key_manager_mut(&mut self) -> &mut K {
+        &mut self.key_manager
+    }
+
+    /// Validate `reissue_req` and spend the inputs whose names match this mint's
+    /// prefix, returning this mint node's ReissueShare.  A request with no input in
+    /// the prefix is refused.
+    pub fn reissue(&mut self, reissue_req: ReissueRequest) -> Result<ReissueShare>
+    where
+        K: MaybeSync,
+    {
+        if reissue_req.transaction.fee < self.min_fee {
+            return Err(Error::FeeTooLow {
+                fee: reissue_req.transaction.fee,
+                required: self.min_fee,
+            });
+        }
+        reissue_req
+            .transaction
+            .validate_with_legacy_contents(self.key_manager(), &self.legacy_contents)?;
         let transaction = reissue_req.transaction.blinded();
         let transaction_hash = transaction.hash();
-
-        for input_dbc in reissue_req.transaction.inputs.iter() {
-            match reissue_req.input_ownership_proofs.get(&input_dbc.name()) {
-                Some((owner, sig)) if owner.verify(sig, &transaction_hash) => {
-                    input_dbc.content.validate_unblinding(owner)?;
+        let epoch = self.current_epoch();
+
+        parallel::check_all(
+            &reissue_req.transaction.inputs_by_name(),
+            |(name, input_dbc)| match (
+                reissue_req.input_ownership_proofs.get(name),
+                reissue_req.input_spending_proofs.get(name),
+            ) {
+                (Some((owner, sig)), _) if owner.verify(sig, transaction_hash) => input_dbc
+                    .content
+                    .validate_spend(&SpendingCondition::Key(*owner), epoch),
+                (Some(_), _) => Err(Error::FailedSignature),
+                (None, Some(proof)) => {
+                    // Only evaluate a condition of a bounded size which is the input's own.
+                    proof.condition.validate_size()?;
+                    input_dbc.content.validate_spend(&proof.condition, epoch)?;
+                    if proof.verify(&transaction_hash) {
+                        Ok(())
+                    } else {
+                        Err(Error::SpendingConditionNotMet)
+                    }
                 }
-                Some(_) => return Err(Error::FailedSignature),
-                None => return Err(Error::MissingInputOwnerProof),
-            }
-        }
+                (None, None) => Err(Error::MissingInputOwnerProof),
+            },
+        )?;
 
-        if !inputs_belonging_to_mint.is_subset(&transaction.inputs) {
-            return Err(Error::FilteredInputNotPresent);
+        // Every input in our prefix is ours to spend, the client does not get to choose.
+        let inputs_belonging_to_mint = BTreeSet::from_iter(
+            transaction
+                .inputs
+                .iter()
+                .copied()
+                .filter(|input| self.prefix.matches(&XorName::from(*input))),
+        );
+        if inputs_belonging_to_mint.is_empty() {
+            return Err(Error::NoInputInMintPrefix);
         }
 
         // Validate that each input has not yet been spent.
         for input in inputs_belonging_to_mint.iter() {
-            if let Some(transaction) = self
-                .spendbook
-                .lookup(input)
-                .map_err(|e| Error::SpendBook(e.to_string()))?
-                .cloned()
-            {
-                // This input has already been spent, return the spend transaction to the user
-                let transaction_sigs = self.sign_transaction(&transaction)?;
-                return Err(Error::DbcAlreadySpent {
-                    transaction,
-                    transaction_sigs,
-                });
+            if let Some(spent_proof_share) = self.spent_proof_share(*input)? {
+                // This input has already been spent, return proof of the spend to the user
+                return Err(Error::DbcAlreadySpent(Box::new(spent_proof_share)));
             }
         }
 
-        let transaction_sigs = self.sign_transaction(&transaction)?;
+        // Sign only for the inputs we spend, the mints of other prefixes sign for theirs.
+        let transaction_sigs = self.sign_transaction(&transaction, &inputs_belonging_to_mint)?;
 
-        for input in reissue_req
-            .transaction
-            .inputs
-            .iter()
-            .filter(|&i| inputs_belonging_to_mint.contains(&i.name()))
-        {
-            self.spendbook
-                .log(input.name(), transaction.clone())
-                .map_err(|e| Error::SpendBook(e.to_string()))?;
+        // Log all of our inputs as spent in a single batch so that a failure
+        // part way through cannot leave some inputs spent without a ReissueShare,
+        // nor an input spent without the hash lock preimages its spend revealed.
+        let spends = Vec::from_iter(
+            inputs_belonging_to_mint
+                .iter()
+                .map(|input| (*input, transaction.clone())),
+        );
+        let mut preimages = BTreeMap::new();
+        for input in inputs_belonging_to_mint.iter() {
+            if let Some(proof) = reissue_req.input_spending_proofs.get(input) {
+                for (hash, preimage) in proof.revealed_preimages() {
+                    preimages.entry(hash).or_insert(preimage);
+                }
+            }
         }
+        self.spendbook
+            .log_batch_with_preimages(spends, preimages)
+            .map_err(|e| Error::SpendBook(e.to_string()))?;
 
         let reissue_share = ReissueShare {
             dbc_transaction: transaction,
@@ -330,33 +672,35 @@ impl<K: KeyManager, S: SpendBook> Mint<K, S> {
     fn sign_transaction(
         &self,
         transaction: &DbcTransaction,
-    ) -> Result<BTreeMap<DbcContentHash, (PublicKeySet, NodeSignature)>> {
-        let sig = self
+        inputs: &BTreeSet<DbcContentHash>,
+    ) -> Result<MintNodeSignatures> {
+        let public_key_set = self
             .key_manager
-            .sign(&transaction.hash())
+            .public_key_set()
             .map_err(|e| Error::Signing(e.to_string()))?;
 
-        Ok(transaction
-            .inputs
+        inputs
             .iter()
-            .copied()
-            .zip(std::iter::repeat((
-                self.key_manager
-                    .public_key_set()
-                    .map_err(|e| Error::Signing(e.to_string()))?,
-                sig,
-            )))
-            .collect())
+            .map(|input| {
+                let sig = self
+                    .key_manager
+                    .sign(&transaction.input_sig_hash(input))
+                    .map_err(|e| Error::Signing(e.to_string()))?;
+                Ok((*input, (public_key_set.clone(), sig)))
+            })
+            .collect()
     }
 
     // Used in testing / benchmarking
-    pub fn snapshot_spendbook(&self) -> S {
-        self.spendbook.clone()
+    pub fn reset_spendbook(&mut self, spendbook: S) {
+        self.spendbook = spendbook
     }
+}
 
+impl<K: KeyManager, S: SpendBook + Clone> Mint<K, S> {
     // Used in testing / benchmarking
-    pub fn reset_spendbook(&mut self, spendbook: S) {
-        self.spendbook = spendbook
+    pub fn snapshot_spendbook(&self) -> S {
+        self.spendbook.clone()
     }
 }
 
@@ -367,7 +711,7 @@ mod tests {
     use quickcheck_macros::quickcheck;
 
     use crate::{
-        tests::{TinyInt, TinyVec},
+        tests::{NonZeroTinyInt, TinyInt, TinyVec},
         DbcBuilder, DbcHelper, SimpleKeyManager, SimpleSigner,
     };
 
@@ -408,7 +752,10 @@ mod tests {
     }
 
     #[quickcheck]
-    fn prop_splitting_the_genesis_dbc(output_amounts: TinyVec<TinyInt>) -> Result<(), Error> {
+    fn prop_splitting_the_genesis_dbc(
+        output_amounts: TinyVec<TinyInt>,
+        aggregate_range_proofs: bool,
+    ) -> Result<(), Error> {
         let output_amounts =
             Vec::from_iter(output_amounts.into_iter().map(TinyInt::coerce::<Amount>));
         let n_outputs = output_amounts.len();
@@ -440,17 +787,26 @@ mod tests {
         let output_owner = crate::bls_dkg_id();
         let output_owner_pk = output_owner.public_key_set.public_key();
 
-        let (reissue_tx, _output_owners) = crate::TransactionBuilder::default()
+        let (reissue_tx, receipt) = crate::TransactionBuilder::default()
             .add_input(genesis_dbc, genesis_amount_secrets)
-            .add_outputs(output_amounts.iter().map(|a| crate::Output {
-                amount: *a,
-                owner: output_owner_pk,
-            }))
+            .add_outputs(
+                output_amounts
+                    .iter()
+                    .map(|a| crate::Output::new(*a, output_owner_pk)),
+            )
+            .aggregate_range_proofs(aggregate_range_proofs)
             .build()?;
+        // The payer hands the proof to recipients with the receipt.
+        let aggregated_range_proof = receipt.aggregated_range_proof;
+        assert_eq!(aggregated_range_proof, reissue_tx.aggregated_range_proof);
+        assert_eq!(
+            aggregated_range_proof.is_some(),
+            aggregate_range_proofs && n_outputs > 0
+        );
 
         let sig_share = genesis_owner
             .secret_key_share
-            .sign(&reissue_tx.blinded().hash());
+            .sign(reissue_tx.blinded().hash());
 
         let sig = genesis_owner
             .public_key_set
@@ -459,22 +815,22 @@ mod tests {
         let reissue_req = ReissueRequest {
             transaction: reissue_tx.clone(),
             input_ownership_proofs: HashMap::from_iter([(gen_dbc_name, (genesis_key, sig))]),
+            input_spending_proofs: Default::default(),
         };
 
-        let reissue_share =
-            match genesis_node.reissue(reissue_req, BTreeSet::from_iter([gen_dbc_name])) {
-                Ok(rs) => {
-                    // Verify that at least one output was present.
-                    assert_ne!(n_outputs, 0);
-                    rs
-                }
-                Err(Error::DbcReissueRequestDoesNotBalance) => {
-                    // Verify that no outputs were present and we got correct validation error.
-                    assert_eq!(n_outputs, 0);
-                    return Ok(());
-                }
-                Err(e) => return Err(e),
-            };
+        let reissue_share = match genesis_node.reissue(reissue_req) {
+            Ok(rs) => {
+                // Verify that at least one output was present.
+                assert_ne!(n_outputs, 0);
+                rs
+            }
+            Err(Error::DbcReissueRequestDoesNotBalance) => {
+                // Verify that no outputs were present and we got correct validation error.
+                assert_eq!(n_outputs, 0);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
 
         // Aggregate ReissueShare to build output DBCs
         let mut dbc_builder = DbcBuilder::new(reissue_tx);
@@ -483,8 +839,11 @@ mod tests {
 
         for dbc in output_dbcs.iter() {
             let dbc_amount = DbcHelper::decrypt_amount(&output_owner, &dbc.content)?;
-            assert!(output_amounts.iter().any(|a| *a == dbc_amount));
+            assert!(output_amounts.contains(&dbc_amount));
             assert!(dbc.confirm_valid(&key_manager).is_ok());
+            assert!(dbc
+                .verify_full(&key_manager, None, aggregated_range_proof.as_ref())
+                .is_valid());
         }
 
         assert_eq!(
@@ -526,10 +885,10 @@ mod tests {
         let output_owner = crate::bls_dkg_id();
         let (reissue_tx, _output_owners) = crate::TransactionBuilder::default()
             .add_input(genesis_dbc.clone(), genesis_amount_secrets)
-            .add_output(crate::Output {
-                amount: 1000,
-                owner: output_owner.public_key_set.public_key(),
-            })
+            .add_output(crate::Output::new(
+                1000,
+                output_owner.public_key_set.public_key(),
+            ))
             .build()?;
 
         let sig_share = genesis_node
@@ -544,19 +903,18 @@ mod tests {
         let reissue_req = ReissueRequest {
             transaction: reissue_tx,
             input_ownership_proofs: HashMap::from_iter([(gen_dbc_name, (genesis_key, sig))]),
+            input_spending_proofs: Default::default(),
         };
 
-        let reissue_share =
-            genesis_node.reissue(reissue_req, BTreeSet::from_iter([gen_dbc_name]))?;
+        let reissue_share = genesis_node.reissue(reissue_req)?;
         let t = reissue_share.dbc_transaction;
-        let s = reissue_share.mint_node_signatures;
 
         let (double_spend_reissue_tx, _output_owners) = crate::TransactionBuilder::default()
             .add_input(genesis_dbc, genesis_amount_secrets)
-            .add_output(crate::Output {
-                amount: 1000,
-                owner: output_owner.public_key_set.public_key(),
-            })
+            .add_output(crate::Output::new(
+                1000,
+                output_owner.public_key_set.public_key(),
+            ))
             .build()?;
 
         let node_share = genesis_node
@@ -571,22 +929,309 @@ mod tests {
         let double_spend_reissue_req = ReissueRequest {
             transaction: double_spend_reissue_tx,
             input_ownership_proofs: HashMap::from_iter([(gen_dbc_name, (genesis_key, sig))]),
+            input_spending_proofs: Default::default(),
         };
 
-        let res = genesis_node.reissue(
-            double_spend_reissue_req,
-            BTreeSet::from_iter([gen_dbc_name]),
-        );
+        let res = genesis_node.reissue(double_spend_reissue_req);
 
         println!("res {:?}", res);
         assert!(matches!(
             res,
-            Err(Error::DbcAlreadySpent { transaction, transaction_sigs }) if transaction == t && transaction_sigs == s
+            Err(Error::DbcAlreadySpent(share)) if share.dbc_name == gen_dbc_name
+                && share.transaction == t
+                && crate::SpentProof::from_shares([(*share).clone()]).is_ok()
         ));
 
         Ok(())
     }
 
+    #[test]
+    fn test_aggregated_range_proof_must_match_outputs() -> Result<()> {
+        let mut cluster = crate::MintCluster::random(1, 0);
+        let genesis_dbc = cluster.issue_genesis_dbc(1000)?;
+        let gen_dbc_name = genesis_dbc.name();
+        let genesis_amount_secrets = cluster.decrypt_amount_secrets(&genesis_dbc.content)?;
+        let key_manager = cluster.node(0).unwrap().key_manager().clone();
+
+        let output_owner_pk = crate::bls_dkg_id().public_key_set.public_key();
+        let split_tx = |amounts: &[Amount]| {
+            crate::TransactionBuilder::default()
+                .add_input(genesis_dbc.clone(), genesis_amount_secrets)
+                .add_outputs(
+                    amounts
+                        .iter()
+                        .map(|amount| crate::Output::new(*amount, output_owner_pk)),
+                )
+                .aggregate_range_proofs(true)
+                .build()
+                .map(|(tx, _)| tx)
+        };
+
+        let reissue_tx = split_tx(&[500, 300, 200])?;
+        let proof = reissue_tx
+            .aggregated_range_proof
+            .clone()
+            .expect("aggregated proof should be present");
+
+        // The proof is padded to a power of two, and outputs carry no proof of their own.
+        assert_eq!(proof.commitments.len(), 4);
+        for output in reissue_tx.outputs.iter() {
+            assert!(output.range_proof_bytes.is_empty());
+            assert_eq!(output.aggregated_range_proof, Some(proof.hash()));
+            assert!(output.verify_aggregated_range_proof(&proof).is_ok());
+            assert!(matches!(
+                output.verify_range_proof(),
+                Err(Error::AggregatedRangeProofNotFound)
+            ));
+        }
+
+        // Without the aggregated proof the outputs' amounts are unproven.
+        let mut missing_proof_tx = reissue_tx.clone();
+        missing_proof_tx.aggregated_range_proof = None;
+        assert!(matches!(
+            missing_proof_tx.validate(&key_manager),
+            Err(Error::AggregatedRangeProofNotFound)
+        ));
+
+        // A valid proof for some other outputs is not accepted either.
+        let mut other_proof_tx = reissue_tx.clone();
+        other_proof_tx.aggregated_range_proof = split_tx(&[600, 400])?.aggregated_range_proof;
+        assert!(matches!(
+            other_proof_tx.validate(&key_manager),
+            Err(Error::AggregatedRangeProofNotFound)
+        ));
+
+        // A proof over the right commitments, but which does not verify, is rejected.
+        let mut bad_proof_tx = reissue_tx.clone();
+        let mut bad_proof = proof.clone();
+        bad_proof.proof_bytes[0] ^= 0xff;
+        let bad_proof_hash = bad_proof.hash();
+        bad_proof_tx.outputs = HashSet::from_iter(bad_proof_tx.outputs.into_iter().map(|mut o| {
+            o.aggregated_range_proof = Some(bad_proof_hash);
+            o
+        }));
+        bad_proof_tx.aggregated_range_proof = Some(bad_proof);
+        assert!(matches!(
+            bad_proof_tx.validate(&key_manager),
+            Err(Error::RangeProof(_))
+        ));
+
+        let reissue_req = crate::ReissueRequestBuilder::new(reissue_tx)
+            .add_signer(
+                gen_dbc_name,
+                crate::OwnerSigner::SecretKeySet(cluster.secret_key_set().clone()),
+            )
+            .build()?;
+        let output_dbcs = cluster.reissue(reissue_req)?;
+        for dbc in output_dbcs.iter() {
+            assert!(dbc.confirm_valid(&key_manager).is_ok());
+            assert!(dbc.verify_full(&key_manager, None, Some(&proof)).is_valid());
+            assert!(matches!(
+                dbc.verify_full(&key_manager, None, None).range_proof,
+                Err(Error::AggregatedRangeProofNotFound)
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reissue_pays_fee() -> Result<()> {
+        let mut cluster = crate::MintCluster::random(1, 0);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let genesis_name = genesis.name();
+        let genesis_secrets = cluster.decrypt_amount_secrets(&genesis.content)?;
+        let owner = crate::OwnerSigner::SecretKeySet(cluster.secret_key_set().clone());
+        cluster.node_mut(0).unwrap().set_min_fee(10);
+
+        let payee = crate::bls_dkg_id().public_key_set.public_key();
+        let change = crate::bls_dkg_id().public_key_set.public_key();
+        let pay = |fee: Amount| {
+            crate::TransactionBuilder::default()
+                .add_output(crate::Output::new(600, payee))
+                .fee(fee)
+                .select_inputs(
+                    [(genesis.clone(), genesis_secrets)],
+                    change,
+                    &crate::LargestFirst,
+                )?
+                .build()
+        };
+
+        // Less than the mint's minimum fee is refused.
+        let (cheap_tx, _) = pay(5)?;
+        let cheap_req = crate::ReissueRequestBuilder::new(cheap_tx)
+            .add_signer(genesis_name, owner.clone())
+            .build()?;
+        assert!(matches!(
+            cluster.reissue(cheap_req),
+            Err(Error::FeeTooLow {
+                fee: 5,
+                required: 10
+            })
+        ));
+
+        // The change is what remains after the payment and the fee.
+        let (reissue_tx, receipt) = pay(10)?;
+        assert_eq!(receipt.transaction.fee, 10);
+        assert_eq!(receipt.amount_paid_to(&change), 390);
+
+        // No output pays the fee to the mint, it is burned.
+        let mint_key = cluster.public_key_set().public_key();
+        assert_eq!(receipt.amount_paid_to(&mint_key), 0);
+        assert_eq!(
+            receipt.amount_paid_to(&payee) + receipt.amount_paid_to(&change),
+            1000 - 10
+        );
+
+        // The fee is part of the transaction the owners and mint sign, and the
+        // amounts do not balance without it.
+        let mut no_fee_tx = reissue_tx.clone();
+        no_fee_tx.fee = 0;
+        assert_ne!(no_fee_tx.blinded().hash(), reissue_tx.blinded().hash());
+        assert!(matches!(
+            no_fee_tx.validate_balance(),
+            Err(Error::DbcReissueRequestDoesNotBalance)
+        ));
+
+        let reissue_req = crate::ReissueRequestBuilder::new(reissue_tx)
+            .add_signer(genesis_name, owner)
+            .build()?;
+        let dbcs = cluster.reissue(reissue_req)?;
+        assert_eq!(dbcs.len(), 2);
+        for dbc in dbcs.iter() {
+            assert_eq!(dbc.transaction.fee, 10);
+            dbc.confirm_valid(cluster.node(0).unwrap().key_manager())?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_spend_logging_leaves_no_input_spent() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "sn_dbc_spendbook_{}.log",
+            hex::encode(rand::random::<[u8; 16]>())
+        ));
+        let secret_key_set = blsttc::SecretKeySet::random(0, &mut rand::thread_rng());
+        let mut cluster = crate::MintCluster::from_secret_key_set(secret_key_set, 1, |_| {
+            crate::FileSpendBook::open(&path).expect("Failed to open spendbook")
+        });
+
+        // Split the genesis DBC so that we have several inputs to merge.
+        let owner = blsttc::SecretKey::random();
+        let inputs =
+            crate::tests::split_genesis(&mut cluster, &[500, 300, 200], owner.public_key())?;
+        let merge_inputs = Vec::from_iter(inputs.iter().map(|(dbc, _)| dbc.name()));
+        let merge_req = crate::tests::merge_request(inputs, &owner, owner.public_key())?;
+        let genesis_node = cluster.node_mut(0).unwrap();
+
+        // The spendbook fails to log the batch, as it would on a full disk.
+        genesis_node.spendbook.set_read_only(true)?;
+        let res = genesis_node.reissue(merge_req.clone());
+        assert!(matches!(res, Err(Error::SpendBook(_))));
+
+        for input in merge_inputs.iter() {
+            assert!(!genesis_node.is_spent(*input)?);
+        }
+
+        // Once the fault clears, the same request can be reissued in full.
+        genesis_node.spendbook.set_read_only(false)?;
+        genesis_node.reissue(merge_req)?;
+
+        for input in merge_inputs.iter() {
+            assert!(genesis_node.is_spent(*input)?);
+        }
+
+        drop(cluster);
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    // A reissue refused as one of its inputs is spent must leave the others unspent.
+    fn assert_refused_batch_spends_nothing<S: SpendBook>(
+        mut cluster: crate::MintCluster<S>,
+    ) -> Result<()> {
+        let owner = blsttc::SecretKey::random();
+        let inputs =
+            crate::tests::split_genesis(&mut cluster, &[500, 300, 200], owner.public_key())?;
+        cluster.reissue(crate::tests::merge_request(
+            inputs[..1].to_vec(),
+            &owner,
+            owner.public_key(),
+        )?)?;
+
+        let merge_req = crate::tests::merge_request(inputs.clone(), &owner, owner.public_key())?;
+        assert!(matches!(
+            cluster.reissue(merge_req),
+            Err(Error::DbcAlreadySpent(share)) if share.dbc_name == inputs[0].0.name()
+        ));
+        for node in cluster.nodes() {
+            for (dbc, _) in inputs[1..].iter() {
+                assert!(!node.is_spent(dbc.name())?);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_refused_batch_leaves_spendbooks_unchanged() -> Result<()> {
+        assert_refused_batch_spends_nothing(crate::MintCluster::random(3, 1))?;
+
+        let secret_key_set = blsttc::SecretKeySet::random(1, &mut rand::thread_rng());
+        let paths = Vec::from_iter((0..3).map(|i| {
+            std::env::temp_dir().join(format!(
+                "sn_dbc_spendbook_{}_{}.log",
+                hex::encode(rand::random::<[u8; 16]>()),
+                i
+            ))
+        }));
+        let cluster = crate::MintCluster::from_secret_key_set(secret_key_set, 3, |i| {
+            crate::FileSpendBook::open(&paths[i as usize]).expect("Failed to open spendbook")
+        });
+        assert_refused_batch_spends_nothing(cluster)?;
+        for path in paths {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_simple_spendbook_logs_batch_with_its_preimages() -> Result<()> {
+        let hash: Hash = rand::random();
+        let spends = Vec::from_iter((0..3).map(|_| {
+            let input: DbcContentHash = rand::random();
+            let tx = DbcTransaction::new(
+                BTreeSet::from_iter([input]),
+                BTreeSet::from_iter([rand::random()]),
+            );
+            (input, tx)
+        }));
+
+        let mut spendbook = SimpleSpendBook::new();
+        spendbook
+            .log_batch_with_preimages(
+                spends[..2].to_vec(),
+                BTreeMap::from_iter([(hash, b"first".to_vec())]),
+            )
+            .unwrap();
+        spendbook
+            .log_batch_with_preimages(
+                spends[2..].to_vec(),
+                BTreeMap::from_iter([(hash, b"second".to_vec())]),
+            )
+            .unwrap();
+
+        for (input, tx) in spends.iter() {
+            assert_eq!(spendbook.lookup(input).unwrap(), Some(tx));
+        }
+        assert_eq!(
+            spendbook.lookup_preimage(&hash).unwrap(),
+            Some(&b"first"[..])
+        );
+        Ok(())
+    }
+
     #[quickcheck]
     fn prop_dbc_transaction_many_to_many(
         // the amount of each input transaction
@@ -656,21 +1301,22 @@ mod tests {
             (owner.public_key_set.public_key(), (a, owner))
         }));
 
-        let (reissue_tx, output_owner_pks) = crate::TransactionBuilder::default()
+        let (reissue_tx, receipt) = crate::TransactionBuilder::default()
             .add_input(genesis_dbc, genesis_amount_secrets)
             .add_outputs(
                 owner_amounts_and_keys
                     .clone()
                     .into_iter()
-                    .map(|(owner, (amount, _))| crate::Output { amount, owner }),
+                    .map(|(owner, (amount, _))| crate::Output::new(amount, owner)),
             )
             .build()?;
 
-        let owners =
-            BTreeMap::from_iter(output_owner_pks.into_iter().map(|(dbc_hash, owner_pk)| {
+        let owners = BTreeMap::from_iter(receipt.output_owners.into_iter().map(
+            |(dbc_hash, owner_pk)| {
                 let (_, owner) = &owner_amounts_and_keys[&owner_pk];
                 (dbc_hash, owner)
-            }));
+            },
+        ));
 
         let sig_share = genesis_node
             .key_manager
@@ -683,22 +1329,22 @@ mod tests {
         let reissue_req = ReissueRequest {
             transaction: reissue_tx,
             input_ownership_proofs: HashMap::from_iter([(gen_dbc_name, (genesis_key, sig))]),
+            input_spending_proofs: Default::default(),
         };
 
-        let reissue_share =
-            match genesis_node.reissue(reissue_req.clone(), BTreeSet::from_iter([gen_dbc_name])) {
-                Ok(rs) => {
-                    // Verify that at least one input (output in this tx) was present.
-                    assert!(!input_amounts.is_empty());
-                    rs
-                }
-                Err(Error::DbcReissueRequestDoesNotBalance) => {
-                    // Verify that no inputs (outputs in this tx) were present and we got correct validation error.
-                    assert!(input_amounts.is_empty());
-                    return Ok(());
-                }
-                Err(e) => return Err(e),
-            };
+        let reissue_share = match genesis_node.reissue(reissue_req.clone()) {
+            Ok(rs) => {
+                // Verify that at least one input (output in this tx) was present.
+                assert!(!input_amounts.is_empty());
+                rs
+            }
+            Err(Error::DbcReissueRequestDoesNotBalance) => {
+                // Verify that no inputs (outputs in this tx) were present and we got correct validation error.
+                assert!(input_amounts.is_empty());
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
 
         // Aggregate ReissueShare to build output DBCs
         let mut dbc_builder = DbcBuilder::new(reissue_req.transaction);
@@ -718,9 +1364,8 @@ mod tests {
 
         let (mut reissue_tx, _) = crate::TransactionBuilder::default()
             .add_inputs(input_dbcs)
-            .add_outputs(output_amounts.iter().map(|amount| crate::Output {
-                amount: *amount,
-                owner: outputs_owner.public_key_set.public_key(),
+            .add_outputs(output_amounts.iter().map(|amount| {
+                crate::Output::new(*amount, outputs_owner.public_key_set.public_key())
             }))
             .build()?;
 
@@ -748,7 +1393,7 @@ mod tests {
             .filter_map(|input_num| reissue_tx.inputs.iter().nth(input_num))
             .map(|dbc| {
                 let owner = &owners[&dbc.name()];
-                let sig_share = owner.secret_key_share.sign(&reissue_tx.blinded().hash());
+                let sig_share = owner.secret_key_share.sign(reissue_tx.blinded().hash());
                 let owner_key_set = &owner.public_key_set;
                 let sig = owner_key_set.combine_signatures(vec![(owner.index, &sig_share)])?;
                 Ok((dbc.name(), (owner_key_set.public_key(), sig)))
@@ -762,7 +1407,7 @@ mod tests {
                 let random_owner = crate::bls_dkg_id();
                 let sig_share = random_owner
                     .secret_key_share
-                    .sign(&reissue_tx.blinded().hash());
+                    .sign(reissue_tx.blinded().hash());
                 let owner_key_set = random_owner.public_key_set;
                 let sig =
                     owner_key_set.combine_signatures(vec![(random_owner.index, &sig_share)])?;
@@ -788,12 +1433,10 @@ mod tests {
         let reissue_req = ReissueRequest {
             transaction: reissue_tx,
             input_ownership_proofs,
+            input_spending_proofs: Default::default(),
         };
 
-        let many_to_many_result = genesis_node.reissue(
-            reissue_req.clone(),
-            BTreeSet::from_iter(reissue_req.transaction.blinded().inputs),
-        );
+        let many_to_many_result = genesis_node.reissue(reissue_req.clone());
 
         match many_to_many_result {
             Ok(rs) => {
@@ -836,7 +1479,7 @@ mod tests {
                     output_total_amount
                 );
             }
-            Err(Error::DbcReissueRequestDoesNotBalance { .. }) => {
+            Err(Error::DbcReissueRequestDoesNotBalance) => {
                 if genesis_amount == output_total_amount {
                     // This can correctly occur if there are 0 outputs and inputs sum to zero.
                     //
@@ -873,16 +1516,300 @@ mod tests {
         Ok(())
     }
 
+    // Rotate the mint from the `section` key set to a freshly generated one.
+    fn churn(
+        mint: &mut Mint<SimpleKeyManager, SimpleSpendBook>,
+        section: &blsttc::SecretKeySet,
+    ) -> Result<blsttc::SecretKeySet> {
+        let new_section = blsttc::SecretKeySet::random(0, &mut rand::thread_rng());
+        let rotation_hash = mint
+            .key_manager()
+            .key_chain()
+            .next_rotation_hash(&new_section.public_keys().public_key());
+        let sig = section.secret_key().sign(rotation_hash);
+        let signer = SimpleSigner::new(
+            new_section.public_keys(),
+            (0, new_section.secret_key_share(0)),
+        );
+        mint.key_manager_mut().rotate(signer, sig)?;
+        Ok(new_section)
+    }
+
     #[quickcheck]
-    #[ignore]
-    fn prop_in_progress_transaction_can_be_continued_across_churn() {
-        todo!()
+    fn prop_in_progress_transaction_can_be_continued_across_churn(
+        n_churns: TinyInt,
+    ) -> Result<(), Error> {
+        let n_churns = n_churns.coerce::<usize>();
+
+        let mut cluster = crate::MintCluster::random(1, 0);
+        let genesis_key = cluster.public_key_set().public_key();
+        let genesis_dbc = cluster.issue_genesis_dbc(1000)?;
+        let gen_dbc_name = genesis_dbc.name();
+        let genesis_amount_secrets = cluster.decrypt_amount_secrets(&genesis_dbc.content)?;
+
+        // The client prepares a reissue before the mint churns.
+        let output_owner = blsttc::SecretKey::random();
+        let (reissue_tx, _) = crate::TransactionBuilder::default()
+            .add_input(genesis_dbc, genesis_amount_secrets)
+            .add_outputs(
+                [400, 600]
+                    .iter()
+                    .map(|amount| crate::Output::new(*amount, output_owner.public_key())),
+            )
+            .build()?;
+        let reissue_req = crate::ReissueRequestBuilder::new(reissue_tx.clone())
+            .add_signer(
+                gen_dbc_name,
+                crate::OwnerSigner::SecretKeySet(cluster.secret_key_set().clone()),
+            )
+            .build()?;
+
+        let mut section = cluster.secret_key_set().clone();
+        let genesis_node = cluster.node_mut(0).unwrap();
+        for _ in 0..n_churns {
+            section = churn(genesis_node, &section)?;
+        }
+        assert_eq!(
+            genesis_node.key_manager().key_chain().keys().count(),
+            n_churns + 1
+        );
+
+        // The reissue is completed, and signed, by the churned mint.
+        let reissue_share = genesis_node.reissue(reissue_req)?;
+        let output_dbcs = DbcBuilder::new(reissue_tx)
+            .add_reissue_share(reissue_share)
+            .build()?;
+
+        // A verifier which only knows genesis accepts the outputs once it learns the key chain.
+        let mut verifier =
+            SimpleKeyManager::new(SimpleSigner::from(crate::bls_dkg_id()), genesis_key);
+        if n_churns > 0 {
+            for dbc in output_dbcs.iter() {
+                assert!(matches!(
+                    dbc.confirm_valid(&verifier),
+                    Err(Error::Signing(_))
+                ));
+            }
+        }
+        verifier.update_key_chain(genesis_node.key_manager().key_chain().clone())?;
+        for dbc in output_dbcs.iter() {
+            dbc.confirm_valid(&verifier)?;
+        }
+
+        // After another churn, the outputs signed by the now superseded key can still be spent.
+        churn(genesis_node, &section)?;
+
+        let inputs = output_dbcs
+            .into_iter()
+            .map(|dbc| {
+                let secrets = dbc.content.amount_secret_by_secret_key(&output_owner)?;
+                Ok((dbc, secrets))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let merge_req =
+            crate::tests::merge_request(inputs, &output_owner, output_owner.public_key())?;
+        genesis_node.reissue(merge_req)?;
+
+        Ok(())
     }
 
     #[quickcheck]
-    #[ignore]
-    fn prop_reject_invalid_prefix() {
-        todo!();
+    fn prop_reject_invalid_prefix(input_amounts: TinyVec<NonZeroTinyInt>) -> Result<(), Error> {
+        let input_amounts = Vec::from_iter(
+            input_amounts
+                .into_iter()
+                .map(NonZeroTinyInt::coerce::<Amount>),
+        );
+        let total_amount: Amount = input_amounts.iter().sum();
+        if input_amounts.is_empty() {
+            return Ok(());
+        }
+
+        let mut cluster = crate::MintCluster::random(1, 0);
+
+        // Split genesis into DBCs which will land on either side of the name space.
+        let owner = blsttc::SecretKey::random();
+        let inputs = crate::tests::split_genesis(&mut cluster, &input_amounts, owner.public_key())?;
+
+        // Merge them all back together, across two sections splitting the name space.
+        let merge_req = crate::tests::merge_request(inputs, &owner, owner.public_key())?;
+        let merge_tx = merge_req.transaction.clone();
+        let genesis_node = cluster.node(0).unwrap();
+        let key_manager = genesis_node.key_manager().clone();
+
+        let prefixes = [
+            Prefix::default().pushed(false),
+            Prefix::default().pushed(true),
+        ];
+        let mut mints = Vec::from_iter(prefixes.iter().map(|prefix| {
+            Mint::new_with_prefix(key_manager.clone(), genesis_node.spendbook.clone(), *prefix)
+        }));
+
+        let split = merge_req.split_by_prefix(&prefixes)?;
+        assert_eq!(
+            split.values().map(BTreeSet::len).sum::<usize>(),
+            merge_tx.inputs.len()
+        );
+        if split.len() > 1 {
+            // Without the second prefix there is nowhere to send some of the inputs.
+            assert!(matches!(
+                merge_req.split_by_prefix(&prefixes[..1]),
+                Err(Error::NoPrefixForInput(_))
+            ));
+        }
+
+        let input_names = merge_tx.blinded().inputs;
+        let mut dbc_builder = DbcBuilder::new(merge_tx);
+        for (prefix, inputs) in split.iter() {
+            let mint = &mut mints[usize::from(*prefix == prefixes[1])];
+            let share = mint.reissue(merge_req.clone())?;
+
+            // Each mint spends every input in its prefix, and no other.
+            for input in input_names.iter() {
+                assert_eq!(
+                    mint.spendbook.lookup(input)?.is_some(),
+                    inputs.contains(input)
+                );
+            }
+            dbc_builder = dbc_builder.add_reissue_share(share);
+        }
+        if split.len() == 1 {
+            // The mint for the other half of the name space has nothing to spend.
+            let idle = usize::from(!split.contains_key(&prefixes[1]));
+            assert!(matches!(
+                mints[idle].reissue(merge_req.clone()),
+                Err(Error::NoInputInMintPrefix)
+            ));
+        }
+
+        for dbc in dbc_builder.build()? {
+            dbc.confirm_valid(&key_manager)?;
+            assert_eq!(
+                dbc.content.amount_secret_by_secret_key(&owner)?.amount,
+                total_amount
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reissue_spends_every_input_in_prefix() -> Result<()> {
+        let mut cluster = crate::MintCluster::random(1, 0);
+        let owner = blsttc::SecretKey::random();
+        let (dbc, secrets) =
+            cluster.issue_genesis_to(crate::Output::new(100, owner.public_key()))?;
+        let transaction = crate::tests::spend_to(&dbc, secrets, owner.public_key())?;
+        let reissue_req = crate::ReissueRequestBuilder::new(transaction)
+            .add_signer(dbc.name(), crate::OwnerSigner::SecretKey(owner))
+            .build()?;
+
+        // A mint whose prefix holds none of the inputs has nothing to spend, nor sign.
+        let mint = cluster.node_mut(0).unwrap();
+        let name = XorName::from(dbc.name());
+        mint.set_prefix(Prefix::default().pushed(!name.bit(0)));
+        assert!(matches!(
+            mint.reissue(reissue_req.clone()),
+            Err(Error::NoInputInMintPrefix)
+        ));
+
+        // The input in the prefix is spent without the client naming it, so it can
+        // not be left out to have the transaction signed again.
+        mint.set_prefix(Prefix::default());
+        mint.reissue(reissue_req.clone())?;
+        assert!(mint.spendbook.lookup(&dbc.name())?.is_some());
+        assert!(matches!(
+            mint.reissue(reissue_req),
+            Err(Error::DbcAlreadySpent(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reissue_signs_only_inputs_in_prefix() -> Result<()> {
+        let mut cluster = crate::MintCluster::random(1, 0);
+        let owner = blsttc::SecretKeySet::random(0, &mut rand::thread_rng());
+        let owner_pk = owner.public_keys().public_key();
+        let side = |dbc: &Dbc| XorName::from(dbc.name()).bit(0);
+
+        // Respend one of two inputs until they are on either side of the name space.
+        let mut inputs = crate::tests::split_genesis(&mut cluster, &[1, 1], owner_pk)?;
+        while side(&inputs[0].0) == side(&inputs[1].0) {
+            let reissue_req =
+                crate::tests::merge_request(inputs.pop(), &owner.secret_key(), owner_pk)?;
+            let dbc = cluster.reissue(reissue_req)?.remove(0);
+            let secrets = dbc.content.amount_secrets_by_secret_key_set(&owner)?;
+            inputs.push((dbc, secrets));
+        }
+        inputs.sort_by_key(|(dbc, _)| side(dbc));
+        let (zero, one) = (inputs[0].0.name(), inputs[1].0.name());
+        let reissue_req = crate::tests::merge_request(inputs, &owner.secret_key(), owner_pk)?;
+
+        // The mint of prefix 0 spends and signs for its input, but not the other.
+        let mint = cluster.node_mut(0).unwrap();
+        mint.set_prefix(Prefix::default().pushed(false));
+        let zero_share = mint.reissue(reissue_req.clone())?;
+        assert!(zero_share.mint_node_signatures.contains_key(&zero));
+        assert!(!zero_share.mint_node_signatures.contains_key(&one));
+        assert!(mint.spendbook.lookup(&one)?.is_none());
+
+        // So its share alone can not make the outputs, until the mint of prefix 1 adds its own.
+        assert!(matches!(
+            DbcBuilder::new(reissue_req.transaction.clone())
+                .add_reissue_share(zero_share.clone())
+                .build(),
+            Err(Error::ReissueShareMintNodeSignatureNotFoundForInput)
+        ));
+        mint.set_prefix(Prefix::default().pushed(true));
+        let one_share = mint.reissue(reissue_req.clone())?;
+        assert!(!one_share.mint_node_signatures.contains_key(&zero));
+        let dbcs = DbcBuilder::new(reissue_req.transaction)
+            .add_reissue_share(zero_share)
+            .add_reissue_share(one_share)
+            .build()?;
+        dbcs[0].confirm_valid(mint.key_manager())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_signature_moved_to_input_of_other_prefix_is_rejected() -> Result<()> {
+        let mut cluster = crate::MintCluster::random(1, 0);
+        let owner = blsttc::SecretKeySet::random(0, &mut rand::thread_rng());
+        let owner_pk = owner.public_keys().public_key();
+        let side = |dbc: &Dbc| XorName::from(dbc.name()).bit(0);
+
+        let mut inputs = crate::tests::split_genesis(&mut cluster, &[1, 1], owner_pk)?;
+        while side(&inputs[0].0) == side(&inputs[1].0) {
+            let reissue_req =
+                crate::tests::merge_request(inputs.pop(), &owner.secret_key(), owner_pk)?;
+            let dbc = cluster.reissue(reissue_req)?.remove(0);
+            let secrets = dbc.content.amount_secrets_by_secret_key_set(&owner)?;
+            inputs.push((dbc, secrets));
+        }
+        inputs.sort_by_key(|(dbc, _)| side(dbc));
+        let (zero, one) = (inputs[0].0.name(), inputs[1].0.name());
+        let reissue_req = crate::tests::merge_request(inputs, &owner.secret_key(), owner_pk)?;
+
+        // Only the mint of prefix 0 is asked, and its signature is copied onto the
+        // input of prefix 1, which that mint never spent.
+        let mint = cluster.node_mut(0).unwrap();
+        mint.set_prefix(Prefix::default().pushed(false));
+        let mut share = mint.reissue(reissue_req.clone())?;
+        let zero_sig = share.mint_node_signatures[&zero].clone();
+        share.mint_node_signatures.insert(one, zero_sig);
+        assert!(mint.spendbook.lookup(&one)?.is_none());
+
+        let dbcs = DbcBuilder::new(reissue_req.transaction)
+            .add_reissue_share(share)
+            .build()?;
+        assert!(matches!(
+            dbcs[0].confirm_valid(mint.key_manager()),
+            Err(Error::Signing(_))
+        ));
+
+        Ok(())
     }
 
     #[test]
@@ -903,28 +1830,30 @@ mod tests {
         )?;
         let input_content_hashes = BTreeSet::from_iter([input_content.hash()]);
 
-        let fraudulant_reissue_result = genesis_node.reissue(
-            ReissueRequest {
-                transaction: ReissueTransaction {
-                    inputs: HashSet::from_iter([Dbc {
-                        content: input_content,
-                        transaction: DbcTransaction {
-                            inputs: Default::default(),
-                            outputs: input_content_hashes.clone(),
-                        },
-                        transaction_sigs: Default::default(),
-                    }]),
-                    outputs: HashSet::from_iter([DbcContent::new(
+        let fraudulant_reissue_result = genesis_node.reissue(ReissueRequest {
+            transaction: ReissueTransaction {
+                inputs: HashSet::from_iter([Dbc {
+                    content: input_content,
+                    transaction: DbcTransaction::new(
+                        Default::default(),
                         input_content_hashes.clone(),
-                        100,
-                        crate::bls_dkg_id().public_key_set.public_key(),
-                        DbcContent::random_blinding_factor(),
-                    )?]),
-                },
-                input_ownership_proofs: HashMap::default(),
+                    ),
+                    transaction_sigs: Default::default(),
+                }]),
+                outputs: HashSet::from_iter([DbcContent::new(
+                    input_content_hashes.clone(),
+                    100,
+                    crate::bls_dkg_id().public_key_set.public_key(),
+                    DbcContent::random_blinding_factor(),
+                )?]),
+                aggregated_range_proof: None,
+                excess_commitments: Default::default(),
+                fee: 0,
+                asset_surjection_proofs: Default::default(),
             },
-            input_content_hashes,
-        );
+            input_ownership_proofs: HashMap::default(),
+            input_spending_proofs: Default::default(),
+        });
         assert!(fraudulant_reissue_result.is_err());
 
         Ok(())
@@ -942,14 +1871,14 @@ mod tests {
     ///
     /// 1. produce a standard genesis DBC with value 1000
     /// 2. reissue genesis DBC to an output with mis-matched amounts where the
-    ///      committed amount is 1000 (required to match input) but the secret
-    ///      amount is 2000.
+    ///    committed amount is 1000 (required to match input) but the secret
+    ///    amount is 2000.
     /// 3. Check if the amounts match, using the two provided APIs.
-    ///      assert that APIs report they do not match.
+    ///    assert that APIs report they do not match.
     /// 4. Attempt to reissue the mis-matched output using the amount from
-    ///      AmountSecrets.  Verify that this fails with error DbcReissueRequestDoesNotBalance
+    ///    AmountSecrets.  Verify that this fails with error DbcReissueRequestDoesNotBalance
     /// 5. Attempt to reissue using the correct amount that was committed to.
-    ///      Verify that this reissue succeeds.
+    ///    Verify that this reissue succeeds.
     #[test]
     fn test_mismatched_amount_and_commitment() -> Result<(), Error> {
         // ----------
@@ -986,10 +1915,7 @@ mod tests {
 
         let (mut transaction, _) = crate::TransactionBuilder::default()
             .add_input(genesis_dbc.clone(), genesis_secrets)
-            .add_output(crate::Output {
-                amount: output_amount,
-                owner: outputs_owner_pk,
-            })
+            .add_output(crate::Output::new(output_amount, outputs_owner_pk))
             .build()?;
 
         // ----------
@@ -1010,7 +1936,7 @@ mod tests {
         // twice the committed value.
         let fudged_amount_secrets = crate::AmountSecrets {
             amount: secrets.amount * 2, // Claim we are paying twice the committed value
-            blinding_factor: secrets.blinding_factor, // Use the real blinding factor
+            ..secrets                   // Use the real blinding factor
         };
 
         out_dbc_content.amount_secrets_cipher =
@@ -1034,6 +1960,7 @@ mod tests {
                 genesis_dbc.name(),
                 (genesis_node.key_manager.public_key_set()?.public_key(), sig),
             )]),
+            input_spending_proofs: Default::default(),
         };
 
         // The mint should reissue this without error because the output commitment sum matches the
@@ -1042,10 +1969,7 @@ mod tests {
         // or guess it.  And that's assuming the secret blinding_factor is correct, which it is in this
         // case, but might not be in the wild.  So the output DBC could be considered to be in a
         // semi-unspendable state.
-        let reissue_share = genesis_node.reissue(
-            reissue_req.clone(),
-            BTreeSet::from_iter([genesis_dbc.name()]),
-        )?;
+        let reissue_share = genesis_node.reissue(reissue_req.clone())?;
 
         // Aggregate ReissueShare to build output DBCs
         let mut dbc_builder = DbcBuilder::new(reissue_req.transaction);
@@ -1097,15 +2021,12 @@ mod tests {
 
         let (transaction, _) = crate::TransactionBuilder::default()
             .add_input(input_dbc.clone(), input_secrets)
-            .add_output(crate::Output {
-                amount: input_secrets.amount,
-                owner: outputs_owner_pk,
-            })
+            .add_output(crate::Output::new(input_secrets.amount, outputs_owner_pk))
             .build()?;
 
         let sig_share = outputs_owner
             .secret_key_share
-            .sign(&transaction.blinded().hash());
+            .sign(transaction.blinded().hash());
 
         let sig = outputs_owner
             .public_key_set
@@ -1117,10 +2038,11 @@ mod tests {
                 input_dbc.name(),
                 (outputs_owner.public_key_set.public_key(), sig),
             )]),
+            input_spending_proofs: Default::default(),
         };
 
         // The mint should give an error on reissue because the sum(inputs) does not equal sum(outputs)
-        let result = genesis_node.reissue(reissue_req, BTreeSet::from_iter([input_dbc.name()]));
+        let result = genesis_node.reissue(reissue_req);
         match result {
             Err(Error::DbcReissueRequestDoesNotBalance) => {}
             _ => panic!("Expecting Error::DbcReissueRequestDoesNotBalance"),
@@ -1132,15 +2054,12 @@ mod tests {
 
         let (transaction, _) = crate::TransactionBuilder::default()
             .add_input(input_dbc.clone(), input_secrets)
-            .add_output(crate::Output {
-                amount: output_amount,
-                owner: outputs_owner_pk,
-            })
+            .add_output(crate::Output::new(output_amount, outputs_owner_pk))
             .build()?;
 
         let sig_share = outputs_owner
             .secret_key_share
-            .sign(&transaction.blinded().hash());
+            .sign(transaction.blinded().hash());
 
         let sig = outputs_owner
             .public_key_set
@@ -1152,10 +2071,11 @@ mod tests {
                 input_dbc.name(),
                 (outputs_owner.public_key_set.public_key(), sig),
             )]),
+            input_spending_proofs: Default::default(),
         };
 
         // The mint should reissue without error because the sum(inputs) does equal sum(outputs)
-        let result = genesis_node.reissue(reissue_req, BTreeSet::from_iter([input_dbc.name()]));
+        let result = genesis_node.reissue(reissue_req);
         assert!(result.is_ok());
 
         Ok(())
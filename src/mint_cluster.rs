@@ -0,0 +1,475 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use blsttc::SecretKeySet;
+use std::iter::FromIterator;
+
+use crate::{
+    asset_issuance_input, Amount, AmountSecrets, AssetId, Dbc, DbcBuilder, DbcContent, EpochSource,
+    Error, GenesisDbcProposal, Hash, KeyManager, Mint, NodeSignature, Output, OwnerSigner,
+    PublicKey, PublicKeySet, ReissueRequest, ReissueRequestBuilder, ReissueShare, Result,
+    Signature, SimpleKeyManager, SimpleSigner, SimpleSpendBook, SpendBook, TransactionBuilder,
+    NATIVE_ASSET,
+};
+
+/// How a node of a MintCluster responds to requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MintNodeState {
+    /// Handles requests and signs honestly.
+    Online,
+    /// Does not respond to requests.
+    Offline,
+    /// Handles requests, but returns signature shares over the wrong message.
+    Faulty,
+}
+
+/// N mint nodes in a single process, sharing the PublicKeySet of one SecretKeySet.
+///
+/// Requests are fanned out to every node which responds, and the resulting
+/// signature shares combined, just as a client of a real multi-node mint would.
+/// Since the cluster holds the SecretKeySet, it can also act as owner of the
+/// DBCs owned by the mint's key, such as the genesis DBC.
+pub struct MintCluster<S: SpendBook = SimpleSpendBook> {
+    secret_key_set: SecretKeySet,
+    nodes: Vec<(Mint<SimpleKeyManager, S>, MintNodeState)>,
+}
+
+impl MintCluster<SimpleSpendBook> {
+    /// A cluster of `n_nodes` with a random SecretKeySet, needing `threshold` + 1
+    /// nodes to sign.
+    pub fn random(n_nodes: usize, threshold: usize) -> Self {
+        let secret_key_set = SecretKeySet::random(threshold, &mut rand::thread_rng());
+        Self::from_secret_key_set(secret_key_set, n_nodes, |_| SimpleSpendBook::new())
+    }
+}
+
+impl<S: SpendBook> MintCluster<S> {
+    /// A cluster of `n_nodes`, node i signing with share i of `secret_key_set`
+    /// and logging spends to the spendbook made by `mk_spendbook(i)`.
+    pub fn from_secret_key_set(
+        secret_key_set: SecretKeySet,
+        n_nodes: usize,
+        mut mk_spendbook: impl FnMut(u64) -> S,
+    ) -> Self {
+        let public_key_set = secret_key_set.public_keys();
+        let genesis_key = public_key_set.public_key();
+        let nodes = Vec::from_iter((0..n_nodes as u64).map(|i| {
+            let key_manager = SimpleKeyManager::new(
+                SimpleSigner::new(
+                    public_key_set.clone(),
+                    (i, secret_key_set.secret_key_share(i)),
+                ),
+                genesis_key,
+            );
+            let mint = Mint::new(key_manager, mk_spendbook(i));
+            (mint, MintNodeState::Online)
+        }));
+
+        Self {
+            secret_key_set,
+            nodes,
+        }
+    }
+
+    pub fn public_key_set(&self) -> PublicKeySet {
+        self.secret_key_set.public_keys()
+    }
+
+    pub fn secret_key_set(&self) -> &SecretKeySet {
+        &self.secret_key_set
+    }
+
+    /// The number of signature shares needed to sign is threshold() + 1.
+    pub fn threshold(&self) -> usize {
+        self.secret_key_set.threshold()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, index: usize) -> Option<&Mint<SimpleKeyManager, S>> {
+        self.nodes.get(index).map(|(mint, _)| mint)
+    }
+
+    pub fn node_mut(&mut self, index: usize) -> Option<&mut Mint<SimpleKeyManager, S>> {
+        self.nodes.get_mut(index).map(|(mint, _)| mint)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Mint<SimpleKeyManager, S>> {
+        self.nodes.iter().map(|(mint, _)| mint)
+    }
+
+    pub fn node_state(&self, index: usize) -> Option<MintNodeState> {
+        self.nodes.get(index).map(|(_, state)| *state)
+    }
+
+    pub fn set_node_state(&mut self, index: usize, state: MintNodeState) -> Result<()> {
+        let node = self
+            .nodes
+            .get_mut(index)
+            .ok_or_else(|| Error::InvalidOperation(format!("no mint node with index {}", index)))?;
+        node.1 = state;
+        Ok(())
+    }
+
+    /// Give every node the same epoch source, see Mint::set_epoch_source().
+    pub fn set_epoch_source(&mut self, epoch_source: impl EpochSource + Clone + 'static) {
+        for (mint, _) in self.nodes.iter_mut() {
+            mint.set_epoch_source(epoch_source.clone());
+        }
+    }
+
+    /// Issues the genesis DBC, owned by the mint's key.
+    ///
+    /// The cluster proposes the genesis DBC, which every responding node validates
+    /// and signs, see GenesisDbcProposal.
+    pub fn issue_genesis_dbc(&mut self, amount: Amount) -> Result<Dbc> {
+        self.issue_asset_dbc(NATIVE_ASSET, amount)
+    }
+
+    /// Issues the genesis DBC of `output.amount`, and reissues it to `output`, eg. for a
+    /// DBC with a time lock or spending condition.  Returns the output DBC and its
+    /// AmountSecrets.
+    pub fn issue_genesis_to(&mut self, output: Output) -> Result<(Dbc, AmountSecrets)> {
+        let genesis = self.issue_genesis_dbc(output.amount)?;
+        let genesis_name = genesis.name();
+        let genesis_secrets = self.decrypt_amount_secrets(&genesis.content)?;
+        let (transaction, receipt) = TransactionBuilder::default()
+            .add_input(genesis, genesis_secrets)
+            .add_output(output)
+            .build()?;
+        let reissue_req = ReissueRequestBuilder::new(transaction)
+            .add_signer(
+                genesis_name,
+                OwnerSigner::SecretKeySet(self.secret_key_set.clone()),
+            )
+            .build()?;
+        let dbc = self.reissue(reissue_req)?.remove(0);
+        let amount_secrets = receipt.output_amount_secrets[&dbc.name()];
+        Ok((dbc, amount_secrets))
+    }
+
+    /// Issues `amount` of `asset`, owned by the mint's key.  Each asset may be issued once.
+    ///
+    /// Like reissue(), every responding node is asked to sign, and refusals and shares
+    /// from faulty nodes are tolerated so long as threshold() + 1 nodes sign.
+    pub fn issue_asset_dbc(&mut self, asset: AssetId, amount: Amount) -> Result<Dbc> {
+        let public_key_set = self.public_key_set();
+        let proposal = GenesisDbcProposal::new_asset(asset, amount, public_key_set.public_key())?;
+        let msg_hash = proposal
+            .transaction
+            .input_sig_hash(&asset_issuance_input(&asset));
+
+        let mut sig_shares = Vec::new();
+        let mut first_refusal = None;
+        for (mint, state) in self.responding_nodes()? {
+            let sig = mint
+                .sign_asset_issuance(asset, amount, &proposal)
+                .and_then(|(_, sig)| Self::node_sig(mint, state, &msg_hash, sig));
+            match sig {
+                Ok(sig) => sig_shares.push(sig),
+                Err(e) => {
+                    first_refusal.get_or_insert(e);
+                }
+            }
+        }
+
+        proposal
+            .into_dbc(&public_key_set, sig_shares)
+            .map_err(|e| first_refusal.unwrap_or(e))
+    }
+
+    /// Reissues on every responding node, combining their shares into the output DBCs.
+    ///
+    /// Nothing is sent to any node unless at least threshold() + 1 nodes respond,
+    /// so a reissue which cannot succeed does not spend the inputs.  Every responding
+    /// node is sent the request, even once one has refused it, and shares from faulty
+    /// nodes are discarded, see DbcBuilder::build_robust().
+    ///
+    /// If fewer than threshold() + 1 nodes give a good share, the error of the first
+    /// node to refuse is returned.  The inputs are then left spent on the nodes which
+    /// did not refuse, so retrying will not mint the outputs.
+    pub fn reissue(&mut self, reissue_req: ReissueRequest) -> Result<Vec<Dbc>> {
+        let transaction = reissue_req.transaction.blinded();
+
+        let mut dbc_builder = DbcBuilder::new(reissue_req.transaction.clone());
+        let mut first_refusal = None;
+        for (mint, state) in self.responding_nodes()? {
+            let reissue_share = mint.reissue(reissue_req.clone()).and_then(|reissue_share| {
+                let mint_node_signatures = reissue_share
+                    .mint_node_signatures
+                    .into_iter()
+                    .map(|(input, (public_key_set, sig))| {
+                        let msg_hash = transaction.input_sig_hash(&input);
+                        let sig = Self::node_sig(mint, state, &msg_hash, sig)?;
+                        Ok((input, (public_key_set, sig)))
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(ReissueShare {
+                    dbc_transaction: reissue_share.dbc_transaction,
+                    mint_node_signatures,
+                })
+            });
+            match reissue_share {
+                Ok(reissue_share) => dbc_builder = dbc_builder.add_reissue_share(reissue_share),
+                Err(e) => {
+                    first_refusal.get_or_insert(e);
+                }
+            }
+        }
+
+        let (output_dbcs, _misbehaving_nodes, _forged_shares) = dbc_builder
+            .build_robust(&self.public_key_set())
+            .map_err(|e| first_refusal.unwrap_or(e))?;
+
+        Ok(output_dbcs)
+    }
+
+    /// Decrypts the AmountSecrets of a DBC owned by the mint's key, eg. the genesis DBC.
+    pub fn decrypt_amount_secrets(&self, content: &DbcContent) -> Result<AmountSecrets> {
+        content.amount_secrets_by_secret_key_set(&self.secret_key_set)
+    }
+
+    /// Signs `msg_hash` as the owner of DBCs owned by the mint's key, eg. to
+    /// give the input ownership proof for spending the genesis DBC.
+    pub fn sign_as_owner(&self, msg_hash: &Hash) -> (PublicKey, Signature) {
+        (
+            self.secret_key_set.public_keys().public_key(),
+            self.secret_key_set.secret_key().sign(msg_hash),
+        )
+    }
+
+    // The nodes which respond to requests, or an error if too few respond to sign.
+    fn responding_nodes(&mut self) -> Result<Vec<(&mut Mint<SimpleKeyManager, S>, MintNodeState)>> {
+        let required = self.threshold() + 1;
+        let responding = Vec::from_iter(
+            self.nodes
+                .iter_mut()
+                .filter(|(_, state)| *state != MintNodeState::Offline)
+                .map(|(mint, state)| (mint, *state)),
+        );
+
+        if responding.len() < required {
+            return Err(Error::NotEnoughMintNodes {
+                responding: responding.len(),
+                required,
+            });
+        }
+        Ok(responding)
+    }
+
+    // The signature share a node in `state` hands back for `msg_hash`.
+    fn node_sig(
+        mint: &Mint<SimpleKeyManager, S>,
+        state: MintNodeState,
+        msg_hash: &Hash,
+        sig: NodeSignature,
+    ) -> Result<NodeSignature> {
+        match state {
+            MintNodeState::Faulty => {
+                let mut wrong_msg = *msg_hash;
+                wrong_msg.0[0] ^= 1;
+                mint.key_manager()
+                    .sign(&wrong_msg)
+                    .map_err(|e| Error::Signing(e.to_string()))
+            }
+            _ => Ok(sig),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Output, TransactionBuilder, GENESIS_DBC_INPUT};
+    use std::collections::{BTreeSet, HashMap};
+
+    fn split_genesis(cluster: &MintCluster, genesis: Dbc, amounts: &[Amount]) -> ReissueRequest {
+        let amount_secrets = cluster.decrypt_amount_secrets(&genesis.content).unwrap();
+        let owner = crate::bls_dkg_id().public_key_set.public_key();
+        let gen_dbc_name = genesis.name();
+        let (transaction, _) = TransactionBuilder::default()
+            .add_input(genesis, amount_secrets)
+            .add_outputs(amounts.iter().map(|amount| Output::new(*amount, owner)))
+            .build()
+            .unwrap();
+        let ownership_proof = cluster.sign_as_owner(&transaction.blinded().hash());
+
+        ReissueRequest {
+            transaction,
+            input_ownership_proofs: HashMap::from_iter([(gen_dbc_name, ownership_proof)]),
+            input_spending_proofs: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_cluster_issues_and_reissues_dbcs() -> Result<()> {
+        let mut cluster = MintCluster::random(4, 2);
+        assert_eq!(cluster.len(), 4);
+        assert_eq!(cluster.threshold(), 2);
+
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        for node in cluster.nodes() {
+            genesis.confirm_valid(node.key_manager())?;
+            assert_eq!(
+                node.spendbook.lookup(&GENESIS_DBC_INPUT)?,
+                Some(&genesis.transaction)
+            );
+        }
+        assert_eq!(
+            cluster.decrypt_amount_secrets(&genesis.content)?.amount,
+            1000
+        );
+
+        let gen_dbc_name = genesis.name();
+        let reissue_req = split_genesis(&cluster, genesis, &[600, 400]);
+        let output_dbcs = cluster.reissue(reissue_req)?;
+
+        assert_eq!(output_dbcs.len(), 2);
+        for node in cluster.nodes() {
+            assert!(node.is_spent(gen_dbc_name)?);
+            for dbc in output_dbcs.iter() {
+                dbc.confirm_valid(node.key_manager())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cluster_tolerates_offline_nodes_up_to_threshold() -> Result<()> {
+        let mut cluster = MintCluster::random(4, 1);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let gen_dbc_name = genesis.name();
+        let reissue_req = split_genesis(&cluster, genesis, &[1000]);
+
+        cluster.set_node_state(0, MintNodeState::Offline)?;
+        cluster.set_node_state(2, MintNodeState::Offline)?;
+        assert_eq!(cluster.node_state(2), Some(MintNodeState::Offline));
+        assert_eq!(cluster.reissue(reissue_req.clone())?.len(), 1);
+
+        // Offline nodes are not asked to spend the input.
+        let spent = BTreeSet::from_iter(
+            cluster
+                .nodes()
+                .map(|node| node.is_spent(gen_dbc_name))
+                .collect::<Result<Vec<_>>>()?,
+        );
+        assert_eq!(spent, BTreeSet::from_iter([false, true]));
+        assert!(!cluster.node(0).unwrap().is_spent(gen_dbc_name)?);
+
+        // With one more node offline, too few remain to sign.
+        cluster.set_node_state(3, MintNodeState::Offline)?;
+        assert!(matches!(
+            cluster.reissue(reissue_req),
+            Err(Error::NotEnoughMintNodes {
+                responding: 1,
+                required: 2
+            })
+        ));
+        assert!(matches!(
+            cluster.set_node_state(4, MintNodeState::Online),
+            Err(Error::InvalidOperation(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cluster_tolerates_faulty_nodes_up_to_threshold() -> Result<()> {
+        let mut cluster = MintCluster::random(4, 1);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let reissue_req = split_genesis(&cluster, genesis, &[1000]);
+
+        cluster.set_node_state(0, MintNodeState::Faulty)?;
+        cluster.set_node_state(2, MintNodeState::Faulty)?;
+        let output_dbcs = cluster.reissue(reissue_req)?;
+        output_dbcs[0].confirm_valid(cluster.node(0).unwrap().key_manager())?;
+
+        let mut cluster = MintCluster::random(4, 1);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let reissue_req = split_genesis(&cluster, genesis, &[1000]);
+
+        for index in 0..3 {
+            cluster.set_node_state(index, MintNodeState::Faulty)?;
+        }
+        match cluster.reissue(reissue_req) {
+            // A faulty node's signatures do not verify as its own, so it is not blamed.
+            Err(Error::NotEnoughValidReissueShares {
+                valid: 1,
+                required: 2,
+                misbehaving_nodes,
+                forged_shares: 3,
+            }) => assert!(misbehaving_nodes.is_empty()),
+            result => panic!("Unexpected reissue result {:?}", result),
+        }
+
+        // Genesis shares are filtered the same way.
+        let mut cluster = MintCluster::random(3, 1);
+        cluster.set_node_state(1, MintNodeState::Faulty)?;
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        genesis.confirm_valid(cluster.node(1).unwrap().key_manager())?;
+
+        cluster.set_node_state(0, MintNodeState::Faulty)?;
+        match cluster.issue_asset_dbc(rand::random(), 1000) {
+            Err(Error::NotEnoughValidReissueShares {
+                valid: 1,
+                required: 2,
+                misbehaving_nodes,
+                forged_shares: 2,
+            }) => assert!(misbehaving_nodes.is_empty()),
+            result => panic!("Unexpected issuance result {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cluster_asks_every_node_despite_refusals() -> Result<()> {
+        let mut cluster = MintCluster::random(4, 1);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let gen_dbc_name = genesis.name();
+        let reissue_req = split_genesis(&cluster, genesis, &[1000]);
+
+        // Node 0 already spent the input, so refuses, but the other nodes still
+        // spend it and give enough shares.
+        cluster.node_mut(0).unwrap().reissue(reissue_req.clone())?;
+        let output_dbcs = cluster.reissue(reissue_req)?;
+        output_dbcs[0].confirm_valid(cluster.node(0).unwrap().key_manager())?;
+        for node in cluster.nodes() {
+            assert!(node.is_spent(gen_dbc_name)?);
+        }
+
+        // When too few nodes give a share, the first refusal is returned, and the
+        // input is left spent on the nodes which did not refuse.
+        let mut cluster = MintCluster::random(4, 1);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let gen_dbc_name = genesis.name();
+        let reissue_req = split_genesis(&cluster, genesis, &[1000]);
+
+        for index in 0..3 {
+            cluster
+                .node_mut(index)
+                .unwrap()
+                .reissue(reissue_req.clone())?;
+        }
+        assert!(!cluster.node(3).unwrap().is_spent(gen_dbc_name)?);
+        assert!(matches!(
+            cluster.reissue(reissue_req),
+            Err(Error::DbcAlreadySpent(_))
+        ));
+        assert!(cluster.node(3).unwrap().is_spent(gen_dbc_name)?);
+
+        Ok(())
+    }
+}
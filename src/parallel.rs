@@ -0,0 +1,108 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Helpers which run independent checks on the rayon thread pool when the
+//! `parallel` feature is enabled, and sequentially otherwise.
+//!
+//! Either way the outcome is the same: every check is expected to be independent,
+//! and when several fail the error reported is that of the first in order, so the
+//! same bad request always gets the same error regardless of thread scheduling.
+
+use crate::Result;
+
+/// `Sync` when the `parallel` feature is enabled, so a value may be shared with
+/// the rayon thread pool, and implemented by every type otherwise.
+#[cfg(feature = "parallel")]
+pub trait MaybeSync: Sync {}
+
+#[cfg(feature = "parallel")]
+impl<T: Sync + ?Sized> MaybeSync for T {}
+
+/// `Sync` when the `parallel` feature is enabled, so a value may be shared with
+/// the rayon thread pool, and implemented by every type otherwise.
+#[cfg(not(feature = "parallel"))]
+pub trait MaybeSync {}
+
+#[cfg(not(feature = "parallel"))]
+impl<T: ?Sized> MaybeSync for T {}
+
+/// Runs `check` over each of `items`, returning the error of the first failing item.
+#[cfg(feature = "parallel")]
+pub(crate) fn check_all<T, F>(items: &[T], check: F) -> Result<()>
+where
+    T: Sync,
+    F: Fn(&T) -> Result<()> + Sync + Send,
+{
+    use rayon::prelude::*;
+
+    let results: Vec<Result<()>> = items.par_iter().map(check).collect();
+    results.into_iter().collect()
+}
+
+/// Runs `check` over each of `items`, returning the error of the first failing item.
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn check_all<T, F>(items: &[T], check: F) -> Result<()>
+where
+    F: Fn(&T) -> Result<()>,
+{
+    items.iter().try_for_each(check)
+}
+
+/// Runs both checks, returning the error of `a` if it fails, else that of `b`.
+#[cfg(feature = "parallel")]
+pub(crate) fn check_both<A, B>(a: A, b: B) -> Result<()>
+where
+    A: FnOnce() -> Result<()> + Send,
+    B: FnOnce() -> Result<()> + Send,
+{
+    let (a, b) = rayon::join(a, b);
+    a.and(b)
+}
+
+/// Runs both checks, returning the error of `a` if it fails, else that of `b`.
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn check_both<A, B>(a: A, b: B) -> Result<()>
+where
+    A: FnOnce() -> Result<()>,
+    B: FnOnce() -> Result<()>,
+{
+    a()?;
+    b()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_check_all_reports_first_error_in_order() {
+        let items: Vec<usize> = (0..100).collect();
+        let result = check_all(&items, |i| match i % 7 {
+            3 => Err(Error::InvalidOperation(i.to_string())),
+            _ => Ok(()),
+        });
+        assert!(matches!(result, Err(Error::InvalidOperation(i)) if i == "3"));
+
+        assert!(check_all(&items, |_| Ok(())).is_ok());
+        assert!(check_all(&[] as &[usize], |_| Err(Error::UnknownInput)).is_ok());
+    }
+
+    #[test]
+    fn test_check_both_prefers_first_error() {
+        assert!(matches!(
+            check_both(|| Err(Error::UnknownInput), || Err(Error::FailedSignature)),
+            Err(Error::UnknownInput)
+        ));
+        assert!(matches!(
+            check_both(|| Ok(()), || Err(Error::FailedSignature)),
+            Err(Error::FailedSignature)
+        ));
+        assert!(check_both(|| Ok(()), || Ok(())).is_ok());
+    }
+}
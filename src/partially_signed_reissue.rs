@@ -0,0 +1,314 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use blsttc::SignatureShare;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::iter::FromIterator;
+
+use crate::{
+    Error, Hash, OwnerSigner, PublicKey, PublicKeySet, ReissueRequest, ReissueRequestBuilder,
+    ReissueTransaction, Result, Signature, SpendingCondition,
+};
+
+/// A ReissueRequest in the making, passed between the owners of its inputs.
+///
+/// Each owner adds their signature, or their share of a threshold owner key's
+/// signature, then passes it on or hands it back to be merged with the others'.
+/// Every signature and share is checked as it is added or merged, so once no
+/// inputs are missing a proof the request can be finalized.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PartiallySignedReissue {
+    pub transaction: ReissueTransaction,
+    pub input_ownership_proofs: HashMap<Hash, (PublicKey, Signature)>,
+    /// Signature shares for inputs owned by a threshold key, until there are enough to combine.
+    pub input_signature_shares: HashMap<Hash, (PublicKeySet, BTreeMap<usize, SignatureShare>)>,
+}
+
+impl PartiallySignedReissue {
+    pub fn new(transaction: ReissueTransaction) -> Self {
+        Self {
+            transaction,
+            input_ownership_proofs: Default::default(),
+            input_signature_shares: Default::default(),
+        }
+    }
+
+    /// Add the owner's signature over the transaction for `input`.
+    pub fn add_signature(&mut self, input: Hash, owner: PublicKey, sig: Signature) -> Result<()> {
+        let msg_hash = self.transaction.blinded().hash();
+        if !owner.verify(&sig, msg_hash) {
+            return Err(Error::FailedSignature);
+        }
+        self.input(&input)?
+            .content
+            .validate_spender(&SpendingCondition::Key(owner))?;
+
+        self.input_signature_shares.remove(&input);
+        self.input_ownership_proofs.insert(input, (owner, sig));
+        Ok(())
+    }
+
+    /// Add shares of the signature over the transaction for `input`, by holders of
+    /// shares of the `owner` key set.  Once there are threshold + 1 they are combined.
+    pub fn add_signature_shares(
+        &mut self,
+        input: Hash,
+        owner: PublicKeySet,
+        sig_shares: impl IntoIterator<Item = (usize, SignatureShare)>,
+    ) -> Result<()> {
+        if self.input_ownership_proofs.contains_key(&input) {
+            // Already signed, more shares add nothing.
+            return Ok(());
+        }
+        self.input(&input)?
+            .content
+            .validate_spender(&SpendingCondition::Key(owner.public_key()))?;
+
+        let msg_hash = self.transaction.blinded().hash();
+        let sig_shares = BTreeMap::from_iter(sig_shares);
+        if sig_shares
+            .iter()
+            .any(|(index, share)| !owner.public_key_share(*index).verify(share, msg_hash))
+        {
+            return Err(Error::FailedSignature);
+        }
+
+        let (known_owner, known_shares) = self
+            .input_signature_shares
+            .entry(input)
+            .or_insert_with(|| (owner.clone(), Default::default()));
+        if *known_owner != owner {
+            return Err(Error::InputOwnerKeySetMismatch(input));
+        }
+        known_shares.extend(sig_shares);
+
+        if known_shares.len() > owner.threshold() {
+            let sig = owner.combine_signatures(&*known_shares)?;
+            self.add_signature(input, owner.public_key(), sig)?;
+        }
+        Ok(())
+    }
+
+    /// Merge in the signatures and shares collected by another owner, checking each.
+    pub fn merge(&mut self, other: Self) -> Result<()> {
+        if other.transaction != self.transaction {
+            return Err(Error::PartiallySignedReissueMismatch);
+        }
+        for (input, (owner, sig)) in other.input_ownership_proofs {
+            self.add_signature(input, owner, sig)?;
+        }
+        for (input, (owner, sig_shares)) in other.input_signature_shares {
+            self.add_signature_shares(input, owner, sig_shares)?;
+        }
+        Ok(())
+    }
+
+    /// The inputs which do not yet have an ownership proof.
+    pub fn missing_inputs(&self) -> BTreeSet<Hash> {
+        BTreeSet::from_iter(
+            self.transaction
+                .inputs
+                .iter()
+                .map(|input| input.name())
+                .filter(|name| !self.input_ownership_proofs.contains_key(name)),
+        )
+    }
+
+    /// How many more signature shares `input` needs, if any shares have been added for it.
+    pub fn shares_needed(&self, input: &Hash) -> Option<usize> {
+        self.input_signature_shares
+            .get(input)
+            .map(|(owner, sig_shares)| owner.threshold() + 1 - sig_shares.len())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing_inputs().is_empty()
+    }
+
+    /// The ReissueRequest, once every input has an ownership proof.
+    pub fn finalize(self) -> Result<ReissueRequest> {
+        ReissueRequestBuilder::new(self.transaction)
+            .add_signers(
+                self.input_ownership_proofs
+                    .into_iter()
+                    .map(|(input, (owner, sig))| (input, OwnerSigner::Signature(owner, sig))),
+            )
+            .build()
+    }
+
+    fn input(&self, name: &Hash) -> Result<&crate::Dbc> {
+        self.transaction
+            .inputs
+            .iter()
+            .find(|input| &input.name() == name)
+            .ok_or(Error::UnknownInput)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MintCluster, Output, TransactionBuilder};
+    use blsttc::SecretKeySet;
+
+    #[test]
+    fn test_owners_sign_separately_and_merge() -> Result<()> {
+        let mut cluster = MintCluster::random(1, 0);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let genesis_name = genesis.name();
+        let genesis_secrets = cluster.decrypt_amount_secrets(&genesis.content)?;
+
+        // One input owned by alice alone, the other by a 2 of 3 group.
+        let alice = crate::bls_dkg_id();
+        let group = SecretKeySet::random(1, &mut rand::thread_rng());
+        let (split_tx, split_receipt) = TransactionBuilder::default()
+            .add_input(genesis, genesis_secrets)
+            .add_output(Output::new(400, alice.public_key_set.public_key()))
+            .add_output(Output::new(600, group.public_keys().public_key()))
+            .build()?;
+        let split_req = ReissueRequestBuilder::new(split_tx)
+            .add_signer(
+                genesis_name,
+                OwnerSigner::SecretKeySet(cluster.secret_key_set().clone()),
+            )
+            .build()?;
+        let split_dbcs = cluster.reissue(split_req)?;
+        let owned_by = |owner: PublicKey| {
+            split_dbcs
+                .iter()
+                .find(|dbc| split_receipt.output_owners[&dbc.name()] == owner)
+                .unwrap()
+                .name()
+        };
+        let alice_input = owned_by(alice.public_key_set.public_key());
+        let group_input = owned_by(group.public_keys().public_key());
+
+        let (merge_tx, _) = TransactionBuilder::default()
+            .add_inputs(split_dbcs.iter().cloned().map(|dbc| {
+                let secrets = split_receipt.output_amount_secrets[&dbc.name()];
+                (dbc, secrets)
+            }))
+            .add_output(Output::new(
+                1000,
+                crate::bls_dkg_id().public_key_set.public_key(),
+            ))
+            .build()?;
+        let msg_hash = merge_tx.blinded().hash();
+        let unsigned = PartiallySignedReissue::new(merge_tx);
+        assert_eq!(
+            unsigned.missing_inputs(),
+            BTreeSet::from_iter([alice_input, group_input])
+        );
+
+        // Each party signs their own copy.
+        let mut by_alice = unsigned.clone();
+        let alice_sig = alice
+            .public_key_set
+            .combine_signatures([(alice.index, &alice.secret_key_share.sign(msg_hash))])?;
+        by_alice.add_signature(alice_input, alice.public_key_set.public_key(), alice_sig)?;
+
+        let group_member = |i: usize| -> Result<PartiallySignedReissue> {
+            let mut by_member = unsigned.clone();
+            by_member.add_signature_shares(
+                group_input,
+                group.public_keys(),
+                [(i, group.secret_key_share(i).sign(msg_hash))],
+            )?;
+            Ok(by_member)
+        };
+        let by_member_0 = group_member(0)?;
+        let by_member_2 = group_member(2)?;
+        assert_eq!(by_member_0.shares_needed(&group_input), Some(1));
+
+        // Passed around serialised, and merged.
+        let roundtrip = |psr: &PartiallySignedReissue| -> PartiallySignedReissue {
+            bincode::deserialize(&bincode::serialize(psr).unwrap()).unwrap()
+        };
+        let mut collected = roundtrip(&by_alice);
+        collected.merge(roundtrip(&by_member_0))?;
+        assert_eq!(
+            collected.missing_inputs(),
+            BTreeSet::from_iter([group_input])
+        );
+        assert!(matches!(
+            collected.clone().finalize(),
+            Err(Error::MissingInputOwnerProof)
+        ));
+
+        collected.merge(roundtrip(&by_member_2))?;
+        assert!(collected.is_complete());
+        assert_eq!(collected.shares_needed(&group_input), None);
+
+        let merge_dbcs = cluster.reissue(collected.finalize()?)?;
+        assert_eq!(merge_dbcs.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_signatures_are_not_merged() -> Result<()> {
+        let mut cluster = MintCluster::random(1, 0);
+        let genesis = cluster.issue_genesis_dbc(1000)?;
+        let genesis_name = genesis.name();
+        let genesis_secrets = cluster.decrypt_amount_secrets(&genesis.content)?;
+        let (transaction, _) = TransactionBuilder::default()
+            .add_input(genesis, genesis_secrets)
+            .add_output(Output::new(
+                1000,
+                crate::bls_dkg_id().public_key_set.public_key(),
+            ))
+            .build()?;
+        let mint_keys = cluster.secret_key_set().clone();
+        let mut psr = PartiallySignedReissue::new(transaction.clone());
+
+        // A share over something else.
+        assert!(matches!(
+            psr.add_signature_shares(
+                genesis_name,
+                mint_keys.public_keys(),
+                [(0, mint_keys.secret_key_share(0).sign([1; 32]))],
+            ),
+            Err(Error::FailedSignature)
+        ));
+
+        // Someone else's signature.
+        let stranger = SecretKeySet::random(0, &mut rand::thread_rng()).secret_key();
+        let stranger_sig = stranger.sign(transaction.blinded().hash());
+        assert!(matches!(
+            psr.add_signature(genesis_name, stranger.public_key(), stranger_sig),
+            Err(Error::FailedUnblinding)
+        ));
+
+        // A signature for something which is not an input.
+        let (owner, sig) = cluster.sign_as_owner(&transaction.blinded().hash());
+        assert!(matches!(
+            psr.add_signature(Hash::from([1; 32]), owner, sig.clone()),
+            Err(Error::UnknownInput)
+        ));
+
+        // Nor is a tampered copy merged in.
+        let mut tampered = psr.clone();
+        tampered
+            .input_ownership_proofs
+            .insert(genesis_name, (owner, mint_keys.secret_key().sign([1; 32])));
+        assert!(matches!(psr.merge(tampered), Err(Error::FailedSignature)));
+
+        let (other_tx, _) = TransactionBuilder::default().build()?;
+        assert!(matches!(
+            psr.merge(PartiallySignedReissue::new(other_tx)),
+            Err(Error::PartiallySignedReissueMismatch)
+        ));
+
+        assert_eq!(psr.missing_inputs(), BTreeSet::from_iter([genesis_name]));
+        psr.add_signature(genesis_name, owner, sig)?;
+        psr.finalize()?;
+
+        Ok(())
+    }
+}
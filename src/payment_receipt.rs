@@ -0,0 +1,217 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+    AggregatedRangeProof, Amount, AmountSecrets, Dbc, DbcContentHash, DbcTransaction, Error,
+    KeyManager, PublicKey, Result, SpendingCondition,
+};
+
+/// The payer's record of the outputs of a transaction built by TransactionBuilder.
+///
+/// The output amounts are encrypted to the recipients, so without this the payer
+/// has no way to recover them.  With it the payer may give recipients their
+/// AmountSecrets out of band, and prove what was paid, see verify_payment().
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PaymentReceipt {
+    pub transaction: DbcTransaction,
+    pub output_owners: HashMap<DbcContentHash, PublicKey>,
+    pub output_amount_secrets: HashMap<DbcContentHash, AmountSecrets>,
+    /// The SpendingCondition of each output spendable on one instead of by its owner key.
+    #[serde(default)]
+    pub output_spending_conditions: HashMap<DbcContentHash, SpendingCondition>,
+    /// The AggregatedRangeProof of the outputs, if they share one.  It is not copied
+    /// into each output DBC, so recipients need it from here to verify their amount,
+    /// see Dbc::verify_full().
+    #[serde(default)]
+    pub aggregated_range_proof: Option<AggregatedRangeProof>,
+}
+
+impl PaymentReceipt {
+    /// The total paid to `owner` by the transaction.
+    pub fn amount_paid_to(&self, owner: &PublicKey) -> Amount {
+        self.output_owners
+            .iter()
+            .filter(|(_, output_owner)| *output_owner == owner)
+            .filter_map(|(output, _)| self.output_amount_secrets.get(output))
+            .map(|secrets| secrets.amount)
+            .sum()
+    }
+
+    /// Prove to anyone holding the output `dbc` that it was paid by this receipt's
+    /// transaction, and to whom and how much.
+    ///
+    /// The DBC must be signed by a mint key known to `verifier`, and its owner, or
+    /// spending condition if it has one, and amount commitment must match the receipt.
+    /// Returns the owner, to whom the amount is encrypted, and the amount paid.
+    pub fn verify_payment<K: KeyManager>(
+        &self,
+        dbc: &Dbc,
+        verifier: &K,
+    ) -> Result<(PublicKey, Amount)> {
+        let name = dbc.name();
+        if dbc.transaction != self.transaction {
+            return Err(Error::DbcNotInPaymentReceipt(name));
+        }
+        let (owner, amount_secrets) = match (
+            self.output_owners.get(&name),
+            self.output_amount_secrets.get(&name),
+        ) {
+            (Some(owner), Some(amount_secrets)) => (owner, amount_secrets),
+            _ => return Err(Error::DbcNotInPaymentReceipt(name)),
+        };
+
+        dbc.confirm_valid(verifier)?;
+        match self.output_spending_conditions.get(&name) {
+            Some(condition) => {
+                if condition.blinded_owner(&dbc.content.parents) != dbc.content.owner {
+                    return Err(Error::FailedUnblinding);
+                }
+            }
+            None => dbc.content.validate_unblinding(owner)?,
+        }
+        if !dbc
+            .content
+            .confirm_provided_amount_matches_commitment(amount_secrets)
+        {
+            return Err(Error::AmountSecretsDoNotMatchCommitment);
+        }
+
+        Ok((*owner, amount_secrets.amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbcHelper, MintCluster, Output, ReissueRequest, TransactionBuilder};
+    use std::iter::FromIterator;
+
+    #[test]
+    fn test_payment_receipt_proves_payment() -> Result<()> {
+        let mut mint = MintCluster::random(1, 0);
+        let genesis = mint.issue_genesis_dbc(1000)?;
+        let genesis_name = genesis.name();
+        let genesis_secrets = mint.decrypt_amount_secrets(&genesis.content)?;
+
+        let payee = crate::bls_dkg_id();
+        let payee_key = payee.public_key_set.public_key();
+        let change_key = crate::bls_dkg_id().public_key_set.public_key();
+        let (transaction, receipt) = TransactionBuilder::default()
+            .add_input(genesis, genesis_secrets)
+            .add_output(Output::new(300, payee_key))
+            .add_output(Output::new(700, change_key))
+            .build()?;
+        assert_eq!(receipt.transaction, transaction.blinded());
+        assert_eq!(receipt.amount_paid_to(&payee_key), 300);
+        assert_eq!(receipt.amount_paid_to(&change_key), 700);
+
+        let dbcs = mint.reissue(ReissueRequest {
+            input_ownership_proofs: HashMap::from_iter([(
+                genesis_name,
+                mint.sign_as_owner(&transaction.blinded().hash()),
+            )]),
+            transaction,
+            input_spending_proofs: Default::default(),
+        })?;
+        let verifier = mint.node(0).unwrap().key_manager();
+
+        for dbc in dbcs.iter() {
+            // The secrets in the receipt are those the owner decrypts.
+            let owner = receipt.output_owners[&dbc.name()];
+            if owner == payee_key {
+                assert_eq!(
+                    receipt.output_amount_secrets[&dbc.name()],
+                    DbcHelper::decrypt_amount_secrets(&payee, &dbc.content)?
+                );
+                assert_eq!(receipt.verify_payment(dbc, verifier)?, (payee_key, 300));
+            } else {
+                assert_eq!(receipt.verify_payment(dbc, verifier)?, (change_key, 700));
+            }
+        }
+
+        // A receipt claiming a different amount, or owner, proves nothing.
+        let payee_dbc = dbcs
+            .iter()
+            .find(|dbc| receipt.output_owners[&dbc.name()] == payee_key)
+            .unwrap();
+        let mut overstated = receipt.clone();
+        for secrets in overstated.output_amount_secrets.values_mut() {
+            secrets.amount += 1;
+        }
+        assert!(matches!(
+            overstated.verify_payment(payee_dbc, verifier),
+            Err(Error::AmountSecretsDoNotMatchCommitment)
+        ));
+
+        let mut other_owner = receipt.clone();
+        for owner in other_owner.output_owners.values_mut() {
+            *owner = change_key;
+        }
+        assert!(matches!(
+            other_owner.verify_payment(payee_dbc, verifier),
+            Err(Error::FailedUnblinding)
+        ));
+
+        let mut other_tx = receipt;
+        other_tx.transaction.outputs.clear();
+        assert!(matches!(
+            other_tx.verify_payment(payee_dbc, verifier),
+            Err(Error::DbcNotInPaymentReceipt(_))
+        ));
+
+        Ok(())
+    }
+    #[test]
+    fn test_payment_receipt_proves_hash_time_locked_payment() -> Result<()> {
+        let mut mint = MintCluster::random(1, 0);
+        let genesis = mint.issue_genesis_dbc(100)?;
+        let genesis_name = genesis.name();
+        let genesis_secrets = mint.decrypt_amount_secrets(&genesis.content)?;
+
+        let recipient = blsttc::SecretKey::random().public_key();
+        let lock = crate::HashTimeLock {
+            hash: crate::SpendingCondition::preimage_hash(b"the preimage"),
+            recipient,
+            refund: blsttc::SecretKey::random().public_key(),
+            refund_after: 10,
+        };
+        let (transaction, receipt) = TransactionBuilder::default()
+            .add_input(genesis, genesis_secrets)
+            .add_output(lock.output(100))
+            .build()?;
+        let dbcs = mint.reissue(ReissueRequest {
+            input_ownership_proofs: HashMap::from_iter([(
+                genesis_name,
+                mint.sign_as_owner(&transaction.blinded().hash()),
+            )]),
+            transaction,
+            input_spending_proofs: Default::default(),
+        })?;
+        let verifier = mint.node(0).unwrap().key_manager();
+
+        // The DBC is owned by its spending condition, so is checked against that.
+        assert_eq!(
+            receipt.verify_payment(&dbcs[0], verifier)?,
+            (recipient, 100)
+        );
+
+        let mut other_condition = receipt.clone();
+        for condition in other_condition.output_spending_conditions.values_mut() {
+            *condition = crate::SpendingCondition::Key(recipient);
+        }
+        assert!(matches!(
+            other_condition.verify_payment(&dbcs[0], verifier),
+            Err(Error::FailedUnblinding)
+        ));
+
+        Ok(())
+    }
+}
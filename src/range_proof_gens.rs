@@ -0,0 +1,92 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use bulletproofs::{BulletproofGens, PedersenGens};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::dbc_content::RANGE_PROOF_BITS;
+
+/// The generators needed to create and verify range proofs.
+///
+/// Building the BulletproofGens table is expensive, so rather than rebuilding
+/// it for every proof either create one RangeProofGens and pass it explicitly,
+/// or use the lazily initialised RangeProofGens::shared() instance.
+#[derive(Clone)]
+pub struct RangeProofGens {
+    pub pc_gens: PedersenGens,
+    pub bullet_gens: BulletproofGens,
+}
+
+impl RangeProofGens {
+    /// Generators able to create and verify aggregated proofs over up to `party_capacity` values.
+    pub fn new(party_capacity: usize) -> Self {
+        Self {
+            pc_gens: PedersenGens::default(),
+            bullet_gens: BulletproofGens::new(RANGE_PROOF_BITS, party_capacity),
+        }
+    }
+
+    /// The maximum number of values an aggregated proof using these generators can cover.
+    pub fn party_capacity(&self) -> usize {
+        self.bullet_gens.party_capacity
+    }
+
+    /// Process wide generators with capacity for at least `party_capacity` parties.
+    ///
+    /// Generators are built on first use and cached for every subsequent call.
+    /// Capacity is rounded up to a power of two, matching how aggregated proofs are padded.
+    pub fn shared(party_capacity: usize) -> Arc<Self> {
+        static SHARED: OnceLock<Mutex<BTreeMap<usize, Arc<RangeProofGens>>>> = OnceLock::new();
+
+        let party_capacity = party_capacity.max(1).next_power_of_two();
+        let mut cache = SHARED
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // Generators for a larger capacity also serve any smaller capacity.
+        if let Some((_, gens)) = cache.range(party_capacity..).next() {
+            return gens.clone();
+        }
+
+        let gens = Arc::new(Self::new(party_capacity));
+        cache.insert(party_capacity, gens.clone());
+        gens
+    }
+}
+
+impl fmt::Debug for RangeProofGens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RangeProofGens")
+            .field("gens_capacity", &self.bullet_gens.gens_capacity)
+            .field("party_capacity", &self.bullet_gens.party_capacity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_gens_are_reused() {
+        for party_capacity in [1, 2, 3, 8] {
+            let gens = RangeProofGens::shared(party_capacity);
+            assert!(gens.party_capacity() >= party_capacity);
+            assert!(Arc::ptr_eq(&gens, &RangeProofGens::shared(party_capacity)));
+        }
+
+        // Other tests share the cache, so only check a large request is served by
+        // generators with at least the rounded up capacity.
+        let gens = RangeProofGens::shared(100);
+        assert!(gens.party_capacity() >= 128);
+        assert!(gens.party_capacity().is_power_of_two());
+    }
+}
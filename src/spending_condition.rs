@@ -0,0 +1,648 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Spending policies richer than a signature by a single owner key.
+//!
+//! A DBC's BlindedOwner commits to a SpendingCondition in place of the owner key, so
+//! the condition stays hidden until the DBC is spent.  To spend it the ReissueRequest
+//! carries a SpendingProof revealing the condition, with the signatures and hash
+//! preimages which satisfy it.
+//!
+//! A condition is revealed by whoever spends the DBC, so the mint limits its size,
+//! see MAX_SPENDING_CONDITION_DEPTH and MAX_SPENDING_CONDITIONS.
+
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+use tiny_keccak::{Hasher, Sha3};
+
+use crate::{BlindedOwner, DbcContentHash, Error, Hash, PublicKey, Result, Signature};
+
+const HASH_LOCK_DOMAIN: &[u8] = b"SN_DBC_HASH_LOCK";
+
+/// The deepest a SpendingCondition may nest, counting itself.
+pub const MAX_SPENDING_CONDITION_DEPTH: usize = 8;
+
+/// The most conditions a SpendingCondition may have, counting itself and every
+/// condition nested in it.
+pub const MAX_SPENDING_CONDITIONS: usize = 64;
+
+/// The largest hash lock preimage which meets its condition, in bytes.
+pub const MAX_PREIMAGE_SIZE: usize = 256;
+
+/// A condition which must be met to spend a DBC.
+///
+/// Deserialising refuses conditions which fail SpendingCondition::validate_size().
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "ConditionRepr")]
+pub enum SpendingCondition {
+    /// A signature by the key over the transaction, as for a DBC owned by the key.
+    Key(PublicKey),
+    /// The preimage of the hash, of at most MAX_PREIMAGE_SIZE bytes, see
+    /// SpendingCondition::hash_lock().
+    HashLock(Hash),
+    /// At least `threshold` of the conditions.
+    Threshold {
+        threshold: usize,
+        conditions: Vec<SpendingCondition>,
+    },
+}
+
+impl SpendingCondition {
+    /// Signatures by at least `threshold` of `keys`, which need not share a BLS key set.
+    /// A key given more than once counts once.
+    pub fn m_of_n(threshold: usize, keys: impl IntoIterator<Item = PublicKey>) -> Self {
+        Self::Threshold {
+            threshold,
+            conditions: distinct(keys.into_iter().map(Self::Key)),
+        }
+    }
+
+    /// Any one of `conditions`.
+    pub fn any_of(conditions: impl IntoIterator<Item = SpendingCondition>) -> Self {
+        Self::Threshold {
+            threshold: 1,
+            conditions: distinct(conditions),
+        }
+    }
+
+    /// Every one of `conditions`.
+    pub fn all_of(conditions: impl IntoIterator<Item = SpendingCondition>) -> Self {
+        let conditions = distinct(conditions);
+        Self::Threshold {
+            threshold: conditions.len(),
+            conditions,
+        }
+    }
+
+    /// Knowledge of `preimage`.  Anyone who learns the preimage meets the condition,
+    /// so it is usually combined with a key, eg. all_of([hash_lock(p), Key(k)]).
+    pub fn hash_lock(preimage: &[u8]) -> Self {
+        Self::HashLock(Self::preimage_hash(preimage))
+    }
+
+    /// The hash a hash lock on `preimage` is locked to.
+    pub fn preimage_hash(preimage: &[u8]) -> Hash {
+        let mut sha3 = Sha3::v256();
+        sha3.update(HASH_LOCK_DOMAIN);
+        sha3.update(preimage);
+        let mut hash = [0; 32];
+        sha3.finalize(&mut hash);
+        Hash(hash)
+    }
+
+    /// Check the condition nests no deeper than MAX_SPENDING_CONDITION_DEPTH, and has
+    /// no more than MAX_SPENDING_CONDITIONS conditions.
+    pub fn validate_size(&self) -> Result<()> {
+        let mut count = 0;
+        self.validate_size_at(1, &mut count)
+    }
+
+    fn validate_size_at(&self, depth: usize, count: &mut usize) -> Result<()> {
+        *count += 1;
+        if depth > MAX_SPENDING_CONDITION_DEPTH || *count > MAX_SPENDING_CONDITIONS {
+            return Err(Error::SpendingConditionTooLarge);
+        }
+        if let Self::Threshold { conditions, .. } = self {
+            for condition in conditions.iter() {
+                condition.validate_size_at(depth + 1, count)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn meets_hash_lock(preimage: &[u8], hash: &Hash) -> bool {
+        preimage.len() <= MAX_PREIMAGE_SIZE && Self::preimage_hash(preimage) == *hash
+    }
+
+    /// The BlindedOwner of a DBC spendable on this condition, by a SpendingProof.
+    ///
+    /// The condition of a single key blinds as the key itself does, so a DBC owned
+    /// by a key may also be spent by a SpendingProof of its Key condition.
+    pub fn blinded_owner(&self, parents: &BTreeSet<DbcContentHash>) -> BlindedOwner {
+        match self {
+            Self::Key(key) => BlindedOwner::new(key, parents),
+            condition => BlindedOwner::new_from_condition(&condition.to_bytes(), parents),
+        }
+    }
+
+    // The hashes of the hash locks in this condition.
+    fn hash_locks(&self, hashes: &mut BTreeSet<Hash>) {
+        match self {
+            Self::Key(_) => (),
+            Self::HashLock(hash) => {
+                hashes.insert(*hash);
+            }
+            Self::Threshold { conditions, .. } => {
+                for condition in conditions.iter() {
+                    condition.hash_locks(hashes);
+                }
+            }
+        }
+    }
+
+    // Unambiguous bytes of the condition, committed to by its BlindedOwner.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut v: Vec<u8> = Default::default();
+        match self {
+            Self::Key(key) => {
+                v.push(0);
+                v.extend(&key.to_bytes());
+            }
+            Self::HashLock(hash) => {
+                v.push(1);
+                v.extend(hash.as_ref());
+            }
+            Self::Threshold {
+                threshold,
+                conditions,
+            } => {
+                v.push(2);
+                v.extend(&(*threshold as u64).to_le_bytes());
+                v.extend(&(conditions.len() as u64).to_le_bytes());
+                for condition in conditions.iter() {
+                    v.extend(condition.to_bytes());
+                }
+            }
+        }
+        v
+    }
+
+    // Whether `signatures` over `msg_hash` and `preimages` meet this condition.
+    fn is_met(
+        &self,
+        msg_hash: &Hash,
+        signatures: &BTreeMap<PublicKey, Signature>,
+        preimages: &BTreeMap<Hash, Vec<u8>>,
+    ) -> bool {
+        match self {
+            Self::Key(key) => signatures
+                .get(key)
+                .map(|sig| key.verify(sig, msg_hash))
+                .unwrap_or(false),
+            Self::HashLock(hash) => preimages
+                .get(hash)
+                .map(|preimage| Self::meets_hash_lock(preimage, hash))
+                .unwrap_or(false),
+            Self::Threshold {
+                threshold,
+                conditions,
+            } => {
+                // A threshold of zero would let anyone spend, and a condition repeated
+                // counts once, else one signature could meet m_of_n(2, [k, k]).
+                *threshold > 0
+                    && HashSet::<&SpendingCondition>::from_iter(conditions)
+                        .into_iter()
+                        .filter(|condition| condition.is_met(msg_hash, signatures, preimages))
+                        .count()
+                        >= *threshold
+            }
+        }
+    }
+}
+
+/// Proof that a DBC's SpendingCondition is met by a transaction, for a ReissueRequest.
+///
+/// Deserialising refuses proofs which fail SpendingProof::validate().
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "ProofRepr")]
+pub struct SpendingProof {
+    pub condition: SpendingCondition,
+    /// Signatures over the transaction, ie. `ReissueTransaction::blinded().hash()`.
+    pub signatures: BTreeMap<PublicKey, Signature>,
+    /// Preimages of hash locks, by their hash.
+    pub preimages: BTreeMap<Hash, Vec<u8>>,
+}
+
+impl SpendingProof {
+    pub fn new(condition: SpendingCondition) -> Self {
+        Self {
+            condition,
+            signatures: Default::default(),
+            preimages: Default::default(),
+        }
+    }
+
+    pub fn add_signature(mut self, key: PublicKey, sig: Signature) -> Self {
+        self.signatures.insert(key, sig);
+        self
+    }
+
+    pub fn add_preimage(mut self, preimage: Vec<u8>) -> Self {
+        self.preimages
+            .insert(SpendingCondition::preimage_hash(&preimage), preimage);
+        self
+    }
+
+    /// Check the condition passes SpendingCondition::validate_size(), and that no
+    /// preimage is larger than MAX_PREIMAGE_SIZE.
+    pub fn validate(&self) -> Result<()> {
+        self.condition.validate_size()?;
+        if self
+            .preimages
+            .values()
+            .any(|preimage| preimage.len() > MAX_PREIMAGE_SIZE)
+        {
+            return Err(Error::PreimageTooLarge);
+        }
+        Ok(())
+    }
+
+    /// The preimages this reveals of the hash locks in the condition, by their hash.
+    /// Any other preimages the proof carries, or which do not meet their hash lock,
+    /// are left out.
+    pub fn revealed_preimages(&self) -> BTreeMap<Hash, Vec<u8>> {
+        let mut hashes = BTreeSet::new();
+        if self.condition.validate_size().is_ok() {
+            self.condition.hash_locks(&mut hashes);
+        }
+        hashes
+            .into_iter()
+            .filter_map(|hash| {
+                self.preimages
+                    .get(&hash)
+                    .filter(|preimage| SpendingCondition::meets_hash_lock(preimage, &hash))
+                    .map(|preimage| (hash, preimage.clone()))
+            })
+            .collect()
+    }
+
+    /// Whether the condition is met for the transaction whose hash is `msg_hash`.
+    /// A condition too large to pass SpendingCondition::validate_size() never is.
+    pub fn verify(&self, msg_hash: &Hash) -> bool {
+        self.condition.validate_size().is_ok()
+            && self
+                .condition
+                .is_met(msg_hash, &self.signatures, &self.preimages)
+    }
+}
+
+// `conditions` without repeats, in the order first given.
+fn distinct(conditions: impl IntoIterator<Item = SpendingCondition>) -> Vec<SpendingCondition> {
+    let mut seen = HashSet::new();
+    conditions
+        .into_iter()
+        .filter(|condition| seen.insert(condition.clone()))
+        .collect()
+}
+
+// The serialised form of a SpendingCondition, checked by validate_size() once read.
+#[derive(Deserialize)]
+#[serde(rename = "SpendingCondition")]
+enum ConditionRepr {
+    Key(PublicKey),
+    HashLock(Hash),
+    Threshold {
+        threshold: usize,
+        #[serde(deserialize_with = "nested_conditions")]
+        conditions: Vec<SpendingCondition>,
+    },
+}
+
+impl TryFrom<ConditionRepr> for SpendingCondition {
+    type Error = Error;
+
+    fn try_from(repr: ConditionRepr) -> Result<Self> {
+        let condition = match repr {
+            ConditionRepr::Key(key) => Self::Key(key),
+            ConditionRepr::HashLock(hash) => Self::HashLock(hash),
+            ConditionRepr::Threshold {
+                threshold,
+                conditions,
+            } => Self::Threshold {
+                threshold,
+                conditions,
+            },
+        };
+        condition.validate_size()?;
+        Ok(condition)
+    }
+}
+
+thread_local! {
+    // How deep the conditions being deserialised on this thread are nested.
+    static NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+// The conditions of a Threshold, refused before they are read when nested deeper than
+// MAX_SPENDING_CONDITION_DEPTH, so that deserialising can not overflow the stack.
+fn nested_conditions<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<SpendingCondition>, D::Error> {
+    let nesting = NESTING.with(Cell::get) + 1;
+    if nesting > MAX_SPENDING_CONDITION_DEPTH {
+        return Err(de::Error::custom(Error::SpendingConditionTooLarge));
+    }
+    NESTING.with(|n| n.set(nesting));
+    let conditions = Vec::deserialize(deserializer);
+    NESTING.with(|n| n.set(nesting - 1));
+    conditions
+}
+
+// The serialised form of a SpendingProof, checked by validate() once read.
+#[derive(Deserialize)]
+#[serde(rename = "SpendingProof")]
+struct ProofRepr {
+    condition: SpendingCondition,
+    signatures: BTreeMap<PublicKey, Signature>,
+    preimages: BTreeMap<Hash, Vec<u8>>,
+}
+
+impl TryFrom<ProofRepr> for SpendingProof {
+    type Error = Error;
+
+    fn try_from(repr: ProofRepr) -> Result<Self> {
+        let proof = Self {
+            condition: repr.condition,
+            signatures: repr.signatures,
+            preimages: repr.preimages,
+        };
+        proof.validate()?;
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::spend_to;
+    use crate::{
+        Error, MintCluster, Output, ReissueRequest, ReissueRequestBuilder, ReissueTransaction,
+        Result,
+    };
+    use blsttc::SecretKey;
+    use std::collections::HashMap;
+    use std::iter::FromIterator;
+
+    fn signed_by(
+        mut proof: SpendingProof,
+        transaction: &ReissueTransaction,
+        keys: &[&SecretKey],
+    ) -> SpendingProof {
+        let msg_hash = transaction.blinded().hash();
+        for key in keys {
+            proof = proof.add_signature(key.public_key(), key.sign(msg_hash));
+        }
+        proof
+    }
+
+    #[test]
+    fn test_m_of_n_across_unrelated_keys() -> Result<()> {
+        let keys = [
+            SecretKey::random(),
+            SecretKey::random(),
+            SecretKey::random(),
+        ];
+        let condition = SpendingCondition::m_of_n(2, keys.iter().map(SecretKey::public_key));
+        let mut cluster = MintCluster::random(1, 0);
+        let (dbc, secrets) = cluster.issue_genesis_to(
+            Output::new(100, SecretKey::random().public_key())
+                .with_spending_condition(condition.clone()),
+        )?;
+        let transaction = spend_to(&dbc, secrets, SecretKey::random().public_key())?;
+
+        // One signature is not enough, to the builder or the mint.
+        let one = signed_by(
+            SpendingProof::new(condition.clone()),
+            &transaction,
+            &[&keys[1]],
+        );
+        assert!(matches!(
+            ReissueRequestBuilder::new(transaction.clone())
+                .add_spending_proof(dbc.name(), one.clone())
+                .build(),
+            Err(Error::SpendingConditionNotMet)
+        ));
+        let unmet = ReissueRequest {
+            transaction: transaction.clone(),
+            input_ownership_proofs: Default::default(),
+            input_spending_proofs: HashMap::from_iter([(dbc.name(), one)]),
+        };
+        assert!(matches!(
+            cluster.reissue(unmet),
+            Err(Error::SpendingConditionNotMet)
+        ));
+
+        // Nor may a condition other than the DBC's be proven in its place.
+        let own_condition = SpendingCondition::m_of_n(1, [keys[1].public_key()]);
+        let own = signed_by(
+            SpendingProof::new(own_condition.clone()),
+            &transaction,
+            &[&keys[1]],
+        );
+        assert!(matches!(
+            ReissueRequestBuilder::new(transaction.clone())
+                .add_spending_proof(dbc.name(), own)
+                .build(),
+            Err(Error::FailedUnblinding)
+        ));
+
+        // The mint checks a condition is the DBC's before evaluating it.
+        let foreign = ReissueRequest {
+            transaction: transaction.clone(),
+            input_ownership_proofs: Default::default(),
+            input_spending_proofs: HashMap::from_iter([(
+                dbc.name(),
+                SpendingProof::new(own_condition),
+            )]),
+        };
+        assert!(matches!(
+            cluster.reissue(foreign),
+            Err(Error::FailedUnblinding)
+        ));
+
+        let two = signed_by(
+            SpendingProof::new(condition),
+            &transaction,
+            &[&keys[0], &keys[2]],
+        );
+        let reissue_req = ReissueRequestBuilder::new(transaction)
+            .add_spending_proof(dbc.name(), two)
+            .build()?;
+        let reissue_req: ReissueRequest =
+            bincode::deserialize(&bincode::serialize(&reissue_req).unwrap()).unwrap();
+        assert_eq!(cluster.reissue(reissue_req)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_or_hash_lock_with_key() -> Result<()> {
+        let alice = SecretKey::random();
+        let bob = SecretKey::random();
+        let preimage = b"a secret known to whoever may claim".to_vec();
+        let condition = SpendingCondition::any_of([
+            SpendingCondition::Key(alice.public_key()),
+            SpendingCondition::all_of([
+                SpendingCondition::hash_lock(&preimage),
+                SpendingCondition::Key(bob.public_key()),
+            ]),
+        ]);
+        let mut cluster = MintCluster::random(1, 0);
+        let (dbc, secrets) = cluster.issue_genesis_to(
+            Output::new(100, SecretKey::random().public_key())
+                .with_spending_condition(condition.clone()),
+        )?;
+        let transaction = spend_to(&dbc, secrets, SecretKey::random().public_key())?;
+
+        // Bob needs the preimage as well as his signature, while alice needs only hers.
+        let by_bob = signed_by(SpendingProof::new(condition.clone()), &transaction, &[&bob]);
+        assert!(!by_bob.verify(&transaction.blinded().hash()));
+        assert!(!by_bob
+            .clone()
+            .add_preimage(b"a guess".to_vec())
+            .verify(&transaction.blinded().hash()));
+        let by_alice = signed_by(SpendingProof::new(condition), &transaction, &[&alice]);
+        assert!(by_alice.verify(&transaction.blinded().hash()));
+
+        // The mint records the preimage, but not others the proof carries.
+        let junk = b"not a preimage of the condition".to_vec();
+        let junk_hash = SpendingCondition::preimage_hash(&junk);
+        let reissue_req = ReissueRequestBuilder::new(transaction)
+            .add_spending_proof(
+                dbc.name(),
+                by_bob.add_preimage(preimage.clone()).add_preimage(junk),
+            )
+            .build()?;
+        cluster.reissue(reissue_req)?;
+        let mint = cluster.node(0).unwrap();
+        assert_eq!(
+            mint.revealed_preimage(&SpendingCondition::preimage_hash(&preimage))?,
+            Some(preimage.as_slice())
+        );
+        assert_eq!(mint.revealed_preimage(&junk_hash)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_only_hash_locks_met_are_revealed() {
+        let preimage = b"the preimage".to_vec();
+        let oversized = vec![0; MAX_PREIMAGE_SIZE + 1];
+        let other_hash = SpendingCondition::preimage_hash(b"another preimage");
+        let mut proof = SpendingProof::new(SpendingCondition::any_of([
+            SpendingCondition::hash_lock(&preimage),
+            SpendingCondition::hash_lock(&oversized),
+            SpendingCondition::HashLock(other_hash),
+        ]))
+        .add_preimage(preimage.clone())
+        .add_preimage(oversized.clone());
+        proof.preimages.insert(other_hash, b"junk".to_vec());
+        proof
+            .preimages
+            .insert(Hash::from([0; 32]), b"not in the condition".to_vec());
+
+        assert_eq!(
+            proof.revealed_preimages(),
+            BTreeMap::from_iter([(SpendingCondition::preimage_hash(&preimage), preimage)])
+        );
+
+        // Nor does a preimage larger than MAX_PREIMAGE_SIZE meet its hash lock, and a
+        // proof carrying one is refused when deserialised.
+        assert!(matches!(proof.validate(), Err(Error::PreimageTooLarge)));
+        assert!(
+            bincode::deserialize::<SpendingProof>(&bincode::serialize(&proof).unwrap()).is_err()
+        );
+        let oversized_only = SpendingProof {
+            condition: SpendingCondition::hash_lock(&oversized),
+            ..proof
+        };
+        assert!(!oversized_only.verify(&Hash::from([0; 32])));
+    }
+
+    #[test]
+    fn test_repeated_condition_counts_once() {
+        let key = SecretKey::random();
+        let msg_hash = Hash::from([0; 32]);
+        assert_eq!(
+            SpendingCondition::m_of_n(2, [key.public_key(), key.public_key()]),
+            SpendingCondition::Threshold {
+                threshold: 2,
+                conditions: vec![SpendingCondition::Key(key.public_key())],
+            }
+        );
+
+        let repeated = SpendingCondition::Threshold {
+            threshold: 2,
+            conditions: vec![
+                SpendingCondition::Key(key.public_key()),
+                SpendingCondition::Key(key.public_key()),
+            ],
+        };
+        let proof =
+            SpendingProof::new(repeated).add_signature(key.public_key(), key.sign(msg_hash));
+        assert!(!proof.verify(&msg_hash));
+    }
+
+    #[test]
+    fn test_oversized_conditions_are_refused() {
+        let key = SecretKey::random();
+        let msg_hash = Hash::from([0; 32]);
+        let refused = |condition: &SpendingCondition| {
+            let proof = SpendingProof::new(condition.clone())
+                .add_signature(key.public_key(), key.sign(msg_hash));
+            matches!(
+                condition.validate_size(),
+                Err(Error::SpendingConditionTooLarge)
+            ) && !proof.verify(&msg_hash)
+                && bincode::deserialize::<SpendingCondition>(
+                    &bincode::serialize(condition).unwrap(),
+                )
+                .is_err()
+                && serde_json::from_str::<SpendingCondition>(
+                    &serde_json::to_string(condition).unwrap(),
+                )
+                .is_err()
+        };
+
+        let mut deepest = SpendingCondition::Key(key.public_key());
+        for _ in 1..MAX_SPENDING_CONDITION_DEPTH {
+            deepest = SpendingCondition::any_of([deepest]);
+        }
+        assert!(!refused(&deepest));
+        assert!(SpendingProof::new(deepest.clone())
+            .add_signature(key.public_key(), key.sign(msg_hash))
+            .verify(&msg_hash));
+        assert_eq!(
+            bincode::deserialize::<SpendingCondition>(&bincode::serialize(&deepest).unwrap())
+                .unwrap(),
+            deepest
+        );
+        assert!(refused(&SpendingCondition::any_of([deepest])));
+
+        let widest = SpendingCondition::any_of(
+            std::iter::once(SpendingCondition::Key(key.public_key())).chain(
+                (2..MAX_SPENDING_CONDITIONS)
+                    .map(|_| SpendingCondition::Key(SecretKey::random().public_key())),
+            ),
+        );
+        assert!(!refused(&widest));
+        assert!(refused(&SpendingCondition::any_of([
+            widest,
+            SpendingCondition::hash_lock(b"one too many")
+        ])));
+
+        // Nesting too deep for the stack is refused without reading it all.
+        let mut too_deep = Vec::new();
+        for _ in 0..1_000_000 {
+            too_deep.extend(2u32.to_le_bytes()); // Threshold
+            too_deep.extend(1u64.to_le_bytes()); // threshold
+            too_deep.extend(1u64.to_le_bytes()); // conditions.len()
+        }
+        assert!(bincode::deserialize::<SpendingCondition>(&too_deep).is_err());
+    }
+
+    #[test]
+    fn test_zero_threshold_is_never_met() {
+        let proof = SpendingProof::new(SpendingCondition::m_of_n(0, []));
+        assert!(!proof.verify(&Hash::from([0; 32])));
+    }
+}
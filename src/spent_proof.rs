@@ -0,0 +1,230 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    DbcContentHash, DbcTransaction, Error, Hash, KeyManager, NodeSignature, PublicKey,
+    PublicKeySet, Result, Signature,
+};
+use serde::{Deserialize, Serialize};
+use std::iter::FromIterator;
+use tiny_keccak::{Hasher, Sha3};
+
+const SPENT_PROOF_DOMAIN: &[u8] = b"SN_DBC_SPENT_PROOF";
+
+/// The message a mint signs to prove the DBC `dbc_name` was spent by `transaction`.
+///
+/// It names the DBC, so that a mint's signature proves the spend of the input it
+/// logged, and not of the other inputs of the transaction.
+pub(crate) fn spent_proof_hash(dbc_name: &DbcContentHash, transaction: &DbcTransaction) -> Hash {
+    let mut sha3 = Sha3::v256();
+    sha3.update(SPENT_PROOF_DOMAIN);
+    sha3.update(dbc_name);
+    sha3.update(&transaction.hash());
+    let mut hash = [0; 32];
+    sha3.finalize(&mut hash);
+    Hash(hash)
+}
+
+/// A single mint node's share of a SpentProof.
+///
+/// Handed out by Mint::spent_proof_share(), and by Mint::reissue() in
+/// Error::DbcAlreadySpent when asked to spend a DBC a second time.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SpentProofShare {
+    pub dbc_name: DbcContentHash,
+    pub transaction: DbcTransaction,
+    pub public_key_set: PublicKeySet,
+    pub node_sig: NodeSignature,
+}
+
+/// Proof, signed by the mint, that the DBC named `dbc_name` was spent by `transaction`.
+///
+/// A SpentProof needs nothing but the mint's keys to verify, so may be passed to
+/// third parties and checked offline.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SpentProof {
+    pub dbc_name: DbcContentHash,
+    pub transaction: DbcTransaction,
+    pub mint_key: PublicKey,
+    pub mint_sig: Signature,
+}
+
+impl SpentProof {
+    /// Combine at least threshold + 1 shares, from distinct mint nodes, into a SpentProof.
+    pub fn from_shares(shares: impl IntoIterator<Item = SpentProofShare>) -> Result<Self> {
+        let shares = Vec::from_iter(shares);
+        let first = shares.first().ok_or(Error::NoSpentProofShares)?;
+
+        if shares.iter().any(|share| {
+            share.dbc_name != first.dbc_name
+                || share.transaction != first.transaction
+                || share.public_key_set != first.public_key_set
+        }) {
+            return Err(Error::SpentProofShareMismatch);
+        }
+
+        let mint_sig = first
+            .public_key_set
+            .combine_signatures(shares.iter().map(|share| share.node_sig.threshold_crypto()))?;
+
+        let proof = Self {
+            dbc_name: first.dbc_name,
+            transaction: first.transaction.clone(),
+            mint_key: first.public_key_set.public_key(),
+            mint_sig,
+        };
+
+        // A bad share gives us a signature which does not verify.
+        if !proof.mint_key.verify(
+            &proof.mint_sig,
+            spent_proof_hash(&proof.dbc_name, &proof.transaction),
+        ) {
+            return Err(Error::FailedSignature);
+        }
+
+        Ok(proof)
+    }
+
+    /// Verify the DBC was spent by the transaction, and that its spend by the
+    /// transaction is signed by a mint key known to `verifier`.
+    pub fn verify<K: KeyManager>(&self, verifier: &K) -> Result<()> {
+        if !self.transaction.inputs.contains(&self.dbc_name) {
+            return Err(Error::SpentProofInputNotInTransaction);
+        }
+
+        let msg_hash = spent_proof_hash(&self.dbc_name, &self.transaction);
+        verifier
+            .verify(&msg_hash, &self.mint_key, &self.mint_sig)
+            .map_err(|e| Error::Signing(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MintCluster, Output, OwnerSigner, ReissueRequestBuilder, SimpleKeyManager, SimpleSigner,
+        TransactionBuilder,
+    };
+
+    #[test]
+    fn test_spent_proof_can_be_verified_offline() -> Result<()> {
+        let mut cluster = MintCluster::random(1, 0);
+        let genesis_key = cluster.public_key_set().public_key();
+        let genesis_dbc = cluster.issue_genesis_dbc(1000)?;
+        let gen_dbc_name = genesis_dbc.name();
+        let genesis_amount_secrets = cluster.decrypt_amount_secrets(&genesis_dbc.content)?;
+
+        // Nothing to prove before the genesis DBC is spent.
+        let genesis_node = cluster.node(0).unwrap();
+        assert_eq!(genesis_node.spent_proof_share(gen_dbc_name)?, None);
+
+        let (reissue_tx, _) = TransactionBuilder::default()
+            .add_input(genesis_dbc, genesis_amount_secrets)
+            .add_output(Output::new(
+                1000,
+                crate::bls_dkg_id().public_key_set.public_key(),
+            ))
+            .build()?;
+        let reissue_req = ReissueRequestBuilder::new(reissue_tx.clone())
+            .add_signer(
+                gen_dbc_name,
+                OwnerSigner::SecretKeySet(cluster.secret_key_set().clone()),
+            )
+            .build()?;
+        cluster.reissue(reissue_req.clone())?;
+
+        // Attempting to spend it again hands back a share of the proof of the first spend.
+        let genesis_node = cluster.node_mut(0).unwrap();
+        let share = match genesis_node.reissue(reissue_req) {
+            Err(Error::DbcAlreadySpent(share)) => *share,
+            result => panic!("Unexpected reissue result {:?}", result),
+        };
+        assert_eq!(
+            Some(&share),
+            genesis_node.spent_proof_share(gen_dbc_name)?.as_ref()
+        );
+        assert_eq!(share.transaction, reissue_tx.blinded());
+
+        let proof = SpentProof::from_shares([share.clone()])?;
+
+        // A third party, knowing only the mint's keys, verifies the serialised proof.
+        let proof: SpentProof = bincode::deserialize(&bincode::serialize(&proof).unwrap()).unwrap();
+        let third_party =
+            SimpleKeyManager::new(SimpleSigner::from(crate::bls_dkg_id()), genesis_key);
+        proof.verify(&third_party)?;
+
+        // But not if they do not know the mint's key...
+        let stranger_owner = crate::bls_dkg_id();
+        let stranger = SimpleKeyManager::new(
+            SimpleSigner::from(stranger_owner.clone()),
+            stranger_owner.public_key_set.public_key(),
+        );
+        assert!(matches!(proof.verify(&stranger), Err(Error::Signing(_))));
+
+        // ...or the proof is about some other DBC...
+        let mut other_dbc = proof.clone();
+        other_dbc.dbc_name = rand::random::<[u8; 32]>().into();
+        assert!(matches!(
+            other_dbc.verify(&third_party),
+            Err(Error::SpentProofInputNotInTransaction)
+        ));
+
+        // ...or a different transaction.
+        let mut other_tx = proof;
+        other_tx.transaction.outputs.clear();
+        assert!(matches!(
+            other_tx.verify(&third_party),
+            Err(Error::Signing(_))
+        ));
+
+        let mut mismatched = share.clone();
+        mismatched.transaction.outputs.clear();
+        assert!(matches!(
+            SpentProof::from_shares([share, mismatched]),
+            Err(Error::SpentProofShareMismatch)
+        ));
+        assert!(matches!(
+            SpentProof::from_shares([]),
+            Err(Error::NoSpentProofShares)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spent_proof_is_bound_to_its_dbc() -> Result<()> {
+        let mut cluster = MintCluster::random(1, 0);
+        let owner = blsttc::SecretKey::random();
+        let inputs = crate::tests::split_genesis(&mut cluster, &[1, 1], owner.public_key())?;
+        let (spent, other) = (inputs[0].0.name(), inputs[1].0.name());
+        let reissue_req = crate::tests::merge_request(inputs, &owner, owner.public_key())?;
+        cluster.reissue(reissue_req)?;
+
+        let mint = cluster.node(0).unwrap();
+        let share = mint.spent_proof_share(spent)?.unwrap();
+        let proof = SpentProof::from_shares([share.clone()])?;
+        proof.verify(mint.key_manager())?;
+
+        // The signature does not prove the spend of another input of the transaction.
+        let mut other_input = proof;
+        other_input.dbc_name = other;
+        assert!(matches!(
+            other_input.verify(mint.key_manager()),
+            Err(Error::Signing(_))
+        ));
+        let mut other_share = share;
+        other_share.dbc_name = other;
+        assert!(matches!(
+            SpentProof::from_shares([other_share]),
+            Err(Error::FailedSignature)
+        ));
+
+        Ok(())
+    }
+}
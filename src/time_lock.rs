@@ -0,0 +1,192 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! DBCs which may not be reissued before an epoch, or which revert to a refund key
+//! after one, eg. for escrow.
+//!
+//! A mint has no network clock to rely on, so it learns the current epoch, be it a
+//! time, a block height or a count of rounds its nodes agree on, from an EpochSource.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::{BlindedOwner, DbcContentHash, PublicKey};
+
+pub type Epoch = u64;
+
+/// The mint's notion of the current epoch, against which time locks are checked.
+///
+/// Every node of a mint must agree on the epoch closely enough that they reissue the
+/// same time-locked DBCs, or too few will sign for the reissue to succeed.
+pub trait EpochSource: fmt::Debug + Send + Sync {
+    fn current_epoch(&self) -> Epoch;
+}
+
+/// An EpochSource whose epoch is set explicitly, eg. by tests, or by a mint which
+/// agrees on epochs by other means.  Clones share the same epoch.
+#[derive(Debug, Clone, Default)]
+pub struct SimpleEpochSource(Arc<AtomicU64>);
+
+impl SimpleEpochSource {
+    pub fn new(epoch: Epoch) -> Self {
+        Self(Arc::new(AtomicU64::new(epoch)))
+    }
+
+    pub fn set_epoch(&self, epoch: Epoch) {
+        self.0.store(epoch, Ordering::SeqCst);
+    }
+}
+
+impl EpochSource for SimpleEpochSource {
+    fn current_epoch(&self) -> Epoch {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Unblinded time lock for an Output of a TransactionBuilder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeLock {
+    /// The DBC may not be reissued before this epoch.
+    pub not_before: Option<Epoch>,
+    /// From this epoch on the DBC may be reissued by the refund key, and no longer by
+    /// its owner.  Only the owner can decrypt the amount, so the refund key's holder
+    /// must know the AmountSecrets already, eg. from the PaymentReceipt.
+    pub refund: Option<(Epoch, PublicKey)>,
+}
+
+impl TimeLock {
+    /// Blind the refund key as the owner key is blinded, see BlindedOwner.
+    pub fn blind(&self, parents: &BTreeSet<DbcContentHash>) -> BlindedTimeLock {
+        BlindedTimeLock {
+            not_before: self.not_before,
+            refund: self
+                .refund
+                .map(|(after, refund_key)| (after, BlindedOwner::new(&refund_key, parents))),
+        }
+    }
+}
+
+/// The time lock of a DbcContent, checked by the mint against its EpochSource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlindedTimeLock {
+    pub not_before: Option<Epoch>,
+    pub refund: Option<(Epoch, BlindedOwner)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::spend_to;
+    use crate::{
+        AmountSecrets, Dbc, Error, MintCluster, Output, OwnerSigner, ReissueRequest,
+        ReissueRequestBuilder, Result,
+    };
+    use blsttc::SecretKeySet;
+
+    // A request spending `dbc` to a new owner, signed by `signer`.
+    fn spend(dbc: &Dbc, secrets: AmountSecrets, signer: &SecretKeySet) -> Result<ReissueRequest> {
+        let new_owner = SecretKeySet::random(0, &mut rand::thread_rng());
+        let transaction = spend_to(dbc, secrets, new_owner.public_keys().public_key())?;
+        ReissueRequestBuilder::new(transaction)
+            .add_signer(dbc.name(), OwnerSigner::SecretKeySet(signer.clone()))
+            .build()
+    }
+
+    #[test]
+    fn test_not_reissued_before_epoch() -> Result<()> {
+        let owner = SecretKeySet::random(0, &mut rand::thread_rng());
+        let mut cluster = MintCluster::random(1, 0);
+        let (dbc, secrets) = cluster.issue_genesis_to(
+            Output::new(100, owner.public_keys().public_key()).with_time_lock(TimeLock {
+                not_before: Some(10),
+                refund: None,
+            }),
+        )?;
+        let reissue_req = spend(&dbc, secrets, &owner)?;
+
+        // A mint which cannot tell the epoch does not reissue it at all.
+        assert!(matches!(
+            cluster.reissue(reissue_req.clone()),
+            Err(Error::NoEpochSource)
+        ));
+
+        let epoch = SimpleEpochSource::new(9);
+        cluster.set_epoch_source(epoch.clone());
+        assert!(matches!(
+            cluster.reissue(reissue_req.clone()),
+            Err(Error::DbcTimeLocked {
+                not_before: 10,
+                epoch: 9
+            })
+        ));
+
+        epoch.set_epoch(10);
+        cluster.reissue(reissue_req)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escrow_reverts_to_refund_key() -> Result<()> {
+        let payee = SecretKeySet::random(0, &mut rand::thread_rng());
+        let payer = SecretKeySet::random(0, &mut rand::thread_rng());
+        let mut cluster = MintCluster::random(1, 0);
+        let (dbc, secrets) = cluster.issue_genesis_to(
+            Output::new(100, payee.public_keys().public_key()).with_time_lock(TimeLock {
+                not_before: None,
+                refund: Some((20, payer.public_keys().public_key())),
+            }),
+        )?;
+        let epoch = SimpleEpochSource::new(19);
+        cluster.set_epoch_source(epoch.clone());
+
+        // Before the deadline only the payee may reissue it.
+        assert!(matches!(
+            cluster.reissue(spend(&dbc, secrets, &payer)?),
+            Err(Error::FailedUnblinding)
+        ));
+        let by_payee = spend(&dbc, secrets, &payee)?;
+
+        // After it only the payer, who knows the amount from their receipt.
+        epoch.set_epoch(20);
+        assert!(matches!(
+            cluster.reissue(by_payee),
+            Err(Error::FailedUnblinding)
+        ));
+        cluster.reissue(spend(&dbc, secrets, &payer)?)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_covers_time_lock() -> Result<()> {
+        let owner = SecretKeySet::random(0, &mut rand::thread_rng());
+        let mut cluster = MintCluster::random(1, 0);
+        let (dbc, _) = cluster.issue_genesis_to(
+            Output::new(100, owner.public_keys().public_key()).with_time_lock(TimeLock {
+                not_before: Some(10),
+                refund: None,
+            }),
+        )?;
+
+        let mut unlocked = dbc.content.clone();
+        unlocked.time_lock = None;
+        let mut relocked = dbc.content.clone();
+        relocked.set_time_lock(&TimeLock {
+            not_before: Some(9),
+            refund: None,
+        });
+        assert_ne!(unlocked.hash(), dbc.content.hash());
+        assert_ne!(relocked.hash(), dbc.content.hash());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Transactions built together by several parties, none of whom learns the
+//! others' amounts or blinding factors.
+//!
+//! 1. The parties share the DBCs they will spend, giving the transaction's inputs.
+//! 2. Each party builds its outputs with TransactionBuilder::build_contribution().
+//! 3. The contributions are combined into the ReissueTransaction with
+//!    TransactionContribution::combine().
+//! 4. The owners of the inputs sign it, eg. with a PartiallySignedReissue.
+
+use bulletproofs::PedersenGens;
+use curve25519_dalek_ng::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek_ng::scalar::Scalar;
+use merlin::Transcript;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    Amount, AssetSurjectionProof, Dbc, DbcContent, DbcContentHash, Error, ReissueTransaction,
+    Result,
+};
+
+const EXCESS_TRANSCRIPT_LABEL: &[u8] = b"sn_dbc excess commitment";
+
+/// A commitment to an amount of zero, blinded by the difference between the blinding
+/// factors of one party's inputs and outputs.
+///
+/// Summed with the outputs' commitments it balances the inputs' commitments.  Since
+/// it carries a proof of knowledge of its blinding factor, it cannot also commit to
+/// an amount and so cannot be used to create or destroy money.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ExcessCommitment {
+    pub commitment: CompressedRistretto,
+    // Schnorr proof of knowledge of the blinding factor.
+    pub nonce_commitment: CompressedRistretto,
+    pub response: Scalar,
+}
+
+impl ExcessCommitment {
+    pub fn new(blinding_factor: Scalar) -> Self {
+        let blinding_base = PedersenGens::default().B_blinding;
+        let nonce = DbcContent::random_blinding_factor();
+        let commitment = (blinding_factor * blinding_base).compress();
+        let nonce_commitment = (nonce * blinding_base).compress();
+        let challenge = Self::challenge(&commitment, &nonce_commitment);
+
+        Self {
+            commitment,
+            nonce_commitment,
+            response: nonce + challenge * blinding_factor,
+        }
+    }
+
+    /// Check the commitment is to an amount of zero, returning the commitment.
+    pub fn verify(&self) -> Result<RistrettoPoint> {
+        let commitment = self
+            .commitment
+            .decompress()
+            .ok_or(Error::AmountCommitmentInvalid)?;
+        let nonce_commitment = self
+            .nonce_commitment
+            .decompress()
+            .ok_or(Error::InvalidExcessCommitment)?;
+        let challenge = Self::challenge(&self.commitment, &self.nonce_commitment);
+
+        if self.response * PedersenGens::default().B_blinding
+            != nonce_commitment + challenge * commitment
+        {
+            return Err(Error::InvalidExcessCommitment);
+        }
+        Ok(commitment)
+    }
+
+    fn challenge(
+        commitment: &CompressedRistretto,
+        nonce_commitment: &CompressedRistretto,
+    ) -> Scalar {
+        let mut transcript = Transcript::new(EXCESS_TRANSCRIPT_LABEL);
+        transcript.append_message(b"commitment", commitment.as_bytes());
+        transcript.append_message(b"nonce_commitment", nonce_commitment.as_bytes());
+        let mut challenge = [0u8; 64];
+        transcript.challenge_bytes(b"challenge", &mut challenge);
+        Scalar::from_bytes_mod_order_wide(&challenge)
+    }
+}
+
+/// One party's inputs and outputs of a transaction built together with other parties.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TransactionContribution {
+    pub inputs: HashSet<Dbc>,
+    pub outputs: HashSet<DbcContent>,
+    pub excess: ExcessCommitment,
+    /// This party's part of the transaction's burned fee.
+    pub fee: Amount,
+    pub asset_surjection_proofs: HashMap<DbcContentHash, AssetSurjectionProof>,
+}
+
+impl TransactionContribution {
+    /// Combine every party's contribution into the ReissueTransaction, checking it
+    /// balances as the mint will.
+    pub fn combine(contributions: impl IntoIterator<Item = Self>) -> Result<ReissueTransaction> {
+        let mut transaction = ReissueTransaction {
+            inputs: Default::default(),
+            outputs: Default::default(),
+            aggregated_range_proof: None,
+            excess_commitments: Default::default(),
+            fee: 0,
+            asset_surjection_proofs: Default::default(),
+        };
+        for contribution in contributions {
+            transaction.inputs.extend(contribution.inputs);
+            transaction.outputs.extend(contribution.outputs);
+            transaction.excess_commitments.push(contribution.excess);
+            transaction.fee = transaction
+                .fee
+                .checked_add(contribution.fee)
+                .ok_or(Error::AmountOverflow)?;
+            transaction
+                .asset_surjection_proofs
+                .extend(contribution.asset_surjection_proofs);
+        }
+
+        transaction.validate_balance()?;
+        transaction.validate_outputs()?;
+        Ok(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AmountSecrets, DbcHelper, MintCluster, Output, OwnerSigner, PartiallySignedReissue,
+        PaymentReceipt, ReissueRequestBuilder, TransactionBuilder,
+    };
+    use blsttc::SecretKeySet;
+    use std::collections::BTreeSet;
+    use std::iter::FromIterator;
+
+    type OwnedDbc = (Dbc, AmountSecrets, SecretKeySet);
+
+    // A cluster, and a DBC of each amount owned by a new owner.
+    fn owned_dbcs(amounts: &[u64]) -> Result<(MintCluster, Vec<OwnedDbc>)> {
+        let mut cluster = MintCluster::random(1, 0);
+        let genesis = cluster.issue_genesis_dbc(amounts.iter().sum())?;
+        let genesis_name = genesis.name();
+        let genesis_secrets = cluster.decrypt_amount_secrets(&genesis.content)?;
+
+        let owners = Vec::from_iter(
+            amounts
+                .iter()
+                .map(|_| SecretKeySet::random(0, &mut rand::thread_rng())),
+        );
+        let (split_tx, receipt) = TransactionBuilder::default()
+            .add_input(genesis, genesis_secrets)
+            .add_outputs(
+                amounts
+                    .iter()
+                    .zip(owners.iter())
+                    .map(|(amount, owner)| Output::new(*amount, owner.public_keys().public_key())),
+            )
+            .build()?;
+        let split_req = ReissueRequestBuilder::new(split_tx)
+            .add_signer(
+                genesis_name,
+                OwnerSigner::SecretKeySet(cluster.secret_key_set().clone()),
+            )
+            .build()?;
+        let dbcs = cluster.reissue(split_req)?;
+
+        let owned = Vec::from_iter(owners.into_iter().map(|owner| {
+            let dbc = dbcs
+                .iter()
+                .find(|dbc| receipt.output_owners[&dbc.name()] == owner.public_keys().public_key())
+                .unwrap()
+                .clone();
+            let secrets = receipt.output_amount_secrets[&dbc.name()];
+            (dbc, secrets, owner)
+        }));
+        Ok((cluster, owned))
+    }
+
+    #[test]
+    fn test_parties_mix_without_sharing_secrets() -> Result<()> {
+        let (mut cluster, owned) = owned_dbcs(&[300, 700])?;
+        let (alice_dbc, alice_secrets, alice) = owned[0].clone();
+        let (bob_dbc, bob_secrets, bob) = owned[1].clone();
+        let parents = BTreeSet::from_iter([alice_dbc.name(), bob_dbc.name()]);
+
+        // Each pays their new keys, in amounts the other does not learn.
+        let alice_new = crate::bls_dkg_id();
+        let bob_new = crate::bls_dkg_id();
+        let (alice_part, alice_output_secrets) = TransactionBuilder::default()
+            .add_input(alice_dbc.clone(), alice_secrets)
+            .add_output(Output::new(100, alice_new.public_key_set.public_key()))
+            .add_output(Output::new(200, alice_new.public_key_set.public_key()))
+            .build_contribution(parents.clone())?;
+        let (bob_part, bob_output_secrets) = TransactionBuilder::default()
+            .add_input(bob_dbc.clone(), bob_secrets)
+            .add_output(Output::new(700, bob_new.public_key_set.public_key()))
+            .build_contribution(parents)?;
+
+        let transaction = TransactionContribution::combine([alice_part, bob_part])?;
+
+        let msg_hash = transaction.blinded().hash();
+        let mut by_alice = PartiallySignedReissue::new(transaction.clone());
+        by_alice.add_signature(
+            alice_dbc.name(),
+            alice.public_keys().public_key(),
+            alice.secret_key().sign(msg_hash),
+        )?;
+        let mut by_bob = PartiallySignedReissue::new(transaction.clone());
+        by_bob.add_signature(
+            bob_dbc.name(),
+            bob.public_keys().public_key(),
+            bob.secret_key().sign(msg_hash),
+        )?;
+        by_alice.merge(by_bob)?;
+        let dbcs = cluster.reissue(by_alice.finalize()?)?;
+        assert_eq!(dbcs.len(), 3);
+
+        // Each party's AmountSecrets are those its recipients decrypt.
+        for dbc in dbcs.iter() {
+            match alice_output_secrets.get(&dbc.name()) {
+                Some(secrets) => assert_eq!(
+                    *secrets,
+                    DbcHelper::decrypt_amount_secrets(&alice_new, &dbc.content)?
+                ),
+                None => assert_eq!(
+                    bob_output_secrets[&dbc.name()],
+                    DbcHelper::decrypt_amount_secrets(&bob_new, &dbc.content)?
+                ),
+            }
+        }
+
+        // And prove the payment, once the transaction is known.
+        let receipt = PaymentReceipt {
+            transaction: transaction.blinded(),
+            output_owners: HashMap::from_iter(
+                alice_output_secrets
+                    .keys()
+                    .map(|name| (*name, alice_new.public_key_set.public_key())),
+            ),
+            output_amount_secrets: alice_output_secrets,
+            output_spending_conditions: Default::default(),
+            aggregated_range_proof: None,
+        };
+        assert_eq!(
+            receipt.amount_paid_to(&alice_new.public_key_set.public_key()),
+            300
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_excess_must_commit_to_zero() -> Result<()> {
+        let (_, owned) = owned_dbcs(&[300, 700])?;
+        let (alice_dbc, alice_secrets, _) = owned[0].clone();
+        let (bob_dbc, bob_secrets, _) = owned[1].clone();
+        let parents = BTreeSet::from_iter([alice_dbc.name(), bob_dbc.name()]);
+        let owner = crate::bls_dkg_id().public_key_set.public_key();
+
+        // A party's own amounts must balance.
+        assert!(matches!(
+            TransactionBuilder::default()
+                .add_input(alice_dbc.clone(), alice_secrets)
+                .add_output(Output::new(400, owner))
+                .build_contribution(parents.clone()),
+            Err(Error::DbcReissueRequestDoesNotBalance)
+        ));
+
+        let (alice_part, _) = TransactionBuilder::default()
+            .add_input(alice_dbc, alice_secrets)
+            .add_output(Output::new(300, owner))
+            .build_contribution(parents.clone())?;
+        let (bob_part, _) = TransactionBuilder::default()
+            .add_input(bob_dbc, bob_secrets)
+            .add_output(Output::new(700, owner))
+            .build_contribution(parents)?;
+
+        // An excess hiding an amount, with a proof for another commitment.
+        let mut hiding_amount = alice_part.clone();
+        let real_excess = hiding_amount.excess.commitment.decompress().unwrap();
+        hiding_amount.excess.commitment = (real_excess + PedersenGens::default().B).compress();
+        assert!(matches!(
+            TransactionContribution::combine([hiding_amount, bob_part.clone()]),
+            Err(Error::InvalidExcessCommitment)
+        ));
+
+        // Nor do the parties' outputs balance without their excess.
+        let mut transaction = TransactionContribution::combine([alice_part, bob_part])?;
+        transaction.excess_commitments.pop();
+        assert!(matches!(
+            transaction.validate_balance(),
+            Err(Error::DbcReissueRequestDoesNotBalance)
+        ));
+
+        Ok(())
+    }
+}